@@ -0,0 +1,283 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::math::vector::*;
+use crate::net::net::Server;
+use crate::window::{Event, EventIter};
+
+// Published for every Event::Size that goes through the bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowResized {
+    pub size: Vec2i,
+}
+
+// Published for Event::Close
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowClosed;
+
+// Returned by subscribe, for unsubscribing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Subscription(u64);
+
+// Where a reader is up to in one event type's stream
+#[derive(Debug)]
+pub struct Reader<T> {
+    next: u64,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Reader<T> {
+    // Starts with whatever the bus still holds
+    pub fn new() -> Self {
+        Self {
+            next: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for Reader<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for Reader<T> {
+    fn clone(&self) -> Self {
+        Self {
+            next: self.next,
+            marker: PhantomData,
+        }
+    }
+}
+
+type Handler<T> = Box<dyn FnMut(&T)>;
+
+// Events live for this frame and the next, so a reader that reads once a
+// frame sees each one exactly once wherever it runs in the frame
+struct Queue<T> {
+    previous: Vec<T>,
+    current: Vec<T>,
+    // Sequence number of previous[0]
+    start: u64,
+    handlers: Vec<(Subscription, Handler<T>)>,
+}
+
+trait AnyQueue {
+    fn update(&mut self);
+    fn unsubscribe(&mut self, subscription: Subscription) -> bool;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyQueue for Queue<T> {
+    fn update(&mut self) {
+        self.start += self.previous.len() as u64;
+        self.previous = std::mem::take(&mut self.current);
+    }
+
+    fn unsubscribe(&mut self, subscription: Subscription) -> bool {
+        let len = self.handlers.len();
+        self.handlers.retain(|(s, _)| *s != subscription);
+        self.handlers.len() != len
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// Typed publish and subscribe. Any 'static type can be an event. Call
+// `update` once a frame to retire the oldest events.
+#[derive(Default)]
+pub struct EventBus {
+    queues: HashMap<TypeId, Box<dyn AnyQueue>>,
+    next_subscription: u64,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn queue<T: 'static>(&self) -> Option<&Queue<T>> {
+        self.queues
+            .get(&TypeId::of::<T>())
+            .and_then(|q| q.as_any().downcast_ref())
+    }
+
+    fn queue_mut<T: 'static>(&mut self) -> &mut Queue<T> {
+        self.queues
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| {
+                Box::new(Queue::<T> {
+                    previous: Vec::new(),
+                    current: Vec::new(),
+                    start: 0,
+                    handlers: Vec::new(),
+                })
+            })
+            .as_any_mut()
+            .downcast_mut()
+            .expect("queue stored under the wrong type")
+    }
+
+    // Handlers run straight away, before publish returns
+    pub fn publish<T: 'static>(&mut self, event: T) {
+        let queue = self.queue_mut::<T>();
+        for (_, handler) in &mut queue.handlers {
+            handler(&event);
+        }
+        queue.current.push(event);
+    }
+
+    pub fn subscribe<T: 'static, F: FnMut(&T) + 'static>(&mut self, handler: F) -> Subscription {
+        let subscription = Subscription(self.next_subscription);
+        self.next_subscription += 1;
+        self.queue_mut::<T>()
+            .handlers
+            .push((subscription, Box::new(handler)));
+        subscription
+    }
+
+    pub fn unsubscribe(&mut self, subscription: Subscription) -> bool {
+        self.queues
+            .values_mut()
+            .any(|q| q.unsubscribe(subscription))
+    }
+
+    // A reader that skips events already on the bus
+    pub fn reader<T: 'static>(&self) -> Reader<T> {
+        Reader {
+            next: self
+                .queue::<T>()
+                .map_or(0, |q| q.start + (q.previous.len() + q.current.len()) as u64),
+            marker: PhantomData,
+        }
+    }
+
+    // Events the reader hasn't seen yet. A reader left unread for more than
+    // a frame silently misses the retired events.
+    pub fn read<'a, T: 'static>(&'a self, reader: &mut Reader<T>) -> impl Iterator<Item = &'a T> {
+        let (start, previous, current): (u64, &[T], &[T]) = match self.queue::<T>() {
+            Some(q) => (q.start, &q.previous, &q.current),
+            None => (0, &[], &[]),
+        };
+        let skip = reader.next.saturating_sub(start) as usize;
+        reader.next = reader
+            .next
+            .max(start + (previous.len() + current.len()) as u64);
+        previous.iter().chain(current).skip(skip)
+    }
+
+    // Everything of the type still on the bus, without a reader
+    pub fn events<T: 'static>(&self) -> impl Iterator<Item = &T> {
+        let (previous, current): (&[T], &[T]) = match self.queue::<T>() {
+            Some(q) => (&q.previous, &q.current),
+            None => (&[], &[]),
+        };
+        previous.iter().chain(current)
+    }
+
+    pub fn update(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.update();
+        }
+    }
+
+    // Publishes each event as (f64, Event), plus WindowResized and
+    // WindowClosed, and hands them back for the rest of the frame
+    pub fn forward_window_events<'a, I: IntoIterator<Item = (f64, Event)>>(
+        &mut self,
+        events: I,
+    ) -> EventIter<'a> {
+        let events: Vec<_> = events.into_iter().collect();
+        for (time, event) in &events {
+            match event {
+                Event::Size(width, height) => self.publish(WindowResized {
+                    size: [*width, *height].into(),
+                }),
+                Event::Close => self.publish(WindowClosed),
+                _ => {}
+            }
+            self.publish((*time, event.clone()));
+        }
+        EventIter::from_events(events)
+    }
+
+    // Publishes a KeyChange for every value set or removed on the server
+    pub fn forward_server_changes(&mut self, server: &Server) {
+        for change in server.take_changes() {
+            self.publish(change);
+        }
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq)]
+struct PlayerDied(u32);
+
+#[test]
+fn test_bus_readers() {
+    let mut bus = EventBus::new();
+    let mut early = Reader::<PlayerDied>::new();
+    assert!(bus.read(&mut early).count() == 0);
+
+    bus.publish(PlayerDied(1));
+    bus.publish(PlayerDied(2));
+    let mut late = bus.reader::<PlayerDied>();
+
+    // Readers keep their own place
+    let seen: Vec<_> = bus.read(&mut early).collect();
+    assert!(seen == [&PlayerDied(1), &PlayerDied(2)]);
+    assert!(bus.read(&mut early).count() == 0);
+    assert!(bus.read(&mut late).count() == 0);
+
+    // Events survive one update, so a reader that runs before the publisher
+    // in the next frame still sees them
+    bus.update();
+    bus.publish(PlayerDied(3));
+    assert!(bus.events::<PlayerDied>().count() == 3);
+    let seen: Vec<_> = bus.read(&mut late).collect();
+    assert!(seen == [&PlayerDied(3)]);
+
+    bus.update();
+    bus.update();
+    assert!(bus.events::<PlayerDied>().count() == 0);
+    assert!(bus.read(&mut early).count() == 0);
+    assert!(bus.events::<WindowClosed>().count() == 0);
+}
+
+#[test]
+fn test_bus_handlers() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    let mut bus = EventBus::new();
+    let total = Rc::new(Cell::new(0));
+    let counter = total.clone();
+    let subscription = bus.subscribe(move |e: &PlayerDied| counter.set(counter.get() + e.0));
+
+    bus.publish(PlayerDied(2));
+    bus.publish(PlayerDied(3));
+    assert!(total.get() == 5);
+
+    assert!(bus.unsubscribe(subscription));
+    assert!(!bus.unsubscribe(subscription));
+    bus.publish(PlayerDied(10));
+    assert!(total.get() == 5);
+
+    let events: Vec<_> = bus
+        .forward_window_events([(0.5, Event::Size(640, 480)), (0.5, Event::Close)])
+        .collect();
+    assert!(events.len() == 2);
+    let resized: Vec<_> = bus.events::<WindowResized>().collect();
+    assert!(resized.len() == 1 && resized[0].size == [640, 480]);
+    assert!(bus.events::<WindowClosed>().count() == 1);
+    assert!(bus.events::<(f64, Event)>().count() == 2);
+}
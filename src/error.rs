@@ -0,0 +1,93 @@
+use crate::window::WindowError;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Window(WindowError),
+    // A malformed line in a text file format
+    Parse {
+        line: usize,
+        message: String,
+    },
+    // A file format that isn't supported, usually picked by extension
+    Format(String),
+    // A malformed network message
+    Protocol(String),
+    // A backend refused something, like a shader that didn't compile
+    Render(String),
+    // A shader stage that didn't compile, or a program that didn't link
+    Shader {
+        stage: &'static str,
+        messages: Vec<crate::render::gl::LogMessage>,
+    },
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Window(e) => write!(f, "window error: {}", e),
+            Error::Parse { line, message } => {
+                write!(f, "parse error on line {}: {}", line, message)
+            }
+            Error::Format(format) => write!(f, "unsupported format '{}'", format),
+            Error::Protocol(message) => write!(f, "protocol error: {}", message),
+            Error::Render(message) => write!(f, "render error: {}", message),
+            Error::Shader { stage, messages } => {
+                write!(f, "{} shader error", stage)?;
+                for message in messages {
+                    write!(f, "\n  {}", message)?;
+                }
+                Ok(())
+            }
+            Error::TypeMismatch { expected, found } => {
+                write!(
+                    f,
+                    "type mismatch, expected {} but found {}",
+                    expected, found
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Window(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<WindowError> for Error {
+    fn from(e: WindowError) -> Self {
+        Error::Window(e)
+    }
+}
+
+#[test]
+fn test_error_display() {
+    let e = Error::from(std::io::Error::other("boom"));
+    assert!(e.to_string() == "io error: boom");
+    assert!(std::error::Error::source(&e).is_some());
+
+    let e = Error::TypeMismatch {
+        expected: "i",
+        found: "s",
+    };
+    assert!(e.to_string() == "type mismatch, expected i but found s");
+}
@@ -0,0 +1,445 @@
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Write};
+use std::path::Path;
+
+use glfw::{GamepadAxis, GamepadButton, Key, MouseButton};
+
+use crate::error::{Error, Result};
+use crate::input::gamepad::{self, Gamepad};
+use crate::input::{Input, KEYS, MOUSE_BUTTONS};
+
+pub const DEFAULT_DEADZONE: f32 = 0.2;
+
+// Values at or past this count as held, for analog bindings
+pub const PRESS_THRESHOLD: f32 = 0.5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Binding {
+    Key(Key),
+    Mouse(MouseButton),
+    Button(GamepadButton),
+    // Scale flips or weakens the axis, e.g. -1 so pushing a stick up is
+    // positive
+    Axis {
+        axis: GamepadAxis,
+        deadzone: f32,
+        scale: f32,
+    },
+    // -1 while negative is held, 1 for positive, 0 for both or neither
+    Keys {
+        negative: Key,
+        positive: Key,
+    },
+    // Every part held at once, the value comes from the last part. Actions
+    // bound to the parts alone still fire.
+    Chord(Vec<Binding>),
+}
+
+impl Binding {
+    pub fn axis(axis: GamepadAxis) -> Self {
+        Binding::Axis {
+            axis,
+            deadzone: DEFAULT_DEADZONE,
+            scale: 1.0,
+        }
+    }
+
+    pub fn value(&self, input: &Input, pad: Option<&Gamepad>) -> f32 {
+        let held = |down: bool| if down { 1.0 } else { 0.0 };
+        match self {
+            Binding::Key(key) => held(input.is_key_down(*key)),
+            Binding::Mouse(button) => held(input.is_button_down(*button)),
+            Binding::Button(button) => held(pad.is_some_and(|p| p.button(*button))),
+            Binding::Axis {
+                axis,
+                deadzone,
+                scale,
+            } => pad.map_or(0.0, |p| apply_deadzone(p.axis(*axis), *deadzone) * scale),
+            Binding::Keys { negative, positive } => {
+                held(input.is_key_down(*positive)) - held(input.is_key_down(*negative))
+            }
+            Binding::Chord(parts) => {
+                let mut value = 0.0;
+                for part in parts {
+                    value = part.value(input, pad);
+                    if value.abs() < PRESS_THRESHOLD {
+                        return 0.0;
+                    }
+                }
+                value
+            }
+        }
+    }
+
+    // Whatever the player is pressing right now, for "press a key to bind"
+    // prompts. Pad buttons are checked while held, so wait for a release
+    // before capturing again.
+    pub fn capture(input: &Input, pad: Option<&Gamepad>) -> Option<Binding> {
+        if let Some(key) = KEYS.iter().find(|k| input.just_pressed(**k)) {
+            return Some(Binding::Key(*key));
+        }
+        if let Some(button) = MOUSE_BUTTONS
+            .iter()
+            .find(|b| input.button_just_pressed(**b))
+        {
+            return Some(Binding::Mouse(*button));
+        }
+        let pad = pad?;
+        if let Some(button) = gamepad::BUTTONS.iter().find(|b| pad.button(**b)) {
+            return Some(Binding::Button(*button));
+        }
+        gamepad::AXES
+            .iter()
+            .find(|a| pad.axis(**a).abs() >= PRESS_THRESHOLD)
+            .map(|a| Binding::Axis {
+                axis: *a,
+                deadzone: DEFAULT_DEADZONE,
+                scale: pad.axis(*a).signum(),
+            })
+    }
+
+    // The inverse of Display, e.g. "key:Space", "keys:A:D",
+    // "axis:AxisLeftY:0.2:-1" or "key:LeftControl+key:S". Errors are
+    // reported on line 1, `ActionMap::from_config` moves them to theirs.
+    pub fn parse(s: &str) -> Result<Binding> {
+        let s = s.trim();
+        if s.contains('+') {
+            let parts = s
+                .split('+')
+                .map(Binding::parse)
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(Binding::Chord(parts));
+        }
+
+        let invalid = || binding_error(format!("invalid binding '{}'", s));
+        let parts: Vec<&str> = s.split(':').map(str::trim).collect();
+        match parts.as_slice() {
+            ["key", key] => Ok(Binding::Key(by_name(&KEYS, key)?)),
+            ["mouse", button] => Ok(Binding::Mouse(by_name(&MOUSE_BUTTONS, button)?)),
+            ["button", button] => Ok(Binding::Button(by_name(&gamepad::BUTTONS, button)?)),
+            ["keys", negative, positive] => Ok(Binding::Keys {
+                negative: by_name(&KEYS, negative)?,
+                positive: by_name(&KEYS, positive)?,
+            }),
+            ["axis", axis, rest @ ..] if rest.len() <= 2 => {
+                let number = |i: usize, default: f32| match rest.get(i) {
+                    Some(v) => v.parse::<f32>().map_err(|_| invalid()),
+                    None => Ok(default),
+                };
+                Ok(Binding::Axis {
+                    axis: by_name(&gamepad::AXES, axis)?,
+                    deadzone: number(0, DEFAULT_DEADZONE)?,
+                    scale: number(1, 1.0)?,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "key:{:?}", key),
+            Binding::Mouse(button) => write!(f, "mouse:{:?}", button),
+            Binding::Button(button) => write!(f, "button:{:?}", button),
+            Binding::Axis {
+                axis,
+                deadzone,
+                scale,
+            } => {
+                write!(f, "axis:{:?}:{}", axis, deadzone)?;
+                if *scale != 1.0 {
+                    write!(f, ":{}", scale)?;
+                }
+                Ok(())
+            }
+            Binding::Keys { negative, positive } => write!(f, "keys:{:?}:{:?}", negative, positive),
+            Binding::Chord(parts) => {
+                for (i, part) in parts.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "+")?;
+                    }
+                    write!(f, "{}", part)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Rescales so the output still covers the full range past the deadzone
+fn apply_deadzone(value: f32, deadzone: f32) -> f32 {
+    let magnitude = value.abs();
+    if magnitude <= deadzone || deadzone >= 1.0 {
+        0.0
+    } else {
+        value.signum() * (magnitude - deadzone) / (1.0 - deadzone)
+    }
+}
+
+// Names are the glfw variant names, so bindings files don't depend on the
+// keyboard layout
+fn by_name<T: Debug + Copy>(all: &[T], name: &str) -> Result<T> {
+    all.iter()
+        .find(|v| format!("{:?}", v) == name)
+        .copied()
+        .ok_or_else(|| binding_error(format!("unknown name '{}'", name)))
+}
+
+fn binding_error(message: String) -> Error {
+    Error::Parse { line: 1, message }
+}
+
+#[derive(Debug, Clone, Default)]
+struct ActionState {
+    bindings: Vec<Binding>,
+    value: f32,
+    down: bool,
+    was_down: bool,
+}
+
+// Named actions ("jump", "move_x") read through any number of bindings.
+// Call `update` once a frame after `Input::update`.
+#[derive(Debug, Clone, Default)]
+pub struct ActionMap {
+    actions: BTreeMap<String, ActionState>,
+}
+
+impl ActionMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind(&mut self, action: &str, binding: Binding) {
+        let state = self.actions.entry(action.to_string()).or_default();
+        if !state.bindings.contains(&binding) {
+            state.bindings.push(binding);
+        }
+    }
+
+    pub fn unbind(&mut self, action: &str, binding: &Binding) -> bool {
+        match self.actions.get_mut(action) {
+            Some(state) => {
+                let len = state.bindings.len();
+                state.bindings.retain(|b| b != binding);
+                state.bindings.len() != len
+            }
+            None => false,
+        }
+    }
+
+    // Replaces the binding at index, or adds it if index is past the end
+    pub fn rebind(&mut self, action: &str, index: usize, binding: Binding) {
+        let state = self.actions.entry(action.to_string()).or_default();
+        match state.bindings.get_mut(index) {
+            Some(b) => *b = binding,
+            None => state.bindings.push(binding),
+        }
+    }
+
+    pub fn clear(&mut self, action: &str) {
+        if let Some(state) = self.actions.get_mut(action) {
+            state.bindings.clear();
+        }
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.actions
+            .get(action)
+            .map_or(&[], |state| state.bindings.as_slice())
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        self.actions.keys().map(|k| k.as_str())
+    }
+
+    pub fn update(&mut self, input: &Input, pad: Option<&Gamepad>) {
+        for state in self.actions.values_mut() {
+            // The strongest binding wins, so a keyboard and stick don't add up
+            let mut value: f32 = 0.0;
+            for binding in &state.bindings {
+                let v = binding.value(input, pad);
+                if v.abs() > value.abs() {
+                    value = v;
+                }
+            }
+            state.value = value;
+            state.was_down = state.down;
+            state.down = value.abs() >= PRESS_THRESHOLD;
+        }
+    }
+
+    // From -1 to 1 for axes, 0 or 1 for buttons
+    pub fn value(&self, action: &str) -> f32 {
+        self.actions.get(action).map_or(0.0, |s| s.value)
+    }
+
+    pub fn is_down(&self, action: &str) -> bool {
+        self.actions.get(action).is_some_and(|s| s.down)
+    }
+
+    pub fn just_pressed(&self, action: &str) -> bool {
+        self.actions
+            .get(action)
+            .is_some_and(|s| s.down && !s.was_down)
+    }
+
+    pub fn just_released(&self, action: &str) -> bool {
+        self.actions
+            .get(action)
+            .is_some_and(|s| !s.down && s.was_down)
+    }
+
+    // One action per line, "jump = key:Space, button:ButtonA". Lines
+    // starting with # are comments.
+    pub fn from_config(text: &str) -> Result<Self> {
+        let mut map = Self::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parse_error = |message: String| Error::Parse {
+                line: n + 1,
+                message,
+            };
+            let (name, bindings) = line
+                .split_once('=')
+                .ok_or_else(|| parse_error("expected 'action = bindings'".to_string()))?;
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(parse_error("missing action name".to_string()));
+            }
+            map.actions.entry(name.to_string()).or_default();
+            for binding in bindings.split(',').filter(|b| !b.trim().is_empty()) {
+                let binding = Binding::parse(binding).map_err(|e| match e {
+                    Error::Parse { message, .. } => parse_error(message),
+                    e => e,
+                })?;
+                map.bind(name, binding);
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn to_config(&self) -> String {
+        let mut out = String::new();
+        for (name, state) in &self.actions {
+            let bindings: Vec<String> = state.bindings.iter().map(|b| b.to_string()).collect();
+            writeln!(out, "{} = {}", name, bindings.join(", ")).expect("Write failed");
+        }
+        out
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_config(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.to_config())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn press(input: &mut Input, keys: &[Key]) {
+    use crate::window::Event;
+    use glfw::{Action, Modifiers};
+    input.update(
+        keys.iter()
+            .map(|k| (0.0, Event::Key(*k, 0, Action::Press, Modifiers::empty()))),
+    );
+}
+
+#[test]
+fn test_action_map() {
+    let mut map = ActionMap::new();
+    map.bind("jump", Binding::Key(Key::Space));
+    map.bind("jump", Binding::Button(GamepadButton::ButtonA));
+    map.bind(
+        "move_x",
+        Binding::Keys {
+            negative: Key::A,
+            positive: Key::D,
+        },
+    );
+    map.bind("move_x", Binding::axis(GamepadAxis::AxisLeftX));
+    map.bind(
+        "save",
+        Binding::Chord(vec![Binding::Key(Key::LeftControl), Binding::Key(Key::S)]),
+    );
+
+    let mut input = Input::new();
+    let mut pad = Gamepad::new();
+    press(&mut input, &[Key::D, Key::S]);
+    map.update(&input, Some(&pad));
+    assert!(map.value("move_x") == 1.0);
+    assert!(!map.is_down("save") && !map.is_down("jump"));
+
+    // Inside the deadzone the keyboard still wins, past it the stick does
+    pad.set_axis(GamepadAxis::AxisLeftX, -0.1);
+    pad.set_button(GamepadButton::ButtonA, true);
+    press(&mut input, &[Key::LeftControl]);
+    map.update(&input, Some(&pad));
+    assert!(map.value("move_x") == 1.0);
+    assert!(map.just_pressed("jump") && map.just_pressed("save"));
+
+    // Ties go to the first binding, so let go of the key
+    pad.set_axis(GamepadAxis::AxisLeftX, -1.0);
+    map.update(&Input::new(), Some(&pad));
+    assert!(map.value("move_x") == -1.0);
+    assert!(map.is_down("jump") && !map.just_pressed("jump"));
+
+    map.update(&input, None);
+    assert!(map.just_released("jump"));
+    assert!(map.value("unbound") == 0.0 && !map.is_down("unbound"));
+
+    // Rebinding at runtime
+    map.rebind("jump", 0, Binding::Key(Key::W));
+    assert!(map.bindings("jump")[0] == Binding::Key(Key::W));
+    assert!(map.unbind("jump", &Binding::Button(GamepadButton::ButtonA)));
+    assert!(map.bindings("jump").len() == 1);
+
+    assert!((apply_deadzone(0.6, 0.2) - 0.5).abs() < 1e-6);
+    assert!(apply_deadzone(-0.2, 0.2) == 0.0);
+}
+
+#[test]
+fn test_action_config() {
+    let text = "# controls\n\
+                jump = key:Space, button:ButtonA\n\
+                look_y = axis:AxisRightY:0.15:-1\n\
+                move_x = keys:A:D\n\
+                save = key:LeftControl+key:S\n\
+                unbound =\n";
+    let map = ActionMap::from_config(text).unwrap();
+    assert!(map.actions().count() == 5);
+    assert!(
+        map.bindings("look_y")
+            == [Binding::Axis {
+                axis: GamepadAxis::AxisRightY,
+                deadzone: 0.15,
+                scale: -1.0
+            }]
+    );
+
+    let again = ActionMap::from_config(&map.to_config()).unwrap();
+    for name in map.actions() {
+        assert!(map.bindings(name) == again.bindings(name), "{}", name);
+    }
+
+    match ActionMap::from_config("jump = key:Space\nfire = key:Nope") {
+        Err(Error::Parse { line, .. }) => assert!(line == 2),
+        _ => panic!("expected a parse error"),
+    }
+    assert!(ActionMap::from_config("jump key:Space").is_err());
+    assert!(matches!(
+        Binding::parse("key:Space+mouse:Nope"),
+        Err(Error::Parse { line: 1, .. })
+    ));
+
+    let mut input = Input::new();
+    press(&mut input, &[Key::F5]);
+    assert!(Binding::capture(&input, None) == Some(Binding::Key(Key::F5)));
+}
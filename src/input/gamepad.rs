@@ -0,0 +1,609 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use glfw::{Action, GamepadAxis, GamepadButton};
+
+use crate::error::{Error, Result};
+
+// GLFW hands out this many joystick slots
+pub const MAX_JOYSTICKS: usize = 16;
+
+pub const BUTTONS: [GamepadButton; 15] = [
+    GamepadButton::ButtonA,
+    GamepadButton::ButtonB,
+    GamepadButton::ButtonX,
+    GamepadButton::ButtonY,
+    GamepadButton::ButtonLeftBumper,
+    GamepadButton::ButtonRightBumper,
+    GamepadButton::ButtonBack,
+    GamepadButton::ButtonStart,
+    GamepadButton::ButtonGuide,
+    GamepadButton::ButtonLeftThumb,
+    GamepadButton::ButtonRightThumb,
+    GamepadButton::ButtonDpadUp,
+    GamepadButton::ButtonDpadRight,
+    GamepadButton::ButtonDpadDown,
+    GamepadButton::ButtonDpadLeft,
+];
+
+pub const AXES: [GamepadAxis; 6] = [
+    GamepadAxis::AxisLeftX,
+    GamepadAxis::AxisLeftY,
+    GamepadAxis::AxisRightX,
+    GamepadAxis::AxisRightY,
+    GamepadAxis::AxisLeftTrigger,
+    GamepadAxis::AxisRightTrigger,
+];
+
+// Standard layout gamepad, laid out like an Xbox controller. Sticks go from
+// -1 to 1 with +y down, triggers from 0 (released) to 1.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Gamepad {
+    buttons: [bool; 15],
+    axes: [f32; 6],
+}
+
+impl Gamepad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn button(&self, button: GamepadButton) -> bool {
+        self.buttons[button as usize]
+    }
+
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes[axis as usize]
+    }
+
+    pub fn set_button(&mut self, button: GamepadButton, down: bool) {
+        self.buttons[button as usize] = down;
+    }
+
+    pub fn set_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.axes[axis as usize] = value.clamp(-1.0, 1.0);
+    }
+}
+
+impl From<glfw::GamepadState> for Gamepad {
+    fn from(state: glfw::GamepadState) -> Self {
+        let mut pad = Gamepad::new();
+        for button in BUTTONS {
+            pad.set_button(button, state.get_button_state(button) == Action::Press);
+        }
+        for axis in AXES {
+            pad.set_axis(axis, state.get_axis(axis));
+        }
+        // GLFW rests triggers at -1
+        for axis in [GamepadAxis::AxisLeftTrigger, GamepadAxis::AxisRightTrigger] {
+            pad.set_axis(axis, (state.get_axis(axis) + 1.0) * 0.5);
+        }
+        pad
+    }
+}
+
+// A joystick as the driver reports it, before any mapping. Hats are bitmasks
+// of 1 up, 2 right, 4 down and 8 left.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RawJoystick {
+    pub guid: String,
+    pub name: String,
+    pub axes: Vec<f32>,
+    pub buttons: Vec<bool>,
+    pub hats: Vec<u8>,
+    // The layout GLFW's own mapping database came up with, if it knows the
+    // device
+    pub mapped: Option<Gamepad>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Half {
+    Full,
+    Positive,
+    Negative,
+}
+
+// Where a standard button or axis reads from on the raw joystick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Button(usize),
+    Axis {
+        index: usize,
+        half: Half,
+        invert: bool,
+    },
+    Hat {
+        index: usize,
+        mask: u8,
+    },
+}
+
+impl Source {
+    fn parse(s: &str) -> Option<Source> {
+        let (half, s) = split_half(s);
+        let (s, invert) = match s.strip_suffix('~') {
+            Some(s) => (s, true),
+            None => (s, false),
+        };
+        if let Some(index) = s.strip_prefix('b') {
+            Some(Source::Button(index.parse().ok()?))
+        } else if let Some(index) = s.strip_prefix('a') {
+            Some(Source::Axis {
+                index: index.parse().ok()?,
+                half,
+                invert,
+            })
+        } else if let Some(hat) = s.strip_prefix('h') {
+            let (index, mask) = hat.split_once('.')?;
+            Some(Source::Hat {
+                index: index.parse().ok()?,
+                mask: mask.parse().ok()?,
+            })
+        } else {
+            None
+        }
+    }
+
+    // From 0 to 1 for buttons, hats and half axes, -1 to 1 for full axes
+    fn read(&self, raw: &RawJoystick) -> f32 {
+        match *self {
+            Source::Button(i) => raw.buttons.get(i).map_or(0.0, |b| *b as u8 as f32),
+            Source::Hat { index, mask } => raw
+                .hats
+                .get(index)
+                .map_or(0.0, |h| (h & mask != 0) as u8 as f32),
+            Source::Axis {
+                index,
+                half,
+                invert,
+            } => {
+                let v = raw.axes.get(index).copied().unwrap_or(0.0);
+                let v = if invert { -v } else { v };
+                match half {
+                    Half::Full => v,
+                    Half::Positive => v.max(0.0),
+                    Half::Negative => (-v).max(0.0),
+                }
+            }
+        }
+    }
+
+    fn is_full_axis(&self) -> bool {
+        matches!(
+            self,
+            Source::Axis {
+                half: Half::Full,
+                ..
+            }
+        )
+    }
+}
+
+fn split_half(s: &str) -> (Half, &str) {
+    if let Some(s) = s.strip_prefix('+') {
+        (Half::Positive, s)
+    } else if let Some(s) = s.strip_prefix('-') {
+        (Half::Negative, s)
+    } else {
+        (Half::Full, s)
+    }
+}
+
+// One line of an SDL gamecontrollerdb.txt, which maps a device's buttons,
+// axes and hats to the standard layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapping {
+    pub guid: String,
+    pub name: String,
+    pub platform: Option<String>,
+    pub buttons: Vec<(GamepadButton, Source)>,
+    // The half says which direction of the output the source drives
+    pub axes: Vec<(GamepadAxis, Half, Source)>,
+}
+
+impl Mapping {
+    // Fields the standard layout doesn't have (misc1, paddles, touchpad) are
+    // ignored
+    pub fn parse(line: &str) -> std::result::Result<Mapping, String> {
+        let mut fields = line.trim().split(',');
+        let guid = fields.next().unwrap_or("").trim();
+        if guid.is_empty() {
+            return Err("missing GUID".to_string());
+        }
+        let name = fields.next().ok_or("missing name")?.trim();
+
+        let mut mapping = Mapping {
+            guid: guid.to_string(),
+            name: name.to_string(),
+            platform: None,
+            buttons: Vec::new(),
+            axes: Vec::new(),
+        };
+        for field in fields.map(str::trim).filter(|f| !f.is_empty()) {
+            let (target, value) = field
+                .split_once(':')
+                .ok_or_else(|| format!("expected 'name:value', found '{}'", field))?;
+            if target == "platform" {
+                mapping.platform = Some(value.to_string());
+                continue;
+            }
+            let (half, target) = split_half(target);
+            let source =
+                || Source::parse(value).ok_or_else(|| format!("invalid source '{}'", value));
+            if let Some(button) = sdl_button(target) {
+                mapping.buttons.push((button, source()?));
+            } else if let Some(axis) = sdl_axis(target) {
+                mapping.axes.push((axis, half, source()?));
+            }
+        }
+        Ok(mapping)
+    }
+
+    pub fn apply(&self, raw: &RawJoystick) -> Gamepad {
+        let mut pad = Gamepad::new();
+        for (button, source) in &self.buttons {
+            if source.read(raw).abs() >= 0.5 {
+                pad.set_button(*button, true);
+            }
+        }
+        for (axis, half, source) in &self.axes {
+            let mut v = source.read(raw);
+            let trigger = matches!(
+                axis,
+                GamepadAxis::AxisLeftTrigger | GamepadAxis::AxisRightTrigger
+            );
+            if trigger && source.is_full_axis() {
+                v = (v + 1.0) * 0.5;
+            }
+            v = match half {
+                Half::Full => v,
+                Half::Positive => v.abs(),
+                Half::Negative => -v.abs(),
+            };
+            // Sources sharing an output, like a d-pad driving a stick, add up
+            pad.set_axis(*axis, pad.axis(*axis) + v);
+        }
+        pad
+    }
+}
+
+fn sdl_button(name: &str) -> Option<GamepadButton> {
+    Some(match name {
+        "a" => GamepadButton::ButtonA,
+        "b" => GamepadButton::ButtonB,
+        "x" => GamepadButton::ButtonX,
+        "y" => GamepadButton::ButtonY,
+        "leftshoulder" => GamepadButton::ButtonLeftBumper,
+        "rightshoulder" => GamepadButton::ButtonRightBumper,
+        "back" => GamepadButton::ButtonBack,
+        "start" => GamepadButton::ButtonStart,
+        "guide" => GamepadButton::ButtonGuide,
+        "leftstick" => GamepadButton::ButtonLeftThumb,
+        "rightstick" => GamepadButton::ButtonRightThumb,
+        "dpup" => GamepadButton::ButtonDpadUp,
+        "dpright" => GamepadButton::ButtonDpadRight,
+        "dpdown" => GamepadButton::ButtonDpadDown,
+        "dpleft" => GamepadButton::ButtonDpadLeft,
+        _ => return None,
+    })
+}
+
+fn sdl_axis(name: &str) -> Option<GamepadAxis> {
+    Some(match name {
+        "leftx" => GamepadAxis::AxisLeftX,
+        "lefty" => GamepadAxis::AxisLeftY,
+        "rightx" => GamepadAxis::AxisRightX,
+        "righty" => GamepadAxis::AxisRightY,
+        "lefttrigger" => GamepadAxis::AxisLeftTrigger,
+        "righttrigger" => GamepadAxis::AxisRightTrigger,
+        _ => return None,
+    })
+}
+
+// The platform names SDL uses in the platform field
+pub fn current_platform() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "Windows"
+    } else if cfg!(target_os = "macos") {
+        "Mac OS X"
+    } else if cfg!(target_os = "android") {
+        "Android"
+    } else if cfg!(target_os = "ios") {
+        "iOS"
+    } else {
+        "Linux"
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MappingDb {
+    mappings: HashMap<String, Vec<Mapping>>,
+}
+
+impl MappingDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut db = Self::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mapping = Mapping::parse(line).map_err(|message| Error::Parse {
+                line: n + 1,
+                message,
+            })?;
+            db.add(mapping);
+        }
+        Ok(db)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    // A later mapping for the same device and platform replaces the earlier
+    // one, so user overrides can be added after the bundled database
+    pub fn add(&mut self, mapping: Mapping) {
+        let entries = self.mappings.entry(mapping.guid.clone()).or_default();
+        entries.retain(|m| m.platform != mapping.platform);
+        entries.push(mapping);
+    }
+
+    // Prefers the entry for the platform we're running on
+    pub fn get(&self, guid: &str) -> Option<&Mapping> {
+        let entries = self.mappings.get(guid)?;
+        let platform = current_platform();
+        entries
+            .iter()
+            .find(|m| m.platform.as_deref() == Some(platform))
+            .or_else(|| entries.iter().find(|m| m.platform.is_none()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.mappings.values().map(|m| m.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamepadEvent {
+    Connected(usize),
+    Disconnected(usize),
+}
+
+#[derive(Debug, Clone)]
+struct Slot {
+    name: String,
+    guid: String,
+    state: Gamepad,
+    previous: Gamepad,
+}
+
+// Tracks every joystick slot, reporting connects and disconnects and keeping
+// the standard layout state of each. Feed it `Window::joysticks` once a
+// frame.
+#[derive(Debug, Clone)]
+pub struct Gamepads {
+    db: MappingDb,
+    slots: Vec<Option<Slot>>,
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        Self {
+            db: MappingDb::new(),
+            slots: vec![None; MAX_JOYSTICKS],
+        }
+    }
+
+    pub fn set_mappings(mut self, db: MappingDb) -> Self {
+        self.db = db;
+        self
+    }
+
+    pub fn mappings_mut(&mut self) -> &mut MappingDb {
+        &mut self.db
+    }
+
+    pub fn update(&mut self, joysticks: &[Option<RawJoystick>]) -> Vec<GamepadEvent> {
+        let mut events = Vec::new();
+        for (i, slot) in self.slots.iter_mut().enumerate() {
+            match (slot.as_mut(), joysticks.get(i).and_then(|j| j.as_ref())) {
+                (Some(s), Some(raw)) if s.guid == raw.guid => {
+                    s.previous = s.state;
+                    s.state = map_joystick(&self.db, raw);
+                }
+                (Some(_), None) => {
+                    *slot = None;
+                    events.push(GamepadEvent::Disconnected(i));
+                }
+                (current, Some(raw)) => {
+                    // A different device took over the slot between updates
+                    if current.is_some() {
+                        events.push(GamepadEvent::Disconnected(i));
+                    }
+                    let state = map_joystick(&self.db, raw);
+                    *slot = Some(Slot {
+                        name: raw.name.clone(),
+                        guid: raw.guid.clone(),
+                        state,
+                        previous: Gamepad::new(),
+                    });
+                    events.push(GamepadEvent::Connected(i));
+                }
+                (None, None) => {}
+            }
+        }
+        events
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Gamepad> {
+        self.slots.get(index)?.as_ref().map(|s| &s.state)
+    }
+
+    // The lowest connected slot, for single player games
+    pub fn first(&self) -> Option<&Gamepad> {
+        self.slots.iter().flatten().next().map(|s| &s.state)
+    }
+
+    pub fn connected(&self) -> impl Iterator<Item = usize> + '_ {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.is_some())
+            .map(|(i, _)| i)
+    }
+
+    pub fn name(&self, index: usize) -> Option<&str> {
+        self.slots.get(index)?.as_ref().map(|s| s.name.as_str())
+    }
+
+    pub fn just_pressed(&self, index: usize, button: GamepadButton) -> bool {
+        self.slot(index)
+            .is_some_and(|s| s.state.button(button) && !s.previous.button(button))
+    }
+
+    pub fn just_released(&self, index: usize, button: GamepadButton) -> bool {
+        self.slot(index)
+            .is_some_and(|s| !s.state.button(button) && s.previous.button(button))
+    }
+
+    fn slot(&self, index: usize) -> Option<&Slot> {
+        self.slots.get(index)?.as_ref()
+    }
+}
+
+// Our database wins over GLFW's built in one so user mappings apply. Devices
+// neither knows keep a neutral state.
+fn map_joystick(db: &MappingDb, raw: &RawJoystick) -> Gamepad {
+    match db.get(&raw.guid) {
+        Some(mapping) => mapping.apply(raw),
+        None => raw.mapped.unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+const TEST_DB: &str = "# Test mappings\n\
+    03000000de280000ff11000001000000,Steam Virtual Gamepad,a:b0,b:b1,x:b2,y:b3,back:b6,start:b7,leftstick:b8,rightstick:b9,leftshoulder:b4,rightshoulder:b5,dpup:h0.1,dpdown:h0.4,dpleft:h0.8,dpright:h0.2,leftx:a0,lefty:a1,rightx:a3,righty:a4,lefttrigger:a2,righttrigger:a5,platform:Linux,\n\
+    03000000de280000ff11000001000000,Steam Virtual Gamepad,a:b1,b:b0,platform:Windows,\n\
+    0300000000f000000300000000010000,RetroUSB,a:b1,b:b5,-leftx:b9,+leftx:b10,-lefty:a1~,lefttrigger:+a2,misc1:b11,\n";
+
+#[test]
+fn test_mapping_parse() {
+    let db = MappingDb::parse(TEST_DB).unwrap();
+    assert!(db.len() == 3);
+
+    let retro = db.get("0300000000f000000300000000010000").unwrap();
+    assert!(retro.name == "RetroUSB" && retro.platform.is_none());
+    assert!(
+        retro.buttons
+            == [
+                (GamepadButton::ButtonA, Source::Button(1)),
+                (GamepadButton::ButtonB, Source::Button(5)),
+            ]
+    );
+    assert!(
+        retro.axes[2]
+            == (
+                GamepadAxis::AxisLeftY,
+                Half::Negative,
+                Source::Axis {
+                    index: 1,
+                    half: Half::Full,
+                    invert: true
+                }
+            )
+    );
+
+    let steam = db.get("03000000de280000ff11000001000000").unwrap();
+    assert!(steam.platform.as_deref() == Some(current_platform()) || steam.platform.is_none());
+
+    match MappingDb::parse("abc,Pad,a:b0\nabc,Pad,a:q0\n") {
+        Err(Error::Parse { line, .. }) => assert!(line == 2),
+        _ => panic!("expected a parse error"),
+    }
+    assert!(Mapping::parse(",Pad,a:b0").is_err());
+}
+
+#[test]
+fn test_mapping_apply() {
+    let db = MappingDb::parse(TEST_DB).unwrap();
+    let retro = db.get("0300000000f000000300000000010000").unwrap();
+    let raw = RawJoystick {
+        guid: retro.guid.clone(),
+        axes: vec![0.0, 0.5, 0.4],
+        buttons: vec![
+            false, true, false, false, false, false, false, false, false, true,
+        ],
+        ..Default::default()
+    };
+    let pad = retro.apply(&raw);
+    assert!(pad.button(GamepadButton::ButtonA) && !pad.button(GamepadButton::ButtonB));
+    // d-pad style buttons drive the stick
+    assert!(pad.axis(GamepadAxis::AxisLeftX) == -1.0);
+    // Inverted, so 0.5 down reads as 0.5 up
+    assert!(pad.axis(GamepadAxis::AxisLeftY) == -0.5);
+    assert!((pad.axis(GamepadAxis::AxisLeftTrigger) - 0.4).abs() < 1e-6);
+
+    // Full range trigger axes rest at -1
+    let steam = Mapping::parse("id,Pad,lefttrigger:a0,dpup:h0.1,").unwrap();
+    let raw = RawJoystick {
+        axes: vec![-1.0],
+        hats: vec![1 | 2],
+        ..Default::default()
+    };
+    let pad = steam.apply(&raw);
+    assert!(pad.axis(GamepadAxis::AxisLeftTrigger) == 0.0);
+    assert!(pad.button(GamepadButton::ButtonDpadUp));
+}
+
+#[test]
+fn test_gamepads_connect() {
+    let mut pads = Gamepads::new().set_mappings(MappingDb::parse("id,Pad,a:b0,start:b1,").unwrap());
+    let mut slots = vec![None; MAX_JOYSTICKS];
+    assert!(pads.update(&slots).is_empty());
+
+    slots[2] = Some(RawJoystick {
+        guid: "id".to_string(),
+        name: "Pad".to_string(),
+        buttons: vec![true, false],
+        ..Default::default()
+    });
+    assert!(pads.update(&slots) == [GamepadEvent::Connected(2)]);
+    assert!(pads.connected().collect::<Vec<_>>() == [2]);
+    assert!(pads.name(2) == Some("Pad"));
+    assert!(pads.first().unwrap().button(GamepadButton::ButtonA));
+    assert!(pads.just_pressed(2, GamepadButton::ButtonA));
+
+    slots[2].as_mut().unwrap().buttons = vec![false, true];
+    assert!(pads.update(&slots).is_empty());
+    assert!(pads.just_released(2, GamepadButton::ButtonA));
+    assert!(pads.just_pressed(2, GamepadButton::ButtonStart));
+
+    // Unknown devices fall back to GLFW's mapping
+    let mut glfw_mapped = Gamepad::new();
+    glfw_mapped.set_button(GamepadButton::ButtonY, true);
+    slots[0] = Some(RawJoystick {
+        guid: "other".to_string(),
+        mapped: Some(glfw_mapped),
+        ..Default::default()
+    });
+    slots[2] = None;
+    let events = pads.update(&slots);
+    assert!(events == [GamepadEvent::Connected(0), GamepadEvent::Disconnected(2)]);
+    assert!(pads.get(0).unwrap().button(GamepadButton::ButtonY));
+    assert!(pads.get(2).is_none());
+}
@@ -0,0 +1,320 @@
+pub mod action;
+pub mod gamepad;
+pub mod replay;
+pub mod sequence;
+pub mod text;
+
+use std::collections::HashSet;
+
+use glfw::{Action, Key, Modifiers, MouseButton};
+
+use crate::math::vector::*;
+use crate::window::Event;
+
+// Keyboard and mouse state built up from the window's events. Call `update`
+// once a frame with everything `Window::poll` returned, the just_* queries,
+// deltas, scroll and text then describe only that frame.
+#[derive(Debug, Clone)]
+pub struct Input {
+    keys: HashSet<Key>,
+    keys_pressed: HashSet<Key>,
+    keys_released: HashSet<Key>,
+    buttons: HashSet<MouseButton>,
+    buttons_pressed: HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    // None until the first cursor event so the first move isn't a huge jump
+    mouse: Option<Vec2d>,
+    mouse_delta: Vec2d,
+    scroll: Vec2d,
+    modifiers: Modifiers,
+    text: String,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self {
+            keys: HashSet::new(),
+            keys_pressed: HashSet::new(),
+            keys_released: HashSet::new(),
+            buttons: HashSet::new(),
+            buttons_pressed: HashSet::new(),
+            buttons_released: HashSet::new(),
+            mouse: None,
+            mouse_delta: Vec2d::zero(),
+            scroll: Vec2d::zero(),
+            modifiers: Modifiers::empty(),
+            text: String::new(),
+        }
+    }
+
+    pub fn update<I: IntoIterator<Item = (f64, Event)>>(&mut self, events: I) {
+        self.begin_frame();
+        for (_, event) in events {
+            self.handle(&event);
+        }
+    }
+
+    // Clears the per-frame state, held keys and buttons are kept
+    pub fn begin_frame(&mut self) {
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+        self.mouse_delta = Vec2d::zero();
+        self.scroll = Vec2d::zero();
+        self.text.clear();
+    }
+
+    pub fn handle(&mut self, event: &Event) {
+        match *event {
+            Event::Key(key, _, action, mods) => {
+                self.modifiers = mods;
+                match action {
+                    Action::Press => {
+                        if self.keys.insert(key) {
+                            self.keys_pressed.insert(key);
+                        }
+                    }
+                    Action::Release => {
+                        if self.keys.remove(&key) {
+                            self.keys_released.insert(key);
+                        }
+                    }
+                    Action::Repeat => {}
+                }
+                // Platforms disagree on whether a modifier key's own event
+                // includes its flag, so go by what's held
+                if let Some(flag) = modifier_flag(key) {
+                    self.modifiers.set(flag, action != Action::Release);
+                }
+            }
+            Event::MouseButton(button, action, mods) => {
+                self.modifiers = mods;
+                match action {
+                    Action::Press => {
+                        if self.buttons.insert(button) {
+                            self.buttons_pressed.insert(button);
+                        }
+                    }
+                    Action::Release => {
+                        if self.buttons.remove(&button) {
+                            self.buttons_released.insert(button);
+                        }
+                    }
+                    Action::Repeat => {}
+                }
+            }
+            Event::CursorPos(x, y) => {
+                let pos = Vec2d::from([x, y]);
+                if let Some(last) = self.mouse {
+                    self.mouse_delta = self.mouse_delta + (pos - last);
+                }
+                self.mouse = Some(pos);
+            }
+            Event::Scroll(x, y) => {
+                self.scroll = self.scroll + Vec2d::from([x, y]);
+            }
+            // Windows poll both char events, so every typed character also
+            // arrives as a CharModifiers that must be ignored
+            Event::Char(c) => self.text.push(c),
+            // Releases that happen while unfocused never arrive
+            Event::Focus(false) => self.release_all(),
+            _ => {}
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        self.keys_released.extend(self.keys.drain());
+        self.buttons_released.extend(self.buttons.drain());
+        self.modifiers = Modifiers::empty();
+    }
+
+    pub fn is_key_down(&self, key: Key) -> bool {
+        self.keys.contains(&key)
+    }
+
+    // A key tapped within one frame is both just pressed and just released
+    pub fn just_pressed(&self, key: Key) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    pub fn just_released(&self, key: Key) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    pub fn keys_down(&self) -> impl Iterator<Item = &Key> {
+        self.keys.iter()
+    }
+
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    pub fn button_just_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    pub fn button_just_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    pub fn mouse_pos(&self) -> Vec2d {
+        self.mouse.unwrap_or_default()
+    }
+
+    pub fn mouse_delta(&self) -> Vec2d {
+        self.mouse_delta
+    }
+
+    // Total scroll this frame, y is the usual wheel
+    pub fn scroll(&self) -> Vec2d {
+        self.scroll
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    pub fn shift(&self) -> bool {
+        self.modifiers.contains(Modifiers::Shift)
+    }
+
+    pub fn control(&self) -> bool {
+        self.modifiers.contains(Modifiers::Control)
+    }
+
+    pub fn alt(&self) -> bool {
+        self.modifiers.contains(Modifiers::Alt)
+    }
+
+    pub fn super_key(&self) -> bool {
+        self.modifiers.contains(Modifiers::Super)
+    }
+
+    // Characters typed this frame
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+fn modifier_flag(key: Key) -> Option<Modifiers> {
+    match key {
+        Key::LeftShift | Key::RightShift => Some(Modifiers::Shift),
+        Key::LeftControl | Key::RightControl => Some(Modifiers::Control),
+        Key::LeftAlt | Key::RightAlt => Some(Modifiers::Alt),
+        Key::LeftSuper | Key::RightSuper => Some(Modifiers::Super),
+        _ => None,
+    }
+}
+
+pub const MOUSE_BUTTONS: [MouseButton; 8] = [
+    MouseButton::Button1,
+    MouseButton::Button2,
+    MouseButton::Button3,
+    MouseButton::Button4,
+    MouseButton::Button5,
+    MouseButton::Button6,
+    MouseButton::Button7,
+    MouseButton::Button8,
+];
+
+// Every key GLFW can report besides Unknown
+#[rustfmt::skip]
+pub const KEYS: [Key; 120] = [
+    Key::Space, Key::Apostrophe, Key::Comma, Key::Minus, Key::Period, Key::Slash,
+    Key::Num0, Key::Num1, Key::Num2, Key::Num3, Key::Num4,
+    Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+    Key::Semicolon, Key::Equal,
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I,
+    Key::J, Key::K, Key::L, Key::M, Key::N, Key::O, Key::P, Key::Q, Key::R,
+    Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::LeftBracket, Key::Backslash, Key::RightBracket, Key::GraveAccent,
+    Key::World1, Key::World2,
+    Key::Escape, Key::Enter, Key::Tab, Key::Backspace, Key::Insert, Key::Delete,
+    Key::Right, Key::Left, Key::Down, Key::Up,
+    Key::PageUp, Key::PageDown, Key::Home, Key::End,
+    Key::CapsLock, Key::ScrollLock, Key::NumLock, Key::PrintScreen, Key::Pause,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8,
+    Key::F9, Key::F10, Key::F11, Key::F12, Key::F13, Key::F14, Key::F15, Key::F16,
+    Key::F17, Key::F18, Key::F19, Key::F20, Key::F21, Key::F22, Key::F23, Key::F24,
+    Key::F25,
+    Key::Kp0, Key::Kp1, Key::Kp2, Key::Kp3, Key::Kp4,
+    Key::Kp5, Key::Kp6, Key::Kp7, Key::Kp8, Key::Kp9,
+    Key::KpDecimal, Key::KpDivide, Key::KpMultiply, Key::KpSubtract, Key::KpAdd,
+    Key::KpEnter, Key::KpEqual,
+    Key::LeftShift, Key::LeftControl, Key::LeftAlt, Key::LeftSuper,
+    Key::RightShift, Key::RightControl, Key::RightAlt, Key::RightSuper,
+    Key::Menu,
+];
+
+#[cfg(test)]
+fn key(key: Key, action: Action) -> (f64, Event) {
+    (0.0, Event::Key(key, 0, action, Modifiers::empty()))
+}
+
+#[test]
+fn test_input_keys() {
+    let mut input = Input::new();
+    input.update([key(Key::A, Action::Press), key(Key::B, Action::Press)]);
+    assert!(input.is_key_down(Key::A) && input.just_pressed(Key::A));
+    assert!(!input.just_released(Key::A));
+
+    // Held keys stay down, repeats don't count as new presses
+    input.update([key(Key::A, Action::Repeat), key(Key::B, Action::Release)]);
+    assert!(input.is_key_down(Key::A) && !input.just_pressed(Key::A));
+    assert!(!input.is_key_down(Key::B) && input.just_released(Key::B));
+
+    input.update([]);
+    assert!(!input.just_released(Key::B));
+
+    // A tap inside one frame still registers
+    input.update([key(Key::C, Action::Press), key(Key::C, Action::Release)]);
+    assert!(input.just_pressed(Key::C) && input.just_released(Key::C));
+    assert!(!input.is_key_down(Key::C));
+
+    input.update([(0.0, Event::Focus(false))]);
+    assert!(!input.is_key_down(Key::A) && input.just_released(Key::A));
+}
+
+#[test]
+fn test_input_mouse_and_text() {
+    let mut input = Input::new();
+    input.update([
+        (0.0, Event::CursorPos(10.0, 20.0)),
+        (0.0, Event::CursorPos(15.0, 18.0)),
+        (0.0, Event::Scroll(0.0, 1.0)),
+        (0.0, Event::Scroll(0.0, 2.0)),
+        (
+            0.0,
+            Event::MouseButton(MouseButton::Button1, Action::Press, Modifiers::empty()),
+        ),
+        (0.0, Event::Char('h')),
+        (0.0, Event::Char('é')),
+        key(Key::LeftShift, Action::Press),
+    ]);
+    assert!(input.mouse_pos() == [15.0, 18.0]);
+    assert!(input.mouse_delta() == [5.0, -2.0]);
+    assert!(input.scroll() == [0.0, 3.0]);
+    assert!(input.button_just_pressed(MouseButton::Button1));
+    assert!(input.text() == "hé");
+    assert!(input.shift() && !input.control());
+
+    input.update([key(Key::LeftShift, Action::Release)]);
+    assert!(input.mouse_delta() == [0.0, 0.0] && input.scroll() == [0.0, 0.0]);
+    assert!(input.is_button_down(MouseButton::Button1));
+    assert!(input.text().is_empty());
+    assert!(!input.shift());
+
+    // One keystroke on a real window arrives as both char events
+    input.update([
+        (0.0, Event::Char('x')),
+        (0.0, Event::CharModifiers('x', Modifiers::empty())),
+    ]);
+    assert!(input.text() == "x");
+}
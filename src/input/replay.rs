@@ -0,0 +1,440 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use glfw::{Action, Key, Modifiers, MouseButton};
+
+use crate::error::{Error, Result};
+use crate::input::KEYS;
+use crate::window::{Event, EventIter};
+
+const MAGIC: &[u8; 4] = b"QREC";
+pub const VERSION: u16 = 1;
+
+// A timestamped event stream, times are in seconds from the first event.
+//
+// The file is little endian: "QREC", a u16 version and a u32 event count,
+// then per event an f64 time, a u8 tag and the tag's fields.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Recording {
+    events: Vec<(f64, Event)>,
+    start: Option<f64>,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, time: f64, event: Event) {
+        let start = *self.start.get_or_insert(time);
+        self.events.push(((time - start).max(0.0), event));
+    }
+
+    // Records the events and hands them back, so a frame's poll can be
+    // wrapped without changing the code that reads it
+    pub fn record<'a, I: IntoIterator<Item = (f64, Event)>>(&mut self, events: I) -> EventIter<'a> {
+        let events: Vec<_> = events.into_iter().collect();
+        for (time, event) in &events {
+            self.push(*time, event.clone());
+        }
+        EventIter::from_events(events)
+    }
+
+    pub fn events(&self) -> &[(f64, Event)] {
+        &self.events
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.events.last().map_or(0.0, |e| e.0)
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&(self.events.len() as u32).to_le_bytes())?;
+        for (time, event) in &self.events {
+            w.write_all(&time.to_le_bytes())?;
+            write_event(w, event)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(Error::Format("not a recording".to_string()));
+        }
+        let version = u16::from_le_bytes(read(r)?);
+        if version != VERSION {
+            return Err(Error::Format(format!("recording version {}", version)));
+        }
+
+        let count = u32::from_le_bytes(read(r)?) as usize;
+        let mut events = Vec::with_capacity(count.min(1 << 16));
+        for _ in 0..count {
+            let time = f64::from_le_bytes(read(r)?);
+            events.push((time, read_event(r)?));
+        }
+        Ok(Self {
+            events,
+            start: Some(0.0),
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = std::io::BufReader::new(std::fs::File::open(path)?);
+        Self::read_from(&mut file)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_to(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+}
+
+// Plays a recording back one frame at a time. Frame times are scaled by the
+// speed, so the same recording and frame times always give the same events.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    recording: Recording,
+    next: usize,
+    time: f64,
+    pub speed: f64,
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            next: 0,
+            time: 0.0,
+            speed: 1.0,
+        }
+    }
+
+    pub fn set_speed(mut self, speed: f64) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.recording.len()
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    // Advances by a frame's worth of time, returning the events that fall
+    // inside it
+    pub fn poll(&mut self, frame_time: f64) -> EventIter<'static> {
+        let target = self.time + frame_time * self.speed.max(0.0);
+        self.skip_to(target)
+    }
+
+    // Plays everything up to the time in one go, for fast forwarding while
+    // keeping state built from the events correct
+    pub fn skip_to(&mut self, time: f64) -> EventIter<'static> {
+        let events = &self.recording.events;
+        let end = self.next + events[self.next..].partition_point(|e| e.0 <= time);
+        let out = events[self.next..end].to_vec();
+        self.next = end;
+        self.time = self.time.max(time);
+        EventIter::from_events(out)
+    }
+
+    // Jumps without playing the events in between, landing before any at
+    // exactly time, so seek(0.0) is the same as rewind. State built from
+    // earlier events isn't rebuilt, so to land somewhere exact reset it and
+    // `skip_to` from the start.
+    pub fn seek(&mut self, time: f64) {
+        let time = time.max(0.0);
+        self.next = self.recording.events.partition_point(|e| e.0 < time);
+        self.time = time;
+    }
+
+    pub fn rewind(&mut self) {
+        self.next = 0;
+        self.time = 0.0;
+    }
+}
+
+// Longer than any path a platform will hand over in a drop
+const MAX_PATH: usize = 1 << 16;
+
+fn read<R: Read, const N: usize>(r: &mut R) -> Result<[u8; N]> {
+    let mut bytes = [0; N];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_i32<R: Read>(r: &mut R) -> Result<i32> {
+    Ok(i32::from_le_bytes(read(r)?))
+}
+
+fn read_f64<R: Read>(r: &mut R) -> Result<f64> {
+    Ok(f64::from_le_bytes(read(r)?))
+}
+
+fn read_char<R: Read>(r: &mut R) -> Result<char> {
+    let c = u32::from_le_bytes(read(r)?);
+    char::from_u32(c).ok_or_else(|| Error::Format(format!("character {:#x}", c)))
+}
+
+fn read_mods<R: Read>(r: &mut R) -> Result<Modifiers> {
+    Ok(Modifiers::from_bits_truncate(read_i32(r)?))
+}
+
+fn read_action<R: Read>(r: &mut R) -> Result<Action> {
+    match read_i32(r)? {
+        0 => Ok(Action::Release),
+        1 => Ok(Action::Press),
+        2 => Ok(Action::Repeat),
+        n => Err(Error::Format(format!("action {}", n))),
+    }
+}
+
+fn write_event<W: Write>(w: &mut W, event: &Event) -> Result<()> {
+    let mut out = Vec::with_capacity(24);
+    let i32s = |out: &mut Vec<u8>, values: &[i32]| {
+        for v in values {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+    };
+    let f64s = |out: &mut Vec<u8>, x: f64, y: f64| {
+        out.extend_from_slice(&x.to_le_bytes());
+        out.extend_from_slice(&y.to_le_bytes());
+    };
+    // Booleans are written as i32 to keep the tags uniform
+    match event {
+        Event::Pos(x, y) => {
+            out.push(0);
+            i32s(&mut out, &[*x, *y]);
+        }
+        Event::Size(x, y) => {
+            out.push(1);
+            i32s(&mut out, &[*x, *y]);
+        }
+        Event::Close => out.push(2),
+        Event::Refresh => out.push(3),
+        Event::Focus(b) => {
+            out.push(4);
+            i32s(&mut out, &[*b as i32]);
+        }
+        Event::Iconify(b) => {
+            out.push(5);
+            i32s(&mut out, &[*b as i32]);
+        }
+        Event::FramebufferSize(x, y) => {
+            out.push(6);
+            i32s(&mut out, &[*x, *y]);
+        }
+        Event::MouseButton(button, action, mods) => {
+            out.push(7);
+            i32s(&mut out, &[*button as i32, *action as i32, mods.bits()]);
+        }
+        Event::CursorPos(x, y) => {
+            out.push(8);
+            f64s(&mut out, *x, *y);
+        }
+        Event::CursorEnter(b) => {
+            out.push(9);
+            i32s(&mut out, &[*b as i32]);
+        }
+        Event::Scroll(x, y) => {
+            out.push(10);
+            f64s(&mut out, *x, *y);
+        }
+        Event::Key(key, scancode, action, mods) => {
+            out.push(11);
+            i32s(
+                &mut out,
+                &[*key as i32, *scancode, *action as i32, mods.bits()],
+            );
+        }
+        Event::Char(c) => {
+            out.push(12);
+            i32s(&mut out, &[*c as i32]);
+        }
+        Event::CharModifiers(c, mods) => {
+            out.push(13);
+            i32s(&mut out, &[*c as i32, mods.bits()]);
+        }
+        Event::FileDrop(paths) => {
+            out.push(14);
+            out.extend_from_slice(&(paths.len() as u32).to_le_bytes());
+            for path in paths {
+                let path = path.to_string_lossy();
+                out.extend_from_slice(&(path.len() as u32).to_le_bytes());
+                out.extend_from_slice(path.as_bytes());
+            }
+        }
+        Event::Maximize(b) => {
+            out.push(15);
+            i32s(&mut out, &[*b as i32]);
+        }
+        Event::ContentScale(x, y) => {
+            out.push(16);
+            out.extend_from_slice(&x.to_le_bytes());
+            out.extend_from_slice(&y.to_le_bytes());
+        }
+    }
+    w.write_all(&out)?;
+    Ok(())
+}
+
+fn read_event<R: Read>(r: &mut R) -> Result<Event> {
+    let flag = |r: &mut R| Ok::<bool, Error>(read_i32(r)? != 0);
+    let tag = read::<R, 1>(r)?[0];
+    Ok(match tag {
+        0 => Event::Pos(read_i32(r)?, read_i32(r)?),
+        1 => Event::Size(read_i32(r)?, read_i32(r)?),
+        2 => Event::Close,
+        3 => Event::Refresh,
+        4 => Event::Focus(flag(r)?),
+        5 => Event::Iconify(flag(r)?),
+        6 => Event::FramebufferSize(read_i32(r)?, read_i32(r)?),
+        7 => {
+            let button = read_i32(r)?;
+            let button = MouseButton::from_i32(button)
+                .ok_or_else(|| Error::Format(format!("mouse button {}", button)))?;
+            Event::MouseButton(button, read_action(r)?, read_mods(r)?)
+        }
+        8 => Event::CursorPos(read_f64(r)?, read_f64(r)?),
+        9 => Event::CursorEnter(flag(r)?),
+        10 => Event::Scroll(read_f64(r)?, read_f64(r)?),
+        11 => {
+            let key = read_i32(r)?;
+            let key = KEYS
+                .iter()
+                .find(|k| **k as i32 == key)
+                .copied()
+                .unwrap_or(Key::Unknown);
+            Event::Key(key, read_i32(r)?, read_action(r)?, read_mods(r)?)
+        }
+        12 => Event::Char(read_char(r)?),
+        13 => Event::CharModifiers(read_char(r)?, read_mods(r)?),
+        14 => {
+            let count = u32::from_le_bytes(read(r)?);
+            let mut paths = Vec::new();
+            for _ in 0..count {
+                let len = u32::from_le_bytes(read(r)?) as usize;
+                // Don't trust a corrupt file to size the allocation
+                if len > MAX_PATH {
+                    return Err(Error::Format(format!("path of {} bytes", len)));
+                }
+                let mut bytes = vec![0; len];
+                r.read_exact(&mut bytes)?;
+                paths.push(PathBuf::from(String::from_utf8_lossy(&bytes).as_ref()));
+            }
+            Event::FileDrop(paths)
+        }
+        15 => Event::Maximize(flag(r)?),
+        16 => Event::ContentScale(f32::from_le_bytes(read(r)?), f32::from_le_bytes(read(r)?)),
+        _ => return Err(Error::Format(format!("event tag {}", tag))),
+    })
+}
+
+#[cfg(test)]
+fn test_recording() -> Recording {
+    let mut recording = Recording::new();
+    let frames = [
+        vec![
+            (
+                10.0,
+                Event::Key(Key::W, 17, Action::Press, Modifiers::Shift),
+            ),
+            (10.0, Event::CursorPos(1.5, -2.25)),
+        ],
+        vec![
+            (10.5, Event::Char('ß')),
+            (
+                10.5,
+                Event::MouseButton(MouseButton::Button2, Action::Release, Modifiers::empty()),
+            ),
+        ],
+        vec![
+            (11.0, Event::FileDrop(vec![PathBuf::from("/tmp/a b.png")])),
+            (11.25, Event::ContentScale(1.5, 1.5)),
+            (
+                12.0,
+                Event::Key(Key::W, 17, Action::Release, Modifiers::empty()),
+            ),
+        ],
+    ];
+    for frame in frames {
+        let passed: Vec<_> = recording.record(frame.clone()).collect();
+        assert!(passed == frame);
+    }
+    recording
+}
+
+#[test]
+fn test_recording_roundtrip() {
+    let recording = test_recording();
+    assert!(recording.len() == 7 && recording.duration() == 2.0);
+    assert!(recording.events()[0].0 == 0.0);
+
+    let mut bytes = Vec::new();
+    recording.write_to(&mut bytes).unwrap();
+    let read = Recording::read_from(&mut bytes.as_slice()).unwrap();
+    assert!(read.events() == recording.events());
+
+    // Truncated files and other versions are rejected
+    assert!(matches!(
+        Recording::read_from(&mut &bytes[..bytes.len() - 1]),
+        Err(Error::Io(_))
+    ));
+    bytes[4] = 9;
+    assert!(matches!(
+        Recording::read_from(&mut bytes.as_slice()),
+        Err(Error::Format(_))
+    ));
+
+    // A file drop claiming a 4 GiB path is refused before allocating
+    let drop = [14, 1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff];
+    assert!(matches!(
+        read_event(&mut drop.as_slice()),
+        Err(Error::Format(_))
+    ));
+}
+
+#[test]
+fn test_replay() {
+    let mut replay = Replay::new(test_recording());
+    assert!(replay.poll(0.25).count() == 2);
+    assert!(replay.poll(0.25).count() == 2);
+    assert!(replay.poll(0.25).count() == 0);
+
+    // Double speed covers the remaining 1.25s in under a second
+    replay.speed = 2.0;
+    assert!(replay.poll(0.5).count() == 2);
+    assert!(!replay.is_finished());
+    assert!(replay.poll(0.5).count() == 1);
+    assert!(replay.is_finished());
+
+    // Seeking lands before the events at that time
+    replay.seek(0.5);
+    let events: Vec<_> = replay.skip_to(1.0).collect();
+    assert!(events.len() == 3 && events[0].0 == 0.5 && events[2].0 == 1.0);
+    replay.seek(0.0);
+    assert!(replay.skip_to(0.0).count() == 2);
+
+    replay.rewind();
+    assert!(replay.time() == 0.0 && replay.skip_to(100.0).count() == 7);
+}
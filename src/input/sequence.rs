@@ -0,0 +1,506 @@
+use std::collections::VecDeque;
+
+use crate::input::action::{ActionMap, PRESS_THRESHOLD};
+
+// Stick directions relative to the way the character faces, so motions read
+// the same on both sides of the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    DownBack,
+    Down,
+    DownForward,
+    Back,
+    #[default]
+    Neutral,
+    Forward,
+    UpBack,
+    Up,
+    UpForward,
+}
+
+impl Direction {
+    // Forward and up are positive
+    pub fn from_axes(x: i32, y: i32) -> Self {
+        match (x.signum(), y.signum()) {
+            (-1, -1) => Direction::DownBack,
+            (0, -1) => Direction::Down,
+            (1, -1) => Direction::DownForward,
+            (-1, 0) => Direction::Back,
+            (1, 0) => Direction::Forward,
+            (-1, 1) => Direction::UpBack,
+            (0, 1) => Direction::Up,
+            (1, 1) => Direction::UpForward,
+            _ => Direction::Neutral,
+        }
+    }
+
+    pub fn x(self) -> i32 {
+        (self.numpad() as i32 - 1) % 3 - 1
+    }
+
+    pub fn y(self) -> i32 {
+        (self.numpad() as i32 - 1) / 3 - 1
+    }
+
+    // Numpad notation, 5 is neutral and 6 is forward
+    pub fn numpad(self) -> u8 {
+        self as u8 + 1
+    }
+
+    pub fn from_numpad(n: u8) -> Option<Self> {
+        match n {
+            1..=9 => Some(Direction::from_axes(
+                (n as i32 - 1) % 3 - 1,
+                (n as i32 - 1) / 3 - 1,
+            )),
+            _ => None,
+        }
+    }
+
+    // Whether holding this counts as holding other, so down-back charges
+    // back as well as down
+    pub fn holds(self, other: Direction) -> bool {
+        if other == Direction::Neutral {
+            return self == Direction::Neutral;
+        }
+        (other.x() == 0 || other.x() == self.x()) && (other.y() == 0 || other.y() == self.y())
+    }
+}
+
+// What was held on one frame. Buttons are a bit mask, bit n being the nth
+// button in Controls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Frame {
+    pub direction: Direction,
+    pub buttons: u32,
+}
+
+impl Frame {
+    pub fn new(direction: Direction, buttons: u32) -> Self {
+        Self { direction, buttons }
+    }
+
+    pub fn sample(actions: &ActionMap, controls: &Controls, facing_right: bool) -> Self {
+        let axis = |action: &str| {
+            let value = actions.value(action);
+            if value >= PRESS_THRESHOLD {
+                1
+            } else if value <= -PRESS_THRESHOLD {
+                -1
+            } else {
+                0
+            }
+        };
+        let x = axis(&controls.x);
+        let x = if facing_right { x } else { -x };
+        let mut buttons = 0;
+        for (i, button) in controls.buttons.iter().enumerate().take(32) {
+            if actions.is_down(button) {
+                buttons |= 1 << i;
+            }
+        }
+        Self::new(Direction::from_axes(x, axis(&controls.y)), buttons)
+    }
+}
+
+// Names of the actions a Frame is sampled from. The axes are screen
+// relative, right and up positive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Controls {
+    pub x: String,
+    pub y: String,
+    pub buttons: Vec<String>,
+}
+
+impl Controls {
+    pub fn new(x: &str, y: &str) -> Self {
+        Self {
+            x: x.to_string(),
+            y: y.to_string(),
+            buttons: Vec::new(),
+        }
+    }
+
+    pub fn set_buttons(mut self, buttons: &[&str]) -> Self {
+        self.buttons = buttons.iter().map(|b| b.to_string()).collect();
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    // The stick moved into exactly this direction
+    Direction(Direction),
+    // Held a direction for at least this many frames, ending on the frame
+    // the step counts at
+    Charge { direction: Direction, frames: u32 },
+    // Every button in the mask pressed within the simultaneous window
+    Press(u32),
+    // Every button in the mask let go, the last of them on this frame
+    Release(u32),
+}
+
+// Steps in order, each within step_window frames of the one before and the
+// whole thing within total_window frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sequence {
+    steps: Vec<Step>,
+    pub step_window: u32,
+    pub total_window: u32,
+}
+
+impl Sequence {
+    pub fn new(steps: Vec<Step>) -> Self {
+        Self {
+            steps,
+            step_window: 8,
+            total_window: 30,
+        }
+    }
+
+    // Numpad notation then a button press, "236" with a punch is a quarter
+    // circle forward punch. Buttons of 0 is a motion alone, so "656" is a
+    // forward dash. Bad digits are skipped.
+    pub fn motion(numpad: &str, buttons: u32) -> Self {
+        let mut steps: Vec<Step> = numpad
+            .bytes()
+            .filter_map(|b| Direction::from_numpad(b.wrapping_sub(b'0')))
+            .map(Step::Direction)
+            .collect();
+        if buttons != 0 {
+            steps.push(Step::Press(buttons));
+        }
+        Self::new(steps)
+    }
+
+    // Hold direction for frames, then the motion and buttons
+    pub fn charge(direction: Direction, frames: u32, numpad: &str, buttons: u32) -> Self {
+        let mut sequence = Self::motion(numpad, buttons);
+        sequence.steps.insert(0, Step::Charge { direction, frames });
+        sequence
+    }
+
+    pub fn set_step_window(mut self, frames: u32) -> Self {
+        self.step_window = frames;
+        self
+    }
+
+    pub fn set_total_window(mut self, frames: u32) -> Self {
+        self.total_window = frames;
+        self
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+}
+
+// The last few frames of input. Push one Frame per fixed update and ask for
+// sequences by frame count, never by time, so a replay detects exactly what
+// the original run did.
+#[derive(Debug, Clone)]
+pub struct SequenceBuffer {
+    frames: VecDeque<Frame>,
+    capacity: usize,
+    // Frame number of frames[0]
+    start: u64,
+    // The frame that fell off the front, so frames[0] still has a previous
+    dropped: Frame,
+    // Steps before this frame were used by an earlier match
+    consumed: u64,
+    // Frames apart that buttons still count as pressed together
+    pub simultaneous: u32,
+    // Frames a finished sequence keeps matching, so a move input during
+    // recovery still comes out
+    pub buffer: u32,
+}
+
+impl SequenceBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            start: 0,
+            dropped: Frame::default(),
+            consumed: 0,
+            simultaneous: 3,
+            buffer: 4,
+        }
+    }
+
+    pub fn set_simultaneous(mut self, frames: u32) -> Self {
+        self.simultaneous = frames;
+        self
+    }
+
+    pub fn set_buffer(mut self, frames: u32) -> Self {
+        self.buffer = frames;
+        self
+    }
+
+    pub fn push(&mut self, frame: Frame) {
+        if self.frames.len() == self.capacity {
+            self.dropped = self.frames.pop_front().unwrap_or_default();
+            self.start += 1;
+        }
+        self.frames.push_back(frame);
+    }
+
+    // Frames pushed so far
+    pub fn frame(&self) -> u64 {
+        self.start + self.frames.len() as u64
+    }
+
+    pub fn current(&self) -> Option<Frame> {
+        self.frames.back().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // Stops anything pushed so far from being part of another match, call it
+    // once a move has come out
+    pub fn consume(&mut self) {
+        self.consumed = self.frame();
+    }
+
+    pub fn clear(&mut self) {
+        self.consume();
+        self.dropped = self.current().unwrap_or_default();
+        self.start = self.frame();
+        self.frames.clear();
+    }
+
+    fn previous(&self, i: usize) -> Frame {
+        match i {
+            0 => self.dropped,
+            _ => self.frames[i - 1],
+        }
+    }
+
+    fn pressed(&self, i: usize) -> u32 {
+        self.frames[i].buttons & !self.previous(i).buttons
+    }
+
+    fn occurs(&self, step: Step, i: usize) -> bool {
+        let frame = self.frames[i];
+        match step {
+            Step::Direction(d) => frame.direction == d && self.previous(i).direction != d,
+            Step::Charge { direction, frames } => {
+                let frames = (frames as usize).max(1);
+                i + 1 >= frames
+                    && (i + 1 - frames..=i).all(|j| self.frames[j].direction.holds(direction))
+            }
+            Step::Press(mask) => {
+                if mask == 0 || frame.buttons & mask != mask || self.pressed(i) & mask == 0 {
+                    return false;
+                }
+                let first = i.saturating_sub(self.simultaneous as usize);
+                let pressed = (first..=i).fold(0, |bits, j| bits | self.pressed(j));
+                pressed & mask == mask
+            }
+            Step::Release(mask) => {
+                let released = self.previous(i).buttons & !frame.buttons;
+                mask != 0 && frame.buttons & mask == 0 && released & mask != 0
+            }
+        }
+    }
+
+    // Finds the steps before `next` working backwards, trying the latest
+    // place each could have happened first. Answers are kept in memo by
+    // step and frame, so each is worked out once.
+    fn search(
+        &self,
+        sequence: &Sequence,
+        step: usize,
+        next: usize,
+        first: usize,
+        memo: &mut [Option<bool>],
+    ) -> bool {
+        let key = step * self.frames.len() + next;
+        if let Some(found) = memo[key] {
+            return found;
+        }
+        let current = sequence.steps[step];
+        let after = sequence.steps[step + 1];
+        // A frame has one direction, so two direction steps can't share it
+        let latest = match (current, after) {
+            (Step::Direction(_), Step::Direction(_)) if next == 0 => return false,
+            (Step::Direction(_), Step::Direction(_)) => next - 1,
+            _ => next,
+        };
+        let earliest = first.max(next.saturating_sub(sequence.step_window as usize));
+        let found = (earliest..=latest).rev().any(|i| {
+            self.occurs(current, i)
+                && (step == 0 || self.search(sequence, step - 1, i, first, memo))
+        });
+        memo[key] = Some(found);
+        found
+    }
+
+    pub fn matches(&self, sequence: &Sequence) -> bool {
+        let last = match sequence.steps.len().checked_sub(1) {
+            Some(last) if !self.frames.is_empty() => last,
+            _ => return false,
+        };
+        let lower = self.consumed.saturating_sub(self.start) as usize;
+        let now = self.frames.len() - 1;
+        let earliest = lower.max(now.saturating_sub(self.buffer as usize));
+        (earliest..=now).rev().any(|end| {
+            if !self.occurs(sequence.steps[last], end) {
+                return false;
+            }
+            let first = lower.max(end.saturating_sub(sequence.total_window as usize));
+            let mut memo = vec![None; last * self.frames.len()];
+            last == 0 || self.search(sequence, last - 1, end, first, &mut memo)
+        })
+    }
+
+    // The first matching move, so list the longer motions that contain
+    // shorter ones first
+    pub fn first_match<'a, T>(&self, moves: &'a [(T, Sequence)]) -> Option<&'a T> {
+        moves
+            .iter()
+            .find(|(_, sequence)| self.matches(sequence))
+            .map(|(name, _)| name)
+    }
+}
+
+#[cfg(test)]
+const PUNCH: u32 = 1;
+#[cfg(test)]
+const KICK: u32 = 2;
+
+// Holds each numpad direction and buttons for a number of frames
+#[cfg(test)]
+fn play(buffer: &mut SequenceBuffer, frames: &[(u8, u32, usize)]) {
+    for &(numpad, buttons, count) in frames {
+        let direction = Direction::from_numpad(numpad).unwrap();
+        for _ in 0..count {
+            buffer.push(Frame::new(direction, buttons));
+        }
+    }
+}
+
+#[test]
+fn test_sequence_motions() {
+    assert!(Direction::from_numpad(3) == Some(Direction::DownForward));
+    assert!(Direction::DownBack.x() == -1 && Direction::DownBack.y() == -1);
+    assert!(Direction::DownBack.holds(Direction::Back) && !Direction::Down.holds(Direction::Back));
+
+    let fireball = Sequence::motion("236", PUNCH);
+    let dragon = Sequence::motion("623", PUNCH);
+    let moves = [("dragon", dragon.clone()), ("fireball", fireball.clone())];
+
+    let mut buffer = SequenceBuffer::new(60);
+    play(&mut buffer, &[(5, 0, 5), (2, 0, 2), (3, 0, 2), (6, 0, 2)]);
+    assert!(!buffer.matches(&fireball));
+    play(&mut buffer, &[(6, PUNCH, 1)]);
+    assert!(buffer.first_match(&moves) == Some(&"fireball"));
+
+    // Still there during the buffer, gone after it or once consumed
+    play(&mut buffer, &[(5, 0, 4)]);
+    assert!(buffer.matches(&fireball));
+    play(&mut buffer, &[(5, 0, 1)]);
+    assert!(!buffer.matches(&fireball));
+    play(&mut buffer, &[(2, 0, 1), (3, 0, 1), (6, PUNCH, 1)]);
+    buffer.consume();
+    assert!(!buffer.matches(&fireball));
+
+    // The dragon punch motion ends in a fireball, so it is listed first
+    play(
+        &mut buffer,
+        &[(5, 0, 3), (6, 0, 2), (2, 0, 2), (3, PUNCH, 1)],
+    );
+    assert!(buffer.first_match(&moves) == Some(&"dragon"));
+
+    // Too slow between steps
+    play(
+        &mut buffer,
+        &[(5, 0, 3), (2, 0, 12), (3, 0, 1), (6, PUNCH, 1)],
+    );
+    assert!(!buffer.matches(&fireball));
+    assert!(buffer.matches(&fireball.set_step_window(12)));
+
+    // Many ways to place the later steps and none for the first, which
+    // has to stay cheap to rule out
+    let mut mash = vec![Step::Direction(Direction::Up)];
+    mash.extend([Step::Press(KICK); 6]);
+    let mash = Sequence::new(mash).set_step_window(30).set_total_window(60);
+    let mut buffer = SequenceBuffer::new(60);
+    for _ in 0..30 {
+        play(&mut buffer, &[(5, KICK, 1), (5, 0, 1)]);
+    }
+    assert!(!buffer.matches(&mash));
+}
+
+#[test]
+fn test_sequence_charge_and_taps() {
+    let sonic = Sequence::charge(Direction::Back, 30, "6", PUNCH);
+    let dash = Sequence::motion("656", 0);
+    let throw = Sequence::new(vec![Step::Press(PUNCH | KICK)]);
+
+    let mut buffer = SequenceBuffer::new(60);
+    // Down-back still charges back
+    play(&mut buffer, &[(4, 0, 20), (1, 0, 10), (6, PUNCH, 1)]);
+    assert!(buffer.matches(&sonic));
+    buffer.clear();
+    play(&mut buffer, &[(4, 0, 20), (6, PUNCH, 1)]);
+    assert!(!buffer.matches(&sonic));
+
+    play(&mut buffer, &[(5, 0, 2), (6, 0, 2), (5, 0, 2), (6, 0, 1)]);
+    assert!(buffer.matches(&dash));
+    play(&mut buffer, &[(5, 0, 2), (6, 0, 12), (5, 0, 2), (6, 0, 1)]);
+    buffer.consume();
+    play(&mut buffer, &[(5, 0, 2), (6, 0, 1)]);
+    assert!(!buffer.matches(&dash));
+
+    // Two frames apart counts as together, five doesn't
+    play(&mut buffer, &[(5, PUNCH, 2), (5, PUNCH | KICK, 1)]);
+    assert!(buffer.matches(&throw));
+    buffer.consume();
+    play(
+        &mut buffer,
+        &[(5, 0, 1), (5, KICK, 5), (5, PUNCH | KICK, 1)],
+    );
+    assert!(!buffer.matches(&throw));
+
+    // Sampled from actions, mirrored when facing left
+    use crate::input::action::Binding;
+    use crate::input::Input;
+    use glfw::{Action, Key, Modifiers};
+
+    let mut actions = ActionMap::new();
+    actions.bind(
+        "x",
+        Binding::Keys {
+            negative: Key::A,
+            positive: Key::D,
+        },
+    );
+    actions.bind(
+        "y",
+        Binding::Keys {
+            negative: Key::S,
+            positive: Key::W,
+        },
+    );
+    actions.bind("punch", Binding::Key(Key::J));
+    let controls = Controls::new("x", "y").set_buttons(&["punch"]);
+    let mut input = Input::new();
+    let press = |key| {
+        (
+            0.0,
+            crate::window::Event::Key(key, 0, Action::Press, Modifiers::empty()),
+        )
+    };
+    input.update([press(Key::A), press(Key::S), press(Key::J)]);
+    actions.update(&input, None);
+    assert!(Frame::sample(&actions, &controls, true) == Frame::new(Direction::DownBack, PUNCH));
+    assert!(Frame::sample(&actions, &controls, false).direction == Direction::DownForward);
+}
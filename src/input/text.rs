@@ -0,0 +1,553 @@
+use std::ops::Range;
+
+use glfw::{Action, Key, Modifiers};
+
+use crate::platform::Platform;
+use crate::window::Event;
+
+const MAX_UNDO: usize = 100;
+
+pub trait Clipboard {
+    fn get(&self) -> Option<String>;
+    fn set(&mut self, text: &str);
+}
+
+impl<P: Platform> Clipboard for P {
+    fn get(&self) -> Option<String> {
+        self.clipboard()
+    }
+
+    fn set(&mut self, text: &str) {
+        self.set_clipboard(text)
+    }
+}
+
+// Marks, joiners and modifiers that belong to the character before them.
+// A simplification of the Unicode grapheme rules that covers accents,
+// emoji sequences and flags without the full tables.
+fn is_extend(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+        | '\u{0483}'..='\u{0489}'
+        | '\u{0591}'..='\u{05BD}'
+        | '\u{0610}'..='\u{061A}'
+        | '\u{064B}'..='\u{065F}'
+        | '\u{0900}'..='\u{0903}'
+        | '\u{093A}'..='\u{094F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{200C}'..='\u{200D}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FE20}'..='\u{FE2F}'
+        | '\u{1F3FB}'..='\u{1F3FF}'
+        | '\u{E0020}'..='\u{E007F}'
+    )
+}
+
+fn is_regional(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+// Byte index of the end of the cluster starting at i
+pub fn next_boundary(s: &str, i: usize) -> usize {
+    let mut chars = s[i..].char_indices().map(|(j, c)| (i + j, c));
+    let first = match chars.next() {
+        Some((_, c)) => c,
+        None => return s.len(),
+    };
+    let mut prev = first;
+    let mut regional = is_regional(first) as usize;
+    for (j, c) in chars {
+        let joins = is_extend(c)
+            || prev == '\u{200D}'
+            || (prev == '\r' && c == '\n')
+            || (is_regional(c) && regional == 1);
+        if !joins {
+            return j;
+        }
+        regional += is_regional(c) as usize;
+        prev = c;
+    }
+    s.len()
+}
+
+// Byte index of the start of the cluster ending at i
+pub fn prev_boundary(s: &str, i: usize) -> usize {
+    let mut start = 0;
+    while start < i {
+        let next = next_boundary(s, start);
+        if next >= i {
+            return start;
+        }
+        start = next;
+    }
+    0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class {
+    Space,
+    Word,
+    Punctuation,
+}
+
+fn class(s: &str, i: usize) -> Class {
+    match s[i..].chars().next() {
+        Some(c) if c.is_whitespace() => Class::Space,
+        Some(c) if c.is_alphanumeric() || c == '_' => Class::Word,
+        _ => Class::Punctuation,
+    }
+}
+
+// Past any space, then to the end of the word or run of punctuation
+fn next_word(s: &str, mut i: usize) -> usize {
+    while i < s.len() && class(s, i) == Class::Space {
+        i = next_boundary(s, i);
+    }
+    if i < s.len() {
+        let run = class(s, i);
+        while i < s.len() && class(s, i) == run {
+            i = next_boundary(s, i);
+        }
+    }
+    i
+}
+
+fn prev_word(s: &str, mut i: usize) -> usize {
+    while i > 0 && class(s, prev_boundary(s, i)) == Class::Space {
+        i = prev_boundary(s, i);
+    }
+    if i > 0 {
+        let run = class(s, prev_boundary(s, i));
+        while i > 0 && class(s, prev_boundary(s, i)) == run {
+            i = prev_boundary(s, i);
+        }
+    }
+    i
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Snapshot {
+    text: String,
+    cursor: usize,
+    anchor: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Typing,
+    Other,
+}
+
+// An editable line of text with a cursor and selection, driven by window
+// events. Positions are byte indices and always sit on cluster boundaries.
+#[derive(Debug, Clone, Default)]
+pub struct TextInput {
+    text: String,
+    cursor: usize,
+    // The other end of the selection, if there is one
+    anchor: Option<usize>,
+    // Text an input method is still composing, shown at the cursor but not
+    // part of the text yet
+    preedit: Option<(String, usize)>,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    // Consecutive typing is undone as one step
+    last_edit: Option<Edit>,
+}
+
+impl TextInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_text(mut self, text: &str) -> Self {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor?;
+        if anchor == self.cursor {
+            return None;
+        }
+        Some(anchor.min(self.cursor)..anchor.max(self.cursor))
+    }
+
+    pub fn selected_text(&self) -> &str {
+        self.selection().map_or("", |r| &self.text[r])
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            text: self.text.clone(),
+            cursor: self.cursor,
+            anchor: self.anchor,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.text = snapshot.text;
+        self.cursor = snapshot.cursor;
+        self.anchor = snapshot.anchor;
+        self.last_edit = None;
+    }
+
+    fn begin_edit(&mut self, edit: Edit) {
+        if edit == Edit::Other || self.last_edit != Some(Edit::Typing) {
+            if self.undo.len() == MAX_UNDO {
+                self.undo.remove(0);
+            }
+            self.undo.push(self.snapshot());
+        }
+        self.redo.clear();
+        self.last_edit = Some(edit);
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        match self.selection() {
+            Some(range) => {
+                self.cursor = range.start;
+                self.text.replace_range(range, "");
+                self.anchor = None;
+                true
+            }
+            None => {
+                self.anchor = None;
+                false
+            }
+        }
+    }
+
+    fn insert_with(&mut self, text: &str, edit: Edit) {
+        // Typing a space starts a new undo step for the next word
+        if text.chars().any(char::is_whitespace) {
+            self.last_edit = None;
+        }
+        self.begin_edit(edit);
+        self.delete_selection();
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    // Replaces the selection, if any
+    pub fn insert(&mut self, text: &str) {
+        self.insert_with(text, Edit::Other);
+    }
+
+    fn delete_to(&mut self, to: usize) {
+        let range = self.cursor.min(to)..self.cursor.max(to);
+        if range.is_empty() {
+            return;
+        }
+        self.begin_edit(Edit::Other);
+        self.cursor = range.start;
+        self.text.replace_range(range, "");
+    }
+
+    // Deletes the selection, or the cluster before the cursor
+    pub fn backspace(&mut self) {
+        if self.selection().is_some() {
+            self.begin_edit(Edit::Other);
+            self.delete_selection();
+        } else {
+            self.delete_to(prev_boundary(&self.text, self.cursor));
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.selection().is_some() {
+            self.begin_edit(Edit::Other);
+            self.delete_selection();
+        } else {
+            self.delete_to(next_boundary(&self.text, self.cursor));
+        }
+    }
+
+    pub fn delete_word_left(&mut self) {
+        if self.selection().is_some() {
+            return self.backspace();
+        }
+        self.delete_to(prev_word(&self.text, self.cursor));
+    }
+
+    pub fn delete_word_right(&mut self) {
+        if self.selection().is_some() {
+            return self.delete();
+        }
+        self.delete_to(next_word(&self.text, self.cursor));
+    }
+
+    // Moving with select extends the selection, without it any selection
+    // collapses
+    fn move_to(&mut self, to: usize, select: bool) {
+        if select {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = to;
+        self.last_edit = None;
+    }
+
+    pub fn move_left(&mut self, select: bool) {
+        let to = match self.selection() {
+            Some(range) if !select => range.start,
+            _ => prev_boundary(&self.text, self.cursor),
+        };
+        self.move_to(to, select);
+    }
+
+    pub fn move_right(&mut self, select: bool) {
+        let to = match self.selection() {
+            Some(range) if !select => range.end,
+            _ => next_boundary(&self.text, self.cursor),
+        };
+        self.move_to(to, select);
+    }
+
+    pub fn move_word_left(&mut self, select: bool) {
+        self.move_to(prev_word(&self.text, self.cursor), select);
+    }
+
+    pub fn move_word_right(&mut self, select: bool) {
+        self.move_to(next_word(&self.text, self.cursor), select);
+    }
+
+    pub fn home(&mut self, select: bool) {
+        self.move_to(0, select);
+    }
+
+    pub fn end(&mut self, select: bool) {
+        self.move_to(self.text.len(), select);
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = Some(0);
+        self.cursor = self.text.len();
+        self.last_edit = None;
+    }
+
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop() {
+            Some(snapshot) => {
+                self.redo.push(self.snapshot());
+                self.restore(snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(snapshot) => {
+                self.undo.push(self.snapshot());
+                self.restore(snapshot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn copy<C: Clipboard + ?Sized>(&self, clipboard: &mut C) {
+        if self.selection().is_some() {
+            clipboard.set(self.selected_text());
+        }
+    }
+
+    pub fn cut<C: Clipboard + ?Sized>(&mut self, clipboard: &mut C) {
+        if self.selection().is_some() {
+            clipboard.set(self.selected_text());
+            self.backspace();
+        }
+    }
+
+    pub fn paste<C: Clipboard + ?Sized>(&mut self, clipboard: &C) {
+        if let Some(text) = clipboard.get() {
+            self.insert(&text);
+        }
+    }
+
+    // The cursor is a byte index into the composing text, moved back to
+    // the start of a character it lands inside
+    pub fn set_preedit(&mut self, text: &str, cursor: usize) {
+        if text.is_empty() {
+            self.preedit = None;
+        } else {
+            let mut cursor = cursor.min(text.len());
+            while !text.is_char_boundary(cursor) {
+                cursor -= 1;
+            }
+            self.preedit = Some((text.to_string(), cursor));
+        }
+    }
+
+    pub fn preedit(&self) -> Option<&str> {
+        self.preedit.as_ref().map(|(text, _)| text.as_str())
+    }
+
+    pub fn commit_preedit(&mut self) {
+        if let Some((text, _)) = self.preedit.take() {
+            self.insert(&text);
+        }
+    }
+
+    // What to draw: the text with any composition spliced in at the cursor,
+    // and where the caret goes in it
+    pub fn display(&self) -> (String, usize) {
+        match &self.preedit {
+            Some((preedit, caret)) => {
+                let mut text = self.text.clone();
+                text.insert_str(self.cursor, preedit);
+                (text, self.cursor + caret)
+            }
+            None => (self.text.clone(), self.cursor),
+        }
+    }
+
+    // Returns true if the event was used. Ctrl and Cmd both work as the
+    // shortcut modifier.
+    pub fn handle<C: Clipboard + ?Sized>(&mut self, event: &Event, clipboard: &mut C) -> bool {
+        let (key, mods) = match *event {
+            // Windows also send CharModifiers for every typed character,
+            // taking both would insert it twice
+            Event::Char(c) => {
+                if c.is_control() {
+                    return false;
+                }
+                self.insert_with(c.encode_utf8(&mut [0; 4]), Edit::Typing);
+                return true;
+            }
+            Event::Key(key, _, Action::Press | Action::Repeat, mods) => (key, mods),
+            _ => return false,
+        };
+        let shortcut = mods.intersects(Modifiers::Control | Modifiers::Super);
+        let select = mods.contains(Modifiers::Shift);
+        match key {
+            Key::Left if shortcut => self.move_word_left(select),
+            Key::Right if shortcut => self.move_word_right(select),
+            Key::Left => self.move_left(select),
+            Key::Right => self.move_right(select),
+            Key::Home => self.home(select),
+            Key::End => self.end(select),
+            Key::Backspace if shortcut => self.delete_word_left(),
+            Key::Delete if shortcut => self.delete_word_right(),
+            Key::Backspace => self.backspace(),
+            Key::Delete => self.delete(),
+            Key::A if shortcut => self.select_all(),
+            Key::C if shortcut => self.copy(clipboard),
+            Key::X if shortcut => self.cut(clipboard),
+            Key::V if shortcut => self.paste(clipboard),
+            Key::Z if shortcut && select => return self.redo(),
+            Key::Z if shortcut => return self.undo(),
+            Key::Y if shortcut => return self.redo(),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[test]
+fn test_clusters() {
+    // e + combining acute, a flag, a family joined with ZWJ, a skin tone
+    let s =
+        "e\u{301}\u{1F1EB}\u{1F1F7}\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}\u{1F44D}\u{1F3FD}a";
+    let mut bounds = vec![0];
+    while *bounds.last().unwrap() < s.len() {
+        bounds.push(next_boundary(s, *bounds.last().unwrap()));
+    }
+    assert!(bounds == [0, 3, 11, 29, 37, 38], "{:?}", bounds);
+    assert!(prev_boundary(s, 37) == 29 && prev_boundary(s, 3) == 0);
+
+    // Four regional indicators are two flags
+    let flags = "\u{1F1EB}\u{1F1F7}\u{1F1E9}\u{1F1EA}";
+    assert!(next_boundary(flags, 0) == 8);
+    assert!(next_boundary("\r\nx", 0) == 2);
+}
+
+#[test]
+fn test_text_editing() {
+    let mut input = TextInput::new().set_text("hello wo\u{301}rld");
+    input.backspace();
+    input.move_left(false);
+    input.move_left(false);
+    // Backspace takes the accent with its letter
+    input.backspace();
+    assert!(input.text() == "hello wrl");
+
+    input.move_word_left(false);
+    assert!(input.cursor() == 6);
+    input.move_word_right(true);
+    assert!(input.selected_text() == "wrl");
+    input.insert("world");
+    assert!(input.text() == "hello world");
+
+    input.home(false);
+    input.move_word_right(true);
+    input.delete();
+    assert!(input.text() == " world");
+    input.end(false);
+    input.delete_word_left();
+    assert!(input.text() == " ");
+
+    // Every edit can be undone, and redone
+    assert!(input.undo() && input.text() == " world");
+    assert!(input.undo() && input.text() == "hello world");
+    assert!(input.redo() && input.text() == " world");
+    while input.undo() {}
+    assert!(input.text() == "hello wo\u{301}rld");
+}
+
+#[test]
+fn test_text_events() {
+    use crate::platform::headless::Headless;
+
+    let mut clipboard = Headless::new([1, 1]);
+    let key = |key: Key, mods: Modifiers| Event::Key(key, 0, Action::Press, mods);
+    let mut input = TextInput::new();
+    for c in "ab cd".chars() {
+        assert!(input.handle(&Event::Char(c), &mut clipboard));
+    }
+    // Typing coalesces per word
+    input.undo();
+    assert!(input.text() == "ab");
+    input.redo();
+
+    input.handle(&key(Key::A, Modifiers::Control), &mut clipboard);
+    input.handle(&key(Key::C, Modifiers::Control), &mut clipboard);
+    assert!(Clipboard::get(&clipboard).as_deref() == Some("ab cd"));
+    input.handle(&key(Key::End, Modifiers::empty()), &mut clipboard);
+    input.handle(&key(Key::V, Modifiers::Super), &mut clipboard);
+    assert!(input.text() == "ab cdab cd");
+
+    input.handle(&key(Key::Left, Modifiers::Shift), &mut clipboard);
+    input.handle(&key(Key::X, Modifiers::Control), &mut clipboard);
+    assert!(input.text() == "ab cdab c" && Clipboard::get(&clipboard).as_deref() == Some("d"));
+    assert!(!input.handle(&key(Key::F1, Modifiers::empty()), &mut clipboard));
+    assert!(!input.handle(&Event::Char('\u{8}'), &mut clipboard));
+
+    input.set_preedit("にほ", 1);
+    assert!(input.display() == ("ab cdab cにほ".to_string(), 9));
+    input.set_preedit("にほ", 3);
+    assert!(input.display() == ("ab cdab cにほ".to_string(), 12));
+    input.commit_preedit();
+    assert!(input.text() == "ab cdab cにほ" && input.preedit().is_none());
+
+    // One keystroke on a real window arrives as both char events
+    let mut input = TextInput::new();
+    input.handle(&Event::Char('x'), &mut clipboard);
+    let twin = Event::CharModifiers('x', Modifiers::empty());
+    assert!(!input.handle(&twin, &mut clipboard));
+    assert!(input.text() == "x");
+}
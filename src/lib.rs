@@ -1,3 +1,11 @@
+pub mod bus;
+pub mod error;
+pub mod input;
 pub mod math;
 pub mod net;
+pub mod platform;
+pub mod render;
+pub mod time;
 pub mod window;
+
+pub use error::{Error, Result};
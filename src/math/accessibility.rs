@@ -0,0 +1,222 @@
+use crate::math::color::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Deficiency {
+    // No red cones
+    Protanopia,
+    // No green cones
+    Deuteranopia,
+    // No blue cones
+    Tritanopia,
+    // No color vision at all
+    Achromatopsia,
+}
+
+impl Deficiency {
+    pub const ALL: [Deficiency; 4] = [
+        Deficiency::Protanopia,
+        Deficiency::Deuteranopia,
+        Deficiency::Tritanopia,
+        Deficiency::Achromatopsia,
+    ];
+
+    // Linear RGB simulation matrices, the dichromacies are from Machado,
+    // Oliveira and Fernandes 2009 at full severity
+    pub fn matrix(&self) -> [[f32; 3]; 3] {
+        match self {
+            Deficiency::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            Deficiency::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            Deficiency::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+            Deficiency::Achromatopsia => [
+                [0.2126, 0.7152, 0.0722],
+                [0.2126, 0.7152, 0.0722],
+                [0.2126, 0.7152, 0.0722],
+            ],
+        }
+    }
+}
+
+fn to_linear(c: ColorRGBA) -> [f32; 3] {
+    [
+        srgb_to_linear(c.r),
+        srgb_to_linear(c.g),
+        srgb_to_linear(c.b),
+    ]
+}
+
+fn from_linear(c: [f32; 3], a: u8) -> ColorRGBA {
+    ColorRGBA::from_rgba(
+        linear_to_srgb(c[0]),
+        linear_to_srgb(c[1]),
+        linear_to_srgb(c[2]),
+        a,
+    )
+}
+
+fn transform(m: &[[f32; 3]; 3], c: [f32; 3]) -> [f32; 3] {
+    let mut out = [0.0; 3];
+    for (o, row) in out.iter_mut().zip(m) {
+        *o = row[0] * c[0] + row[1] * c[1] + row[2] * c[2];
+    }
+    out
+}
+
+// How the color looks to someone with the deficiency, alpha is kept
+pub fn simulate(c: ColorRGBA, deficiency: Deficiency) -> ColorRGBA {
+    from_linear(transform(&deficiency.matrix(), to_linear(c)), c.a)
+}
+
+pub fn simulate_buffer(pixels: &[ColorRGBA], deficiency: Deficiency) -> Vec<ColorRGBA> {
+    pixels.iter().map(|c| simulate(*c, deficiency)).collect()
+}
+
+// Shifts the information lost to the deficiency into channels that are still
+// seen, so colors that collapse together get pulled apart again
+pub fn daltonize(c: ColorRGBA, deficiency: Deficiency) -> ColorRGBA {
+    let original = to_linear(c);
+    let seen = transform(&deficiency.matrix(), original);
+    let error = [
+        original[0] - seen[0],
+        original[1] - seen[1],
+        original[2] - seen[2],
+    ];
+
+    let shift = match deficiency {
+        Deficiency::Protanopia | Deficiency::Deuteranopia => {
+            [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]]
+        }
+        Deficiency::Tritanopia => [[1.0, 0.0, 0.7], [0.0, 1.0, 0.7], [0.0, 0.0, 0.0]],
+        // Nothing to shift into, lightness is all that's left
+        Deficiency::Achromatopsia => [[0.0; 3]; 3],
+    };
+    let correction = transform(&shift, error);
+
+    from_linear(
+        [
+            original[0] + correction[0],
+            original[1] + correction[1],
+            original[2] + correction[2],
+        ],
+        c.a,
+    )
+}
+
+pub fn daltonize_buffer(pixels: &[ColorRGBA], deficiency: Deficiency) -> Vec<ColorRGBA> {
+    pixels.iter().map(|c| daltonize(*c, deficiency)).collect()
+}
+
+// WCAG 2 relative luminance, 0 for black and 1 for white. Alpha is ignored.
+pub fn relative_luminance(c: ColorRGBA) -> f32 {
+    let [r, g, b] = to_linear(c);
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+// WCAG 2 contrast ratio from 1 to 21. A translucent foreground is blended
+// over the background first, the background is treated as opaque.
+pub fn contrast_ratio(foreground: ColorRGBA, background: ColorRGBA) -> f32 {
+    let a = foreground.a as f32 / 255.0;
+    let blend = |f: u8, b: u8| (f as f32 * a + b as f32 * (1.0 - a)).round() as u8;
+    let fg = ColorRGBA::from_rgba(
+        blend(foreground.r, background.r),
+        blend(foreground.g, background.g),
+        blend(foreground.b, background.b),
+        255,
+    );
+
+    let l1 = relative_luminance(fg);
+    let l2 = relative_luminance(background);
+    (l1.max(l2) + 0.05) / (l1.min(l2) + 0.05)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Wcag {
+    AA,
+    AAA,
+}
+
+impl Wcag {
+    // Large text is 18pt, or 14pt bold, and up
+    pub fn min_ratio(&self, large_text: bool) -> f32 {
+        match (self, large_text) {
+            (Wcag::AA, false) => 4.5,
+            (Wcag::AA, true) => 3.0,
+            (Wcag::AAA, false) => 7.0,
+            (Wcag::AAA, true) => 4.5,
+        }
+    }
+
+    pub fn passes(&self, foreground: ColorRGBA, background: ColorRGBA, large_text: bool) -> bool {
+        contrast_ratio(foreground, background) >= self.min_ratio(large_text)
+    }
+}
+
+#[test]
+fn test_contrast() {
+    let black = ColorRGBA::from_rgba(0, 0, 0, 255);
+    let white = ColorRGBA::from_rgba(255, 255, 255, 255);
+    assert!((contrast_ratio(black, white) - 21.0).abs() < 1e-3);
+    assert!((contrast_ratio(white, black) - 21.0).abs() < 1e-3);
+    assert!((contrast_ratio(white, white) - 1.0).abs() < 1e-3);
+
+    // #777 on white is the classic just-fails-AA grey
+    let grey = ColorRGBA::from_u32(0x77_77_77_FF);
+    let ratio = contrast_ratio(grey, white);
+    assert!((ratio - 4.48).abs() < 0.01, "{}", ratio);
+    assert!(!Wcag::AA.passes(grey, white, false));
+    assert!(Wcag::AA.passes(grey, white, true));
+
+    // Invisible text has no contrast
+    let clear = ColorRGBA::from_rgba(0, 0, 0, 0);
+    assert!((contrast_ratio(clear, white) - 1.0).abs() < 1e-3);
+}
+
+#[test]
+fn test_simulate() {
+    let red = ColorRGBA::from_rgba(255, 0, 0, 255);
+    let green = ColorRGBA::from_rgba(0, 255, 0, 128);
+
+    let grey = simulate(red, Deficiency::Achromatopsia);
+    assert!(grey.r == grey.g && grey.g == grey.b);
+    assert!(simulate(green, Deficiency::Deuteranopia).a == 128);
+
+    // Neutrals look the same to everyone
+    let white = ColorRGBA::from_rgba(255, 255, 255, 255);
+    for d in Deficiency::ALL {
+        let s = simulate(white, d);
+        assert!(s.r >= 253 && s.g >= 253 && s.b >= 253, "{:?} {:?}", d, s);
+    }
+
+    // Red and green collapse for a protanope, daltonizing pulls them apart
+    let distance = |a: ColorRGBA, b: ColorRGBA| Oklab::from(a).distance_sq(Oklab::from(b));
+    let (red, green) = (
+        ColorRGBA::from_rgba(200, 60, 40, 255),
+        ColorRGBA::from_rgba(90, 140, 40, 255),
+    );
+    let before = distance(
+        simulate(red, Deficiency::Protanopia),
+        simulate(green, Deficiency::Protanopia),
+    );
+    let after = distance(
+        simulate(
+            daltonize(red, Deficiency::Protanopia),
+            Deficiency::Protanopia,
+        ),
+        simulate(
+            daltonize(green, Deficiency::Protanopia),
+            Deficiency::Protanopia,
+        ),
+    );
+    assert!(after > before);
+}
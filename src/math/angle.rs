@@ -0,0 +1,354 @@
+use crate::math::vector::*;
+use std::fmt::Display;
+use std::ops::*;
+
+// Angles carry their unit in the type so degrees can't be passed where
+// radians are expected. Convert with .into() or the to_ methods.
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Radians<T>(pub T);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Degrees<T>(pub T);
+
+macro_rules! impl_angle {
+    ($t:ident, $pi:expr) => {
+        impl Radians<$t> {
+            pub const ZERO: Self = Radians(0.0);
+            pub const HALF_TURN: Self = Radians($pi);
+            pub const TURN: Self = Radians($pi * 2.0);
+
+            pub fn to_degrees(self) -> Degrees<$t> {
+                Degrees(self.0.to_degrees())
+            }
+
+            // [-pi, pi)
+            pub fn wrap(self) -> Self {
+                Radians((self.0 + $pi).rem_euclid($pi * 2.0) - $pi)
+            }
+
+            // [0, 2pi)
+            pub fn wrap_positive(self) -> Self {
+                Radians(self.0.rem_euclid($pi * 2.0))
+            }
+
+            // Signed shortest turn from self to b, in [-pi, pi)
+            pub fn diff(self, b: Self) -> Self {
+                (b - self).wrap()
+            }
+
+            // Interpolates along the shortest arc
+            pub fn lerp(self, b: Self, t: $t) -> Self {
+                (self + self.diff(b) * t).wrap()
+            }
+        }
+
+        impl Degrees<$t> {
+            pub const ZERO: Self = Degrees(0.0);
+            pub const HALF_TURN: Self = Degrees(180.0);
+            pub const TURN: Self = Degrees(360.0);
+
+            pub fn to_radians(self) -> Radians<$t> {
+                Radians(self.0.to_radians())
+            }
+
+            // [-180, 180)
+            pub fn wrap(self) -> Self {
+                Degrees((self.0 + 180.0).rem_euclid(360.0) - 180.0)
+            }
+
+            // [0, 360)
+            pub fn wrap_positive(self) -> Self {
+                Degrees(self.0.rem_euclid(360.0))
+            }
+
+            // Signed shortest turn from self to b, in [-180, 180)
+            pub fn diff(self, b: Self) -> Self {
+                (b - self).wrap()
+            }
+
+            // Interpolates along the shortest arc
+            pub fn lerp(self, b: Self, t: $t) -> Self {
+                (self + self.diff(b) * t).wrap()
+            }
+        }
+
+        impl From<Degrees<$t>> for Radians<$t> {
+            fn from(d: Degrees<$t>) -> Self {
+                d.to_radians()
+            }
+        }
+
+        impl From<Radians<$t>> for Degrees<$t> {
+            fn from(r: Radians<$t>) -> Self {
+                r.to_degrees()
+            }
+        }
+
+        impl Sin<$t> for Radians<$t> {
+            fn sin(&self) -> $t {
+                self.0.sin()
+            }
+        }
+
+        impl Cos<$t> for Radians<$t> {
+            fn cos(&self) -> $t {
+                self.0.cos()
+            }
+        }
+
+        impl Sin<$t> for Degrees<$t> {
+            fn sin(&self) -> $t {
+                self.to_radians().sin()
+            }
+        }
+
+        impl Cos<$t> for Degrees<$t> {
+            fn cos(&self) -> $t {
+                self.to_radians().cos()
+            }
+        }
+    };
+}
+
+impl_angle!(f32, std::f32::consts::PI);
+impl_angle!(f64, std::f64::consts::PI);
+
+macro_rules! impl_angle_ops {
+    ($name:ident) => {
+        impl<T: Add<Output = T>> Add for $name<T> {
+            type Output = Self;
+
+            fn add(self, b: Self) -> Self::Output {
+                $name(self.0 + b.0)
+            }
+        }
+
+        impl<T: Sub<Output = T>> Sub for $name<T> {
+            type Output = Self;
+
+            fn sub(self, b: Self) -> Self::Output {
+                $name(self.0 - b.0)
+            }
+        }
+
+        impl<T: Mul<Output = T>> Mul<T> for $name<T> {
+            type Output = Self;
+
+            fn mul(self, b: T) -> Self::Output {
+                $name(self.0 * b)
+            }
+        }
+
+        impl<T: Div<Output = T>> Div<T> for $name<T> {
+            type Output = Self;
+
+            fn div(self, b: T) -> Self::Output {
+                $name(self.0 / b)
+            }
+        }
+
+        impl<T: Neg<Output = T>> Neg for $name<T> {
+            type Output = Self;
+
+            fn neg(self) -> Self::Output {
+                $name(-self.0)
+            }
+        }
+
+        impl<T: AddAssign> AddAssign for $name<T> {
+            fn add_assign(&mut self, b: Self) {
+                self.0 += b.0;
+            }
+        }
+
+        impl<T: SubAssign> SubAssign for $name<T> {
+            fn sub_assign(&mut self, b: Self) {
+                self.0 -= b.0;
+            }
+        }
+
+        impl<T: Zero> Zero for $name<T> {
+            fn zero() -> Self {
+                $name(T::zero())
+            }
+        }
+    };
+}
+
+impl_angle_ops!(Radians);
+impl_angle_ops!(Degrees);
+
+impl<T: Display> Display for Radians<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}rad", self.0)
+    }
+}
+
+impl<T: Display> Display for Degrees<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}°", self.0)
+    }
+}
+
+// 3D conversions treat +Y as the up axis, azimuth turns from +X towards +Z.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Polar {
+    pub radius: f32,
+    pub angle: Radians<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spherical {
+    pub radius: f32,
+    // Measured down from +Y
+    pub inclination: Radians<f32>,
+    pub azimuth: Radians<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cylindrical {
+    pub radius: f32,
+    pub azimuth: Radians<f32>,
+    pub height: f32,
+}
+
+impl Polar {
+    pub fn new<A: Into<Radians<f32>>>(radius: f32, angle: A) -> Self {
+        Self {
+            radius,
+            angle: angle.into(),
+        }
+    }
+}
+
+impl Spherical {
+    pub fn new<A: Into<Radians<f32>>>(radius: f32, inclination: A, azimuth: A) -> Self {
+        Self {
+            radius,
+            inclination: inclination.into(),
+            azimuth: azimuth.into(),
+        }
+    }
+}
+
+impl Cylindrical {
+    pub fn new<A: Into<Radians<f32>>>(radius: f32, azimuth: A, height: f32) -> Self {
+        Self {
+            radius,
+            azimuth: azimuth.into(),
+            height,
+        }
+    }
+}
+
+impl From<Polar> for Vec2f {
+    fn from(p: Polar) -> Self {
+        Vec2f::from([p.radius * p.angle.cos(), p.radius * p.angle.sin()])
+    }
+}
+
+impl From<Vec2f> for Polar {
+    fn from(v: Vec2f) -> Self {
+        Self {
+            radius: v.mag(),
+            angle: Radians(v[Y].atan2(v[X])),
+        }
+    }
+}
+
+impl From<Spherical> for Vec3f {
+    fn from(s: Spherical) -> Self {
+        let ring = s.radius * s.inclination.sin();
+        Vec3f::from([
+            ring * s.azimuth.cos(),
+            s.radius * s.inclination.cos(),
+            ring * s.azimuth.sin(),
+        ])
+    }
+}
+
+impl From<Vec3f> for Spherical {
+    fn from(v: Vec3f) -> Self {
+        let radius = v.mag();
+        let inclination = if radius == 0.0 {
+            0.0
+        } else {
+            (v[Y] / radius).clamp(-1.0, 1.0).acos()
+        };
+        Self {
+            radius,
+            inclination: Radians(inclination),
+            azimuth: Radians(v[Z].atan2(v[X])),
+        }
+    }
+}
+
+impl From<Cylindrical> for Vec3f {
+    fn from(c: Cylindrical) -> Self {
+        Vec3f::from([
+            c.radius * c.azimuth.cos(),
+            c.height,
+            c.radius * c.azimuth.sin(),
+        ])
+    }
+}
+
+impl From<Vec3f> for Cylindrical {
+    fn from(v: Vec3f) -> Self {
+        Self {
+            radius: Vec2f::from([v[X], v[Z]]).mag(),
+            azimuth: Radians(v[Z].atan2(v[X])),
+            height: v[Y],
+        }
+    }
+}
+
+#[cfg(test)]
+fn near(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4
+}
+
+#[test]
+fn test_angle_convert() {
+    let r: Radians<f32> = Degrees(180.0).into();
+    assert!(near(r.0, std::f32::consts::PI));
+    let d: Degrees<f64> = Radians(std::f64::consts::FRAC_PI_2).into();
+    assert!((d.0 - 90.0).abs() < 1e-9);
+    assert!(near(Degrees(90.0f32).sin(), 1.0));
+    assert!(near(Radians(0.0f32).cos(), 1.0));
+}
+
+#[test]
+fn test_angle_wrap() {
+    assert!(near(Degrees(370.0f32).wrap().0, 10.0));
+    assert!(near(Degrees(-190.0f32).wrap().0, 170.0));
+    assert!(near(Degrees(-10.0f32).wrap_positive().0, 350.0));
+    assert!(near(
+        Radians(std::f32::consts::PI * 3.0).wrap().0,
+        -std::f32::consts::PI
+    ));
+
+    // Shortest way from 350 to 10 is forwards through 0
+    assert!(near(Degrees(350.0f32).diff(Degrees(10.0)).0, 20.0));
+    assert!(near(Degrees(10.0f32).diff(Degrees(350.0)).0, -20.0));
+    assert!(near(Degrees(350.0f32).lerp(Degrees(10.0), 0.5).0, 0.0));
+}
+
+#[test]
+fn test_coordinates() {
+    let v: Vec2f = Polar::new(2.0, Degrees(90.0)).into();
+    assert!(near(v[X], 0.0) && near(v[Y], 2.0));
+    let p = Polar::from(Vec2f::from([-1.0, 0.0]));
+    assert!(near(p.radius, 1.0) && near(p.angle.0, std::f32::consts::PI));
+
+    let v = Vec3f::from([1.0, 2.0, 3.0]);
+    let back: Vec3f = Spherical::from(v).into();
+    assert!((0..3).all(|i| near(back[i], v[i])));
+    let back: Vec3f = Cylindrical::from(v).into();
+    assert!((0..3).all(|i| near(back[i], v[i])));
+
+    let up: Vec3f = Spherical::new(1.0, Degrees(0.0), Degrees(45.0)).into();
+    assert!(near(up[Y], 1.0));
+}
@@ -1,9 +1,11 @@
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ColorRGB {
     pub r: u8,
     pub g: u8,
     pub b: u8,
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ColorRGBA {
     pub r: u8,
     pub g: u8,
@@ -56,6 +58,126 @@ impl ColorRGBA {
             a: (c >> 0) as u8,
         }
     }
+
+    // Same layout as from_u32, other layouts go through pixel::PixelFormat
+    pub fn to_u32(&self) -> u32 {
+        (self.r as u32) << 24 | (self.g as u32) << 16 | (self.b as u32) << 8 | self.a as u32
+    }
+}
+
+impl From<ColorRGBA> for ColorRGB {
+    fn from(c: ColorRGBA) -> Self {
+        Self::from_rgb(c.r, c.g, c.b)
+    }
+}
+
+impl From<ColorRGB> for ColorRGBA {
+    fn from(c: ColorRGB) -> Self {
+        Self::from_rgba(c.r, c.g, c.b, 255)
+    }
+}
+
+pub fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let c = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (c * 255.0).round() as u8
+}
+
+// Perceptual color space, euclidean distance roughly matches how different
+// two colors look. https://bottosson.github.io/posts/oklab/
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Oklab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+#[allow(clippy::excessive_precision)]
+impl Oklab {
+    pub fn from_linear(r: f32, g: f32, b: f32) -> Self {
+        let l = 0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b;
+        let m = 0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b;
+        let s = 0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b;
+
+        let (l, m, s) = (l.cbrt(), m.cbrt(), s.cbrt());
+
+        Self {
+            l: 0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            a: 1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            b: 0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+        }
+    }
+
+    pub fn to_linear(self) -> (f32, f32, f32) {
+        let l = self.l + 0.3963377774 * self.a + 0.2158037573 * self.b;
+        let m = self.l - 0.1055613458 * self.a - 0.0638541728 * self.b;
+        let s = self.l - 0.0894841775 * self.a - 1.2914855480 * self.b;
+
+        let (l, m, s) = (l * l * l, m * m * m, s * s * s);
+
+        (
+            4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+            -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+            -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+        )
+    }
+
+    pub fn distance_sq(self, o: Oklab) -> f32 {
+        let (dl, da, db) = (self.l - o.l, self.a - o.a, self.b - o.b);
+        dl * dl + da * da + db * db
+    }
+}
+
+impl From<ColorRGB> for Oklab {
+    fn from(c: ColorRGB) -> Self {
+        Oklab::from_linear(
+            srgb_to_linear(c.r),
+            srgb_to_linear(c.g),
+            srgb_to_linear(c.b),
+        )
+    }
+}
+
+impl From<ColorRGBA> for Oklab {
+    fn from(c: ColorRGBA) -> Self {
+        ColorRGB::from(c).into()
+    }
+}
+
+impl From<Oklab> for ColorRGB {
+    fn from(c: Oklab) -> Self {
+        let (r, g, b) = c.to_linear();
+        Self::from_rgb(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b))
+    }
+}
+
+#[test]
+fn test_oklab_round_trip() {
+    for c in [
+        ColorRGB::from_rgb(0, 0, 0),
+        ColorRGB::from_rgb(255, 255, 255),
+        ColorRGB::from_rgb(255, 0, 0),
+        ColorRGB::from_rgb(12, 200, 77),
+    ] {
+        let lab = Oklab::from(c);
+        assert!(ColorRGB::from(lab) == c);
+    }
+
+    let white = Oklab::from(ColorRGB::from_rgb(255, 255, 255));
+    assert!((white.l - 1.0).abs() < 1e-3 && white.a.abs() < 1e-3);
 }
 
 #[test]
@@ -82,4 +204,6 @@ fn test_i32_to_color() {
 
     let c = ColorRGBA::from_u32(0xFF_FF_FF_FF);
     assert!(c.r == 255 && c.g == 255 && c.b == 255 && c.a == 255);
+
+    assert!(ColorRGBA::from_u32(0x12_34_56_78).to_u32() == 0x12_34_56_78);
 }
@@ -0,0 +1,319 @@
+use crate::math::vector::*;
+use std::ops::*;
+
+pub trait Scalar:
+    Copy
+    + Zero
+    + One
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+{
+}
+
+impl<T> Scalar for T where
+    T: Copy
+        + Zero
+        + One
+        + PartialOrd
+        + Add<Output = T>
+        + Sub<Output = T>
+        + Mul<Output = T>
+        + Div<Output = T>
+{
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Body<T, const N: usize> {
+    pub position: VecN<T, N>,
+    pub velocity: VecN<T, N>,
+}
+
+impl<T, const N: usize> Body<T, N>
+where
+    T: Scalar,
+{
+    pub fn new<V: Into<VecN<T, N>>>(position: V, velocity: V) -> Self {
+        Self {
+            position: position.into(),
+            velocity: velocity.into(),
+        }
+    }
+
+    // Moves along a derivative, which stores velocity in position and
+    // acceleration in velocity
+    fn offset(self, d: Self, dt: T) -> Self {
+        Self {
+            position: self.position + d.position * dt,
+            velocity: self.velocity + d.velocity * dt,
+        }
+    }
+}
+
+// The acceleration callback gets the whole system and the index of the body
+// to evaluate, so forces between bodies (springs, gravity) can be expressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    // Cheap, first order, but symplectic so orbits don't spiral out
+    SemiImplicitEuler,
+    // Second order and symplectic, the usual choice for games
+    VelocityVerlet,
+    // Fourth order, most accurate per step but drifts over long runs
+    Rk4,
+}
+
+impl Integrator {
+    pub fn step<T, F, const N: usize>(&self, bodies: &mut [Body<T, N>], dt: T, accel: F)
+    where
+        T: Scalar,
+        F: Fn(&[Body<T, N>], usize) -> VecN<T, N>,
+    {
+        match self {
+            Integrator::SemiImplicitEuler => {
+                let a = accelerations(bodies, &accel);
+                for (body, a) in bodies.iter_mut().zip(a) {
+                    body.velocity = body.velocity + a * dt;
+                    body.position = body.position + body.velocity * dt;
+                }
+            }
+            Integrator::VelocityVerlet => {
+                let half = T::one() / (T::one() + T::one());
+                let a0 = accelerations(bodies, &accel);
+                for (body, a) in bodies.iter_mut().zip(&a0) {
+                    body.position = body.position + body.velocity * dt + *a * (half * dt * dt);
+                }
+                let a1 = accelerations(bodies, &accel);
+                for (i, body) in bodies.iter_mut().enumerate() {
+                    body.velocity = body.velocity + (a0[i] + a1[i]) * (half * dt);
+                }
+            }
+            Integrator::Rk4 => {
+                let two = T::one() + T::one();
+                let six = two + two + two;
+                let half = dt / two;
+
+                let start = bodies.to_vec();
+                let k1 = derivatives(&start, &accel);
+                let k2 = derivatives(&offset_all(&start, &k1, half), &accel);
+                let k3 = derivatives(&offset_all(&start, &k2, half), &accel);
+                let k4 = derivatives(&offset_all(&start, &k3, dt), &accel);
+
+                for (i, body) in bodies.iter_mut().enumerate() {
+                    let d = Body {
+                        position: (k1[i].position
+                            + (k2[i].position + k3[i].position) * two
+                            + k4[i].position)
+                            / six,
+                        velocity: (k1[i].velocity
+                            + (k2[i].velocity + k3[i].velocity) * two
+                            + k4[i].velocity)
+                            / six,
+                    };
+                    *body = start[i].offset(d, dt);
+                }
+            }
+        }
+    }
+}
+
+fn accelerations<T, F, const N: usize>(bodies: &[Body<T, N>], accel: &F) -> Vec<VecN<T, N>>
+where
+    F: Fn(&[Body<T, N>], usize) -> VecN<T, N>,
+{
+    (0..bodies.len()).map(|i| accel(bodies, i)).collect()
+}
+
+fn derivatives<T, F, const N: usize>(bodies: &[Body<T, N>], accel: &F) -> Vec<Body<T, N>>
+where
+    T: Scalar,
+    F: Fn(&[Body<T, N>], usize) -> VecN<T, N>,
+{
+    (0..bodies.len())
+        .map(|i| Body {
+            position: bodies[i].velocity,
+            velocity: accel(bodies, i),
+        })
+        .collect()
+}
+
+fn offset_all<T, const N: usize>(bodies: &[Body<T, N>], d: &[Body<T, N>], dt: T) -> Vec<Body<T, N>>
+where
+    T: Scalar,
+{
+    bodies
+        .iter()
+        .zip(d)
+        .map(|(b, d)| b.offset(*d, dt))
+        .collect()
+}
+
+// Splits frame times into constant steps, leftover time carries over to the
+// next frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Accumulator<T> {
+    pub dt: T,
+    // Steps allowed per frame before the remaining time is dropped
+    pub max_steps: usize,
+    remainder: T,
+}
+
+impl<T> Accumulator<T>
+where
+    T: Scalar,
+{
+    pub fn new(dt: T) -> Self {
+        Self {
+            dt,
+            max_steps: 8,
+            remainder: T::zero(),
+        }
+    }
+
+    pub fn set_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    // Adds the frame's time and takes out the steps it covers. Returns how
+    // many to run.
+    pub fn steps(&mut self, frame_time: T) -> usize {
+        self.remainder = self.remainder + frame_time;
+        let mut steps = 0;
+        while self.remainder >= self.dt && self.dt > T::zero() {
+            if steps == self.max_steps {
+                self.remainder = T::zero();
+                break;
+            }
+            self.remainder = self.remainder - self.dt;
+            steps += 1;
+        }
+        steps
+    }
+
+    // How far between the last step and the next one we are, for
+    // interpolating rendered positions
+    pub fn alpha(&self) -> T {
+        if self.dt > T::zero() {
+            self.remainder / self.dt
+        } else {
+            T::zero()
+        }
+    }
+}
+
+// Advances a simulation in constant steps no matter how long frames take
+pub struct FixedStep<T> {
+    pub integrator: Integrator,
+    pub accumulator: Accumulator<T>,
+}
+
+impl<T> FixedStep<T>
+where
+    T: Scalar,
+{
+    pub fn new(integrator: Integrator, dt: T) -> Self {
+        Self {
+            integrator,
+            accumulator: Accumulator::new(dt),
+        }
+    }
+
+    pub fn set_max_steps(mut self, max_steps: usize) -> Self {
+        self.accumulator.max_steps = max_steps;
+        self
+    }
+
+    // Returns the number of steps taken
+    pub fn advance<F, const N: usize>(
+        &mut self,
+        frame_time: T,
+        bodies: &mut [Body<T, N>],
+        accel: F,
+    ) -> usize
+    where
+        F: Fn(&[Body<T, N>], usize) -> VecN<T, N>,
+    {
+        let steps = self.accumulator.steps(frame_time);
+        for _ in 0..steps {
+            self.integrator.step(bodies, self.accumulator.dt, &accel);
+        }
+        steps
+    }
+
+    pub fn alpha(&self) -> T {
+        self.accumulator.alpha()
+    }
+}
+
+#[cfg(test)]
+fn spring_energy(bodies: &[Body<f64, 1>], k: f64) -> f64 {
+    let b = bodies[0];
+    0.5 * b.velocity.dot(b.velocity) + 0.5 * k * b.position.dot(b.position)
+}
+
+#[cfg(test)]
+fn orbit_energy(bodies: &[Body<f64, 2>], gm: f64) -> f64 {
+    let b = bodies[0];
+    0.5 * b.velocity.dot(b.velocity) - gm / b.position.mag()
+}
+
+#[test]
+fn test_spring_energy() {
+    let k = 4.0;
+    let spring = |b: &[Body<f64, 1>], i: usize| b[i].position * -k;
+    let start = [Body::new([1.0], [0.0])];
+    let e0 = spring_energy(&start, k);
+
+    for integrator in [
+        Integrator::SemiImplicitEuler,
+        Integrator::VelocityVerlet,
+        Integrator::Rk4,
+    ] {
+        let mut bodies = start;
+        for _ in 0..10_000 {
+            integrator.step(&mut bodies, 0.01, spring);
+        }
+        let drift = (spring_energy(&bodies, k) - e0).abs() / e0;
+        assert!(drift < 0.02, "{:?} drifted {}", integrator, drift);
+    }
+}
+
+#[test]
+fn test_orbit_energy() {
+    let gm = 1.0;
+    let gravity = |b: &[Body<f64, 2>], i: usize| {
+        let r = b[i].position.mag();
+        b[i].position * (-gm / (r * r * r))
+    };
+    // Circular orbit at radius 1
+    let start = [Body::new([1.0, 0.0], [0.0, 1.0])];
+    let e0 = orbit_energy(&start, gm);
+
+    for integrator in [Integrator::VelocityVerlet, Integrator::Rk4] {
+        let mut bodies = start;
+        // About 16 orbits
+        for _ in 0..10_000 {
+            integrator.step(&mut bodies, 0.01, gravity);
+        }
+        let drift = (orbit_energy(&bodies, gm) - e0).abs() / e0.abs();
+        assert!(drift < 1e-3, "{:?} drifted {}", integrator, drift);
+        assert!((bodies[0].position.mag() - 1.0).abs() < 1e-2);
+    }
+}
+
+#[test]
+fn test_fixed_step() {
+    let still = |_: &[Body<f32, 2>], _: usize| Vec2f::zero();
+    let mut bodies = [Body::new([0.0, 0.0], [1.0, 0.0])];
+    let mut stepper = FixedStep::new(Integrator::SemiImplicitEuler, 0.25).set_max_steps(4);
+
+    assert!(stepper.advance(0.6, &mut bodies, still) == 2);
+    assert!((stepper.alpha() - 0.4).abs() < 1e-5);
+    assert!((bodies[0].position[X] - 0.5).abs() < 1e-5);
+
+    // A long hitch is capped and the backlog dropped
+    assert!(stepper.advance(10.0, &mut bodies, still) == 4);
+    assert!(stepper.alpha() == 0.0);
+}
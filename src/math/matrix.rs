@@ -1,14 +1,17 @@
+use crate::math::angle::Radians;
 use crate::math::vector::*;
 use crate::math::*;
 use std::fmt::Display;
 use std::ops::*;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Mat2<T> {
     pub x: VecN<T, 3>,
     pub y: VecN<T, 3>,
     pub z: VecN<T, 3>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Mat3<T> {
     pub x: VecN<T, 4>,
     pub y: VecN<T, 4>,
@@ -162,6 +165,219 @@ where
     }
 }
 
+// Columns are the x, y and z basis vectors, rotations are counter clockwise
+// looking down the axis towards the origin
+
+impl<T> Mat2<T>
+where
+    T: One + Zero + Neg<Output = T> + Copy,
+    Radians<T>: Sin<T> + Cos<T>,
+{
+    pub fn rotation<A: Into<Radians<T>>>(angle: A) -> Self {
+        let angle = angle.into();
+        let (s, c) = (angle.sin(), angle.cos());
+        Self {
+            x: [c, s, T::zero()].into(),
+            y: [-s, c, T::zero()].into(),
+            z: [T::zero(), T::zero(), T::one()].into(),
+        }
+    }
+}
+
+impl<T> Mat2<T>
+where
+    T: One + Zero + Copy,
+{
+    pub fn translation(v: VecN<T, 2>) -> Self {
+        let mut m = Self::identity();
+        m.z = [v[X], v[Y], T::one()].into();
+        m
+    }
+
+    pub fn scale(v: VecN<T, 2>) -> Self {
+        let (o, l) = (T::zero(), T::one());
+        Self {
+            x: [v[X], o, o].into(),
+            y: [o, v[Y], o].into(),
+            z: [o, o, l].into(),
+        }
+    }
+}
+
+impl<T> Mat3<T>
+where
+    T: One + Zero + Neg<Output = T> + Copy,
+    T: Add<Output = T> + Sub<Output = T> + Mul<Output = T>,
+    Radians<T>: Sin<T> + Cos<T>,
+{
+    pub fn rotation_x<A: Into<Radians<T>>>(angle: A) -> Self {
+        let angle = angle.into();
+        let (s, c) = (angle.sin(), angle.cos());
+        let (o, l) = (T::zero(), T::one());
+        Self {
+            x: [l, o, o, o].into(),
+            y: [o, c, s, o].into(),
+            z: [o, -s, c, o].into(),
+            w: [o, o, o, l].into(),
+        }
+    }
+
+    pub fn rotation_y<A: Into<Radians<T>>>(angle: A) -> Self {
+        let angle = angle.into();
+        let (s, c) = (angle.sin(), angle.cos());
+        let (o, l) = (T::zero(), T::one());
+        Self {
+            x: [c, o, -s, o].into(),
+            y: [o, l, o, o].into(),
+            z: [s, o, c, o].into(),
+            w: [o, o, o, l].into(),
+        }
+    }
+
+    pub fn rotation_z<A: Into<Radians<T>>>(angle: A) -> Self {
+        let angle = angle.into();
+        let (s, c) = (angle.sin(), angle.cos());
+        let (o, l) = (T::zero(), T::one());
+        Self {
+            x: [c, s, o, o].into(),
+            y: [-s, c, o, o].into(),
+            z: [o, o, l, o].into(),
+            w: [o, o, o, l].into(),
+        }
+    }
+
+    // axis must be normalized
+    pub fn rotation<A: Into<Radians<T>>>(axis: VecN<T, 3>, angle: A) -> Self {
+        let angle = angle.into();
+        let (s, c) = (angle.sin(), angle.cos());
+        let t = T::one() - c;
+        let (x, y, z) = (axis[X], axis[Y], axis[Z]);
+        let (o, l) = (T::zero(), T::one());
+        Self {
+            x: [t * x * x + c, t * x * y + s * z, t * x * z - s * y, o].into(),
+            y: [t * x * y - s * z, t * y * y + c, t * y * z + s * x, o].into(),
+            z: [t * x * z + s * y, t * y * z - s * x, t * z * z + c, o].into(),
+            w: [o, o, o, l].into(),
+        }
+    }
+}
+
+impl<T> Mat3<T>
+where
+    T: One + Zero + Neg<Output = T> + Copy,
+    T: Add<Output = T> + Sub<Output = T> + Mul<Output = T> + Div<Output = T>,
+{
+    pub fn translation(v: VecN<T, 3>) -> Self {
+        let mut m = Self::identity();
+        m.w = [v[X], v[Y], v[Z], T::one()].into();
+        m
+    }
+
+    pub fn scale(v: VecN<T, 3>) -> Self {
+        let (o, l) = (T::zero(), T::one());
+        Self {
+            x: [v[X], o, o, o].into(),
+            y: [o, v[Y], o, o].into(),
+            z: [o, o, v[Z], o].into(),
+            w: [o, o, o, l].into(),
+        }
+    }
+
+    // Maps the box to clip space from -1 to 1, GL style with y up and the
+    // camera looking down -z
+    pub fn orthographic(left: T, right: T, bottom: T, top: T, near: T, far: T) -> Self {
+        let (o, l) = (T::zero(), T::one());
+        let two = l + l;
+        Self {
+            x: [two / (right - left), o, o, o].into(),
+            y: [o, two / (top - bottom), o, o].into(),
+            z: [o, o, -two / (far - near), o].into(),
+            w: [
+                -(right + left) / (right - left),
+                -(top + bottom) / (top - bottom),
+                -(far + near) / (far - near),
+                l,
+            ]
+            .into(),
+        }
+    }
+}
+
+impl<T> Mat3<T>
+where
+    T: One + Zero + Neg<Output = T> + Copy,
+    T: Add<Output = T> + Sub<Output = T> + Mul<Output = T> + Div<Output = T>,
+    Radians<T>: Sin<T> + Cos<T>,
+{
+    // fov is the vertical field of view, aspect is width over height
+    pub fn perspective<A: Into<Radians<T>>>(fov: A, aspect: T, near: T, far: T) -> Self {
+        let half = fov.into();
+        let half = Radians(half.0 / (T::one() + T::one()));
+        let f = half.cos() / half.sin();
+        let (o, l) = (T::zero(), T::one());
+        let two = l + l;
+        Self {
+            x: [f / aspect, o, o, o].into(),
+            y: [o, f, o, o].into(),
+            z: [o, o, (far + near) / (near - far), -l].into(),
+            w: [o, o, two * far * near / (near - far), o].into(),
+        }
+    }
+}
+
+impl<T> Mul<VecN<T, 3>> for Mat2<T>
+where
+    T: Add<Output = T> + Mul<Output = T> + Copy,
+{
+    type Output = VecN<T, 3>;
+
+    fn mul(self, v: VecN<T, 3>) -> Self::Output {
+        self.x * v[0] + self.y * v[1] + self.z * v[2]
+    }
+}
+
+impl<T> Mul for Mat2<T>
+where
+    T: Add<Output = T> + Mul<Output = T> + Copy,
+{
+    type Output = Self;
+
+    fn mul(self, b: Self) -> Self::Output {
+        Self {
+            x: self * b.x,
+            y: self * b.y,
+            z: self * b.z,
+        }
+    }
+}
+
+impl<T> Mul<VecN<T, 4>> for Mat3<T>
+where
+    T: Add<Output = T> + Mul<Output = T> + Copy,
+{
+    type Output = VecN<T, 4>;
+
+    fn mul(self, v: VecN<T, 4>) -> Self::Output {
+        self.x * v[0] + self.y * v[1] + self.z * v[2] + self.w * v[3]
+    }
+}
+
+impl<T> Mul for Mat3<T>
+where
+    T: Add<Output = T> + Mul<Output = T> + Copy,
+{
+    type Output = Self;
+
+    fn mul(self, b: Self) -> Self::Output {
+        Self {
+            x: self * b.x,
+            y: self * b.y,
+            z: self * b.z,
+            w: self * b.w,
+        }
+    }
+}
+
 impl<T> std::fmt::Display for Mat2<T>
 where
     T: Display,
@@ -213,3 +429,53 @@ fn test_mat_fmt() {
     let _a = Mat2f::identity();
     println!("{}", _a);
 }
+
+#[test]
+fn test_mat_rotation() {
+    use crate::math::angle::Degrees;
+
+    let near = |a: f32, b: f32| (a - b).abs() < 1e-5;
+
+    let v = Mat2f::rotation(Degrees(90.0)) * Vec3f::from([1.0, 0.0, 1.0]);
+    assert!(near(v[X], 0.0) && near(v[Y], 1.0) && near(v[Z], 1.0));
+
+    let v = Mat3f::rotation_y(Degrees(90.0)) * Vec4f::from([0.0, 0.0, 1.0, 1.0]);
+    assert!(near(v[X], 1.0) && near(v[Z], 0.0));
+
+    let a = Mat3f::rotation([0.0, 0.0, 1.0].into(), Radians(0.5));
+    let b = Mat3f::rotation_z(Radians(0.5));
+    for i in 0..4 {
+        for j in 0..4 {
+            assert!(near(a[i][j], b[i][j]));
+        }
+    }
+
+    let twice = Mat3f::rotation_x(Degrees(45.0)) * Mat3f::rotation_x(Degrees(45.0));
+    let v = twice * Vec4f::from([0.0, 1.0, 0.0, 1.0]);
+    assert!(near(v[Y], 0.0) && near(v[Z], 1.0));
+}
+
+#[test]
+fn test_mat_projection() {
+    use crate::math::angle::Degrees;
+
+    let near = |a: f32, b: f32| (a - b).abs() < 1e-5;
+
+    let m = Mat3f::translation([1.0, 2.0, 3.0].into()) * Mat3f::scale([2.0, 2.0, 2.0].into());
+    let v = m * Vec4f::from([1.0, 1.0, 1.0, 1.0]);
+    assert!(v == [3.0, 4.0, 5.0, 1.0]);
+
+    // The near and far planes land on -1 and 1 after the divide
+    let p = Mat3f::perspective(Degrees(90.0), 2.0, 1.0, 10.0);
+    let v = p * Vec4f::from([1.0, 1.0, -1.0, 1.0]);
+    assert!(near(v[Z] / v[W], -1.0) && near(v[X] / v[W], 0.5) && near(v[Y] / v[W], 1.0));
+    let v = p * Vec4f::from([0.0, 0.0, -10.0, 1.0]);
+    assert!(near(v[Z] / v[W], 1.0));
+
+    let m = Mat2f::translation([1.0, 2.0].into()) * Mat2f::scale([3.0, 4.0].into());
+    assert!(m * Vec3f::from([1.0, 1.0, 1.0]) == [4.0, 6.0, 1.0]);
+
+    let o = Mat3f::orthographic(0.0, 100.0, 0.0, 50.0, -1.0, 1.0);
+    let v = o * Vec4f::from([100.0, 25.0, 0.0, 1.0]);
+    assert!(near(v[X], 1.0) && near(v[Y], 0.0) && near(v[Z], 0.0));
+}
@@ -1,8 +1,15 @@
 use std::ops::{Index, Neg};
 
+pub mod accessibility;
+pub mod angle;
 pub mod color;
+pub mod integrate;
 pub mod matrix;
+pub mod palette;
+pub mod pixel;
+pub mod poisson;
 pub mod random;
+pub mod sdf;
 pub mod vector;
 
 const CHAR_DIM: [char; 4] = ['x', 'y', 'z', 'w'];
@@ -0,0 +1,542 @@
+use crate::error::{Error, Result};
+use crate::math::color::*;
+use crate::math::random::Random;
+use std::fmt::Write as _;
+use std::path::Path;
+
+fn parse_error(line: usize, message: &str) -> Error {
+    Error::Parse {
+        line: line + 1,
+        message: message.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    colors: Vec<ColorRGB>,
+    lab: Vec<Oklab>,
+}
+
+impl Palette {
+    pub fn new(colors: Vec<ColorRGB>) -> Self {
+        let lab = colors.iter().map(|c| Oklab::from(*c)).collect();
+        Self { colors, lab }
+    }
+
+    pub fn colors(&self) -> &[ColorRGB] {
+        &self.colors
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    // Index of the perceptually closest color, 0 for an empty palette
+    pub fn nearest(&self, c: ColorRGB) -> usize {
+        self.nearest_lab(c.into())
+    }
+
+    pub fn nearest_color(&self, c: ColorRGB) -> ColorRGB {
+        self.colors.get(self.nearest(c)).copied().unwrap_or(c)
+    }
+
+    fn nearest_lab(&self, lab: Oklab) -> usize {
+        let mut best = 0;
+        let mut best_dist = f32::MAX;
+        for (i, p) in self.lab.iter().enumerate() {
+            let d = p.distance_sq(lab);
+            if d < best_dist {
+                best = i;
+                best_dist = d;
+            }
+        }
+        best
+    }
+
+    // Fully transparent pixels are ignored by the extractors
+
+    // Recursively splits the box of colors with the widest channel at its
+    // median until there are count boxes, each becomes its average color
+    pub fn median_cut(pixels: &[ColorRGBA], count: usize) -> Self {
+        let colors: Vec<[u8; 3]> = pixels
+            .iter()
+            .filter(|p| p.a > 0)
+            .map(|p| [p.r, p.g, p.b])
+            .collect();
+
+        if colors.is_empty() || count == 0 {
+            return Self::new(Vec::new());
+        }
+
+        let mut boxes = vec![colors];
+        while boxes.len() < count {
+            let widest = boxes
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| b.len() > 1)
+                .map(|(i, b)| (i, channel_range(b)))
+                .max_by_key(|(_, (_, range))| *range);
+
+            let (index, (channel, range)) = match widest {
+                Some(w) => w,
+                None => break,
+            };
+            if range == 0 {
+                break;
+            }
+
+            let mut b = boxes.swap_remove(index);
+            b.sort_unstable_by_key(|c| c[channel]);
+            let upper = b.split_off(b.len() / 2);
+            boxes.push(b);
+            boxes.push(upper);
+        }
+
+        Self::new(
+            boxes
+                .iter()
+                .map(|b| {
+                    let mut sum = [0u64; 3];
+                    for c in b {
+                        for i in 0..3 {
+                            sum[i] += c[i] as u64;
+                        }
+                    }
+                    let n = b.len() as u64;
+                    ColorRGB::from_rgb(
+                        ((sum[0] + n / 2) / n) as u8,
+                        ((sum[1] + n / 2) / n) as u8,
+                        ((sum[2] + n / 2) / n) as u8,
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    // Clusters colors in Oklab, seeded with k-means++
+    pub fn kmeans(pixels: &[ColorRGBA], count: usize, iterations: usize, rng: &mut Random) -> Self {
+        let points: Vec<Oklab> = pixels
+            .iter()
+            .filter(|p| p.a > 0)
+            .map(|p| Oklab::from(*p))
+            .collect();
+
+        if points.is_empty() || count == 0 {
+            return Self::new(Vec::new());
+        }
+
+        let mut centers = vec![points[rng.range_usize(0, points.len())]];
+        let mut dists: Vec<f32> = points.iter().map(|p| p.distance_sq(centers[0])).collect();
+        while centers.len() < count {
+            let total: f32 = dists.iter().sum();
+            if total <= 0.0 {
+                break;
+            }
+            let mut target = rng.next_f32() * total;
+            let mut chosen = points.len() - 1;
+            for (i, d) in dists.iter().enumerate() {
+                if target < *d {
+                    chosen = i;
+                    break;
+                }
+                target -= d;
+            }
+            let center = points[chosen];
+            centers.push(center);
+            for (d, p) in dists.iter_mut().zip(&points) {
+                *d = d.min(p.distance_sq(center));
+            }
+        }
+
+        let mut assignment = vec![0; points.len()];
+        for _ in 0..iterations {
+            let palette = Palette {
+                colors: Vec::new(),
+                lab: centers.clone(),
+            };
+            let mut changed = false;
+            for (a, p) in assignment.iter_mut().zip(&points) {
+                let nearest = palette.nearest_lab(*p);
+                changed |= *a != nearest;
+                *a = nearest;
+            }
+
+            let mut sums = vec![(Oklab::default(), 0usize); centers.len()];
+            for (a, p) in assignment.iter().zip(&points) {
+                let (sum, n) = &mut sums[*a];
+                sum.l += p.l;
+                sum.a += p.a;
+                sum.b += p.b;
+                *n += 1;
+            }
+            for (center, (sum, n)) in centers.iter_mut().zip(sums) {
+                if n > 0 {
+                    let n = n as f32;
+                    *center = Oklab {
+                        l: sum.l / n,
+                        a: sum.a / n,
+                        b: sum.b / n,
+                    };
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        Self::new(centers.into_iter().map(ColorRGB::from).collect())
+    }
+
+    // GIMP palette
+    pub fn from_gpl(text: &str) -> Result<Self> {
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, header)) if header.trim() == "GIMP Palette" => {}
+            _ => return Err(parse_error(0, "missing 'GIMP Palette' header")),
+        }
+
+        let mut colors = Vec::new();
+        for (n, line) in lines {
+            let line = line.trim();
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("Name:")
+                || line.starts_with("Columns:")
+            {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let mut channel = || -> Result<u8> {
+                parts
+                    .next()
+                    .and_then(|s| s.parse::<u8>().ok())
+                    .ok_or_else(|| parse_error(n, "expected three channels from 0 to 255"))
+            };
+            colors.push(ColorRGB::from_rgb(channel()?, channel()?, channel()?));
+        }
+        Ok(Self::new(colors))
+    }
+
+    pub fn to_gpl(&self, name: &str) -> String {
+        let mut out = format!("GIMP Palette\nName: {}\nColumns: 8\n#\n", name);
+        for c in &self.colors {
+            writeln!(
+                out,
+                "{:>3} {:>3} {:>3}\t#{:02x}{:02x}{:02x}",
+                c.r, c.g, c.b, c.r, c.g, c.b
+            )
+            .expect("Write failed");
+        }
+        out
+    }
+
+    // One RRGGBB per line, as exported by lospec
+    pub fn from_hex(text: &str) -> Result<Self> {
+        let mut colors = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim().trim_start_matches('#');
+            if line.is_empty() {
+                continue;
+            }
+            // from_str_radix would also take a leading +
+            if line.len() != 6 || !line.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(parse_error(n, "expected six hex digits"));
+            }
+            let rgb = u32::from_str_radix(line, 16)
+                .map_err(|_| parse_error(n, "expected six hex digits"))?;
+            colors.push(ColorRGB::from_u32(rgb << 8));
+        }
+        Ok(Self::new(colors))
+    }
+
+    pub fn to_hex(&self) -> String {
+        let mut out = String::new();
+        for c in &self.colors {
+            writeln!(out, "{:02x}{:02x}{:02x}", c.r, c.g, c.b).expect("Write failed");
+        }
+        out
+    }
+
+    // JASC-PAL, used by Paint Shop Pro and Aseprite
+    pub fn from_pal(text: &str) -> Result<Self> {
+        let lines: Vec<&str> = text.lines().map(str::trim).collect();
+        if lines.first() != Some(&"JASC-PAL") {
+            return Err(parse_error(0, "missing 'JASC-PAL' header"));
+        }
+        if lines.len() < 3 {
+            return Err(parse_error(lines.len(), "missing color count"));
+        }
+        let count: usize = lines[2]
+            .parse()
+            .map_err(|_| parse_error(2, "invalid color count"))?;
+
+        let mut colors = Vec::with_capacity(count);
+        for (n, line) in lines.iter().enumerate().skip(3).take(count) {
+            let c: Vec<u8> = line
+                .split_whitespace()
+                .map(|s| s.parse::<u8>())
+                .collect::<std::result::Result<_, _>>()
+                .map_err(|_| parse_error(n, "expected three channels from 0 to 255"))?;
+            if c.len() != 3 {
+                return Err(parse_error(n, "expected three channels from 0 to 255"));
+            }
+            colors.push(ColorRGB::from_rgb(c[0], c[1], c[2]));
+        }
+        if colors.len() != count {
+            return Err(parse_error(
+                lines.len(),
+                "fewer colors than the header says",
+            ));
+        }
+        Ok(Self::new(colors))
+    }
+
+    pub fn to_pal(&self) -> String {
+        let mut out = format!("JASC-PAL\n0100\n{}\n", self.colors.len());
+        for c in &self.colors {
+            writeln!(out, "{} {} {}", c.r, c.g, c.b).expect("Write failed");
+        }
+        out
+    }
+
+    // Format is picked from the extension, gpl, hex or pal
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        match extension(path).as_str() {
+            "gpl" => Self::from_gpl(&text),
+            "hex" => Self::from_hex(&text),
+            "pal" => Self::from_pal(&text),
+            ext => Err(Error::Format(ext.to_string())),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let text = match extension(path).as_str() {
+            "gpl" => {
+                let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+                self.to_gpl(name)
+            }
+            "hex" => self.to_hex(),
+            "pal" => self.to_pal(),
+            ext => return Err(Error::Format(ext.to_string())),
+        };
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase()
+}
+
+fn channel_range(colors: &[[u8; 3]]) -> (usize, u8) {
+    let mut min = [255u8; 3];
+    let mut max = [0u8; 3];
+    for c in colors {
+        for i in 0..3 {
+            min[i] = min[i].min(c[i]);
+            max[i] = max[i].max(c[i]);
+        }
+    }
+    (0..3)
+        .map(|i| (i, max[i] - min[i]))
+        .max_by_key(|(_, r)| *r)
+        .unwrap_or((0, 0))
+}
+
+// Maps every pixel to its nearest palette color, alpha is kept
+pub fn remap(pixels: &[ColorRGBA], palette: &Palette) -> Vec<ColorRGBA> {
+    pixels
+        .iter()
+        .map(|p| with_alpha(palette.nearest_color((*p).into()), p.a))
+        .collect()
+}
+
+// Bayer matrix dithering, size is 2, 4 or 8. spread is how far in 0-255
+// units the threshold pushes a channel, usually 255 / palette size or so.
+pub fn dither_ordered(
+    pixels: &[ColorRGBA],
+    width: usize,
+    palette: &Palette,
+    size: usize,
+    spread: f32,
+) -> Vec<ColorRGBA> {
+    let size = match size {
+        0..=2 => 2,
+        3..=4 => 4,
+        _ => 8,
+    };
+    let cells = (size * size) as f32;
+
+    pixels
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let (x, y) = (i % width.max(1), i / width.max(1));
+            let threshold = (bayer(x % size, y % size, size) as f32 + 0.5) / cells - 0.5;
+            let offset = threshold * spread;
+            let c = ColorRGB::from_rgb(
+                (p.r as f32 + offset).clamp(0.0, 255.0) as u8,
+                (p.g as f32 + offset).clamp(0.0, 255.0) as u8,
+                (p.b as f32 + offset).clamp(0.0, 255.0) as u8,
+            );
+            with_alpha(palette.nearest_color(c), p.a)
+        })
+        .collect()
+}
+
+// Error diffusion, pushes the quantization error onto the unvisited
+// neighbours with weights 7/16, 3/16, 5/16, 1/16
+pub fn dither_floyd_steinberg(
+    pixels: &[ColorRGBA],
+    width: usize,
+    palette: &Palette,
+) -> Vec<ColorRGBA> {
+    let width = width.max(1);
+    let height = pixels.len().div_ceil(width);
+    let mut work: Vec<[f32; 3]> = pixels
+        .iter()
+        .map(|p| [p.r as f32, p.g as f32, p.b as f32])
+        .collect();
+    let mut out = Vec::with_capacity(pixels.len());
+
+    for i in 0..pixels.len() {
+        let (x, y) = (i % width, i / width);
+        let w = work[i];
+        let c = ColorRGB::from_rgb(
+            w[0].clamp(0.0, 255.0).round() as u8,
+            w[1].clamp(0.0, 255.0).round() as u8,
+            w[2].clamp(0.0, 255.0).round() as u8,
+        );
+        let q = palette.nearest_color(c);
+        out.push(with_alpha(q, pixels[i].a));
+
+        let err = [w[0] - q.r as f32, w[1] - q.g as f32, w[2] - q.b as f32];
+        let mut spread = |dx: isize, dy: usize, weight: f32| {
+            let nx = x as isize + dx;
+            let ny = y + dy;
+            if nx < 0 || nx as usize >= width || ny >= height {
+                return;
+            }
+            if let Some(n) = work.get_mut(ny * width + nx as usize) {
+                for c in 0..3 {
+                    n[c] += err[c] * weight;
+                }
+            }
+        };
+        spread(1, 0, 7.0 / 16.0);
+        spread(-1, 1, 3.0 / 16.0);
+        spread(0, 1, 5.0 / 16.0);
+        spread(1, 1, 1.0 / 16.0);
+    }
+    out
+}
+
+fn with_alpha(c: ColorRGB, a: u8) -> ColorRGBA {
+    ColorRGBA::from_rgba(c.r, c.g, c.b, a)
+}
+
+// Recursive definition of the Bayer index matrix
+fn bayer(x: usize, y: usize, size: usize) -> usize {
+    if size <= 1 {
+        return 0;
+    }
+    let half = size / 2;
+    let quadrant = match (x >= half, y >= half) {
+        (false, false) => 0,
+        (true, true) => 1,
+        (true, false) => 2,
+        (false, true) => 3,
+    };
+    4 * bayer(x % half, y % half, half) + quadrant
+}
+
+#[cfg(test)]
+fn gradient() -> Vec<ColorRGBA> {
+    (0..64u32)
+        .flat_map(|y| {
+            (0..64u32).map(move |x| ColorRGBA::from_rgba((x * 4) as u8, (y * 4) as u8, 128, 255))
+        })
+        .collect()
+}
+
+#[test]
+fn test_palette_extract() {
+    let mut pixels = vec![ColorRGBA::from_rgba(255, 0, 0, 255); 50];
+    pixels.extend(vec![ColorRGBA::from_rgba(0, 0, 255, 255); 50]);
+    pixels.extend(vec![ColorRGBA::from_rgba(0, 255, 0, 0); 50]);
+
+    let p = Palette::median_cut(&pixels, 2);
+    assert!(p.len() == 2);
+    assert!(p.colors().contains(&ColorRGB::from_rgb(255, 0, 0)));
+    assert!(p.colors().contains(&ColorRGB::from_rgb(0, 0, 255)));
+
+    let p = Palette::kmeans(&pixels, 2, 10, &mut Random::from_seed(5));
+    assert!(p.len() == 2);
+    assert!(p.colors().contains(&ColorRGB::from_rgb(255, 0, 0)));
+    assert!(p.colors().contains(&ColorRGB::from_rgb(0, 0, 255)));
+
+    let p = Palette::median_cut(&gradient(), 16);
+    assert!(p.len() == 16);
+}
+
+#[test]
+fn test_palette_nearest_and_dither() {
+    let p = Palette::new(vec![
+        ColorRGB::from_rgb(0, 0, 0),
+        ColorRGB::from_rgb(255, 255, 255),
+    ]);
+    assert!(p.nearest(ColorRGB::from_rgb(30, 30, 30)) == 0);
+    assert!(p.nearest(ColorRGB::from_rgb(200, 210, 220)) == 1);
+
+    // Mid grey dithers to a mix of both, Oklab leans it slightly towards white
+    let grey = vec![ColorRGBA::from_rgba(128, 128, 128, 255); 16 * 16];
+    for out in [
+        dither_ordered(&grey, 16, &p, 4, 255.0),
+        dither_floyd_steinberg(&grey, 16, &p),
+    ] {
+        let white = out.iter().filter(|c| c.r == 255).count();
+        assert!(white > 96 && white < 192, "{}", white);
+        assert!(out.iter().all(|c| c.r == c.g && (c.r == 0 || c.r == 255)));
+    }
+
+    let mut b = [[0; 4]; 4];
+    for (y, row) in b.iter_mut().enumerate() {
+        for (x, v) in row.iter_mut().enumerate() {
+            *v = bayer(x, y, 4);
+        }
+    }
+    assert!(b[0] == [0, 8, 2, 10] && b[1] == [12, 4, 14, 6]);
+}
+
+#[test]
+fn test_palette_formats() {
+    let p = Palette::new(vec![
+        ColorRGB::from_rgb(255, 0, 0),
+        ColorRGB::from_rgb(1, 2, 3),
+    ]);
+    assert!(Palette::from_gpl(&p.to_gpl("test")).unwrap() == p);
+    assert!(Palette::from_hex(&p.to_hex()).unwrap() == p);
+    assert!(Palette::from_pal(&p.to_pal()).unwrap() == p);
+
+    assert!(Palette::from_hex("ff0000\n#00ff00\n").unwrap().len() == 2);
+    assert!(Palette::from_hex("ff00\n").is_err());
+    assert!(Palette::from_hex("+12345\n").is_err());
+    assert!(Palette::from_gpl("GIMP Palette\n255 0\n").is_err());
+    assert!(Palette::from_pal("JASC-PAL\n0100\n2\n0 0 0\n").is_err());
+
+    let path = std::env::temp_dir().join("qengine_palette_test.gpl");
+    p.save(&path).unwrap();
+    assert!(Palette::load(&path).unwrap() == p);
+    std::fs::remove_file(path).unwrap();
+}
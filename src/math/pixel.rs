@@ -0,0 +1,376 @@
+use crate::math::color::*;
+
+// Memory layouts for pixels. Byte formats list channels in memory order,
+// packed 16 bit formats list them from the high bits down and are stored
+// little endian, as are the float formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    Rgba8,
+    Bgra8,
+    Argb8,
+    Rgb565,
+    Rgba4444,
+    Rgba5551,
+    R8,
+    Rg8,
+    Rgba16f,
+    Rgba32f,
+}
+
+impl PixelFormat {
+    pub const ALL: [PixelFormat; 10] = [
+        PixelFormat::Rgba8,
+        PixelFormat::Bgra8,
+        PixelFormat::Argb8,
+        PixelFormat::Rgb565,
+        PixelFormat::Rgba4444,
+        PixelFormat::Rgba5551,
+        PixelFormat::R8,
+        PixelFormat::Rg8,
+        PixelFormat::Rgba16f,
+        PixelFormat::Rgba32f,
+    ];
+
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Rgba8 | PixelFormat::Bgra8 | PixelFormat::Argb8 => 4,
+            PixelFormat::Rgb565 | PixelFormat::Rgba4444 | PixelFormat::Rgba5551 => 2,
+            PixelFormat::R8 => 1,
+            PixelFormat::Rg8 => 2,
+            PixelFormat::Rgba16f => 8,
+            PixelFormat::Rgba32f => 16,
+        }
+    }
+
+    // Bits of precision for r, g, b and a, 0 when the channel isn't stored
+    pub fn channel_bits(&self) -> [u8; 4] {
+        match self {
+            PixelFormat::Rgba8 | PixelFormat::Bgra8 | PixelFormat::Argb8 => [8, 8, 8, 8],
+            PixelFormat::Rgb565 => [5, 6, 5, 0],
+            PixelFormat::Rgba4444 => [4, 4, 4, 4],
+            PixelFormat::Rgba5551 => [5, 5, 5, 1],
+            PixelFormat::R8 => [8, 0, 0, 0],
+            PixelFormat::Rg8 => [8, 8, 0, 0],
+            // Mantissa plus the implicit bit
+            PixelFormat::Rgba16f => [11, 11, 11, 11],
+            PixelFormat::Rgba32f => [24, 24, 24, 24],
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, PixelFormat::Rgba16f | PixelFormat::Rgba32f)
+    }
+
+    // True when every value of self survives a round trip through to
+    pub fn is_lossless(&self, to: PixelFormat) -> bool {
+        if self == &to {
+            return true;
+        }
+        if self.is_float() && !to.is_float() {
+            return false;
+        }
+        let from = self.channel_bits();
+        let dest = to.channel_bits();
+        (0..4).all(|i| from[i] == 0 || dest[i] >= from[i])
+    }
+
+    // Reads one pixel as normalized rgba. Missing color channels read as 0
+    // and missing alpha as 1, the same as OpenGL.
+    pub fn decode(&self, bytes: &[u8]) -> [f32; 4] {
+        let unorm = |v: u32, bits: u32| v as f32 / ((1u32 << bits) - 1) as f32;
+        let byte = |i: usize| bytes[i] as f32 / 255.0;
+        let short = || u16::from_le_bytes([bytes[0], bytes[1]]) as u32;
+
+        match self {
+            PixelFormat::Rgba8 => [byte(0), byte(1), byte(2), byte(3)],
+            PixelFormat::Bgra8 => [byte(2), byte(1), byte(0), byte(3)],
+            PixelFormat::Argb8 => [byte(1), byte(2), byte(3), byte(0)],
+            PixelFormat::Rgb565 => {
+                let v = short();
+                [
+                    unorm(v >> 11, 5),
+                    unorm((v >> 5) & 0x3F, 6),
+                    unorm(v & 0x1F, 5),
+                    1.0,
+                ]
+            }
+            PixelFormat::Rgba4444 => {
+                let v = short();
+                [
+                    unorm(v >> 12, 4),
+                    unorm((v >> 8) & 0xF, 4),
+                    unorm((v >> 4) & 0xF, 4),
+                    unorm(v & 0xF, 4),
+                ]
+            }
+            PixelFormat::Rgba5551 => {
+                let v = short();
+                [
+                    unorm(v >> 11, 5),
+                    unorm((v >> 6) & 0x1F, 5),
+                    unorm((v >> 1) & 0x1F, 5),
+                    unorm(v & 0x1, 1),
+                ]
+            }
+            PixelFormat::R8 => [byte(0), 0.0, 0.0, 1.0],
+            PixelFormat::Rg8 => [byte(0), byte(1), 0.0, 1.0],
+            PixelFormat::Rgba16f => {
+                let mut out = [0.0; 4];
+                for (i, o) in out.iter_mut().enumerate() {
+                    *o = f16_to_f32(u16::from_le_bytes([bytes[i * 2], bytes[i * 2 + 1]]));
+                }
+                out
+            }
+            PixelFormat::Rgba32f => {
+                let mut out = [0.0; 4];
+                for (i, o) in out.iter_mut().enumerate() {
+                    let b = &bytes[i * 4..i * 4 + 4];
+                    *o = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                }
+                out
+            }
+        }
+    }
+
+    // Writes one pixel, integer formats clamp to [0, 1] and round
+    pub fn encode(&self, c: [f32; 4], out: &mut [u8]) {
+        let unorm = |v: f32, bits: u32| {
+            let max = ((1u32 << bits) - 1) as f32;
+            (v.clamp(0.0, 1.0) * max).round() as u32
+        };
+        let byte = |v: f32| unorm(v, 8) as u8;
+        let short = |out: &mut [u8], v: u32| out[..2].copy_from_slice(&(v as u16).to_le_bytes());
+
+        match self {
+            PixelFormat::Rgba8 => {
+                out[..4].copy_from_slice(&[byte(c[0]), byte(c[1]), byte(c[2]), byte(c[3])])
+            }
+            PixelFormat::Bgra8 => {
+                out[..4].copy_from_slice(&[byte(c[2]), byte(c[1]), byte(c[0]), byte(c[3])])
+            }
+            PixelFormat::Argb8 => {
+                out[..4].copy_from_slice(&[byte(c[3]), byte(c[0]), byte(c[1]), byte(c[2])])
+            }
+            PixelFormat::Rgb565 => short(
+                out,
+                unorm(c[0], 5) << 11 | unorm(c[1], 6) << 5 | unorm(c[2], 5),
+            ),
+            PixelFormat::Rgba4444 => short(
+                out,
+                unorm(c[0], 4) << 12 | unorm(c[1], 4) << 8 | unorm(c[2], 4) << 4 | unorm(c[3], 4),
+            ),
+            PixelFormat::Rgba5551 => short(
+                out,
+                unorm(c[0], 5) << 11 | unorm(c[1], 5) << 6 | unorm(c[2], 5) << 1 | unorm(c[3], 1),
+            ),
+            PixelFormat::R8 => out[0] = byte(c[0]),
+            PixelFormat::Rg8 => out[..2].copy_from_slice(&[byte(c[0]), byte(c[1])]),
+            PixelFormat::Rgba16f => {
+                for (i, v) in c.iter().enumerate() {
+                    out[i * 2..i * 2 + 2].copy_from_slice(&f32_to_f16(*v).to_le_bytes());
+                }
+            }
+            PixelFormat::Rgba32f => {
+                for (i, v) in c.iter().enumerate() {
+                    out[i * 4..i * 4 + 4].copy_from_slice(&v.to_le_bytes());
+                }
+            }
+        }
+    }
+
+    pub fn read(&self, bytes: &[u8]) -> ColorRGBA {
+        let c = self.decode(bytes);
+        let byte = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+        ColorRGBA::from_rgba(byte(c[0]), byte(c[1]), byte(c[2]), byte(c[3]))
+    }
+
+    pub fn write(&self, c: ColorRGBA, out: &mut [u8]) {
+        self.encode(
+            [
+                c.r as f32 / 255.0,
+                c.g as f32 / 255.0,
+                c.b as f32 / 255.0,
+                c.a as f32 / 255.0,
+            ],
+            out,
+        )
+    }
+}
+
+pub fn convert_pixel(from: PixelFormat, src: &[u8], to: PixelFormat, dst: &mut [u8]) {
+    if from == to {
+        let n = from.bytes_per_pixel();
+        dst[..n].copy_from_slice(&src[..n]);
+    } else {
+        to.encode(from.decode(src), dst);
+    }
+}
+
+// Converts every whole pixel in src, trailing bytes are ignored
+pub fn convert(src: &[u8], from: PixelFormat, to: PixelFormat) -> Vec<u8> {
+    let (sn, dn) = (from.bytes_per_pixel(), to.bytes_per_pixel());
+    let count = src.len() / sn;
+    if from == to {
+        return src[..count * sn].to_vec();
+    }
+
+    let mut out = vec![0; count * dn];
+    for (s, d) in src.chunks_exact(sn).zip(out.chunks_exact_mut(dn)) {
+        convert_pixel(from, s, to, d);
+    }
+    out
+}
+
+pub fn to_rgba(src: &[u8], from: PixelFormat) -> Vec<ColorRGBA> {
+    src.chunks_exact(from.bytes_per_pixel())
+        .map(|p| from.read(p))
+        .collect()
+}
+
+pub fn from_rgba(pixels: &[ColorRGBA], to: PixelFormat) -> Vec<u8> {
+    let n = to.bytes_per_pixel();
+    let mut out = vec![0; pixels.len() * n];
+    for (c, d) in pixels.iter().zip(out.chunks_exact_mut(n)) {
+        to.write(*c, d);
+    }
+    out
+}
+
+pub fn f32_to_f16(v: f32) -> u16 {
+    let bits = v.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+
+    if exp == 0xFF {
+        // Inf or NaN, keep NaN quiet
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7C00 | nan;
+    }
+
+    let exp = exp - 127 + 15;
+    if exp >= 0x1F {
+        return sign | 0x7C00;
+    }
+    if exp <= 0 {
+        if exp < -10 {
+            return sign;
+        }
+        // Subnormal, shift the implicit bit in and round to nearest even
+        let m = mantissa | 0x80_0000;
+        let shift = (14 - exp) as u32;
+        let half = 1 << (shift - 1);
+        let rest = m & ((1 << shift) - 1);
+        let mut out = m >> shift;
+        if rest > half || (rest == half && out & 1 == 1) {
+            out += 1;
+        }
+        return sign | out as u16;
+    }
+
+    let mut out = ((exp as u32) << 10) | (mantissa >> 13);
+    let rest = mantissa & 0x1FFF;
+    if rest > 0x1000 || (rest == 0x1000 && out & 1 == 1) {
+        // May carry into the exponent, which rounds up to the next power
+        // of two or to infinity as it should
+        out += 1;
+    }
+    sign | out as u16
+}
+
+pub fn f16_to_f32(v: u16) -> f32 {
+    let sign = ((v & 0x8000) as u32) << 16;
+    let exp = ((v >> 10) & 0x1F) as u32;
+    let mantissa = (v & 0x3FF) as u32;
+
+    let bits = match exp {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Subnormal, normalize it
+            let mut e = 127 - 15 + 1;
+            let mut m = mantissa;
+            while m & 0x400 == 0 {
+                m <<= 1;
+                e -= 1;
+            }
+            sign | (e << 23) | ((m & 0x3FF) << 13)
+        }
+        0x1F => sign | 0x7F80_0000 | (mantissa << 13),
+        _ => sign | ((exp + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[test]
+fn test_half_float() {
+    for v in [0.0f32, 1.0, -2.5, 0.5, 65504.0, 6.1035156e-5, 5.9604645e-8] {
+        assert!(f16_to_f32(f32_to_f16(v)) == v, "{}", v);
+    }
+    assert!(f32_to_f16(1e6) == 0x7C00);
+    assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+    assert!(f32_to_f16(1.0) == 0x3C00);
+}
+
+#[test]
+fn test_pixel_layouts() {
+    let c = ColorRGBA::from_rgba(0x11, 0x22, 0x33, 0x44);
+    let mut out = [0u8; 4];
+
+    PixelFormat::Rgba8.write(c, &mut out);
+    assert!(out == [0x11, 0x22, 0x33, 0x44]);
+    PixelFormat::Bgra8.write(c, &mut out);
+    assert!(out == [0x33, 0x22, 0x11, 0x44]);
+    PixelFormat::Argb8.write(c, &mut out);
+    assert!(out == [0x44, 0x11, 0x22, 0x33]);
+
+    let mut out = [0u8; 2];
+    PixelFormat::Rgb565.write(ColorRGBA::from_rgba(255, 0, 255, 255), &mut out);
+    assert!(u16::from_le_bytes(out) == 0xF81F);
+    PixelFormat::Rgba5551.write(ColorRGBA::from_rgba(0, 255, 0, 255), &mut out);
+    assert!(u16::from_le_bytes(out) == 0x07C1);
+    assert!(
+        PixelFormat::Rgba4444.read(&0xF00Fu16.to_le_bytes())
+            == ColorRGBA::from_rgba(255, 0, 0, 255)
+    );
+    assert!(PixelFormat::R8.read(&[7]) == ColorRGBA::from_rgba(7, 0, 0, 255));
+}
+
+#[test]
+fn test_pixel_conversion() {
+    let pixels: Vec<ColorRGBA> = (0..=255u8)
+        .map(|i| ColorRGBA::from_rgba(i, 255 - i, i / 2, i ^ 0x5A))
+        .collect();
+    let rgba = from_rgba(&pixels, PixelFormat::Rgba8);
+
+    // Lossless paths come back exactly
+    for to in [
+        PixelFormat::Bgra8,
+        PixelFormat::Argb8,
+        PixelFormat::Rgba16f,
+        PixelFormat::Rgba32f,
+    ] {
+        assert!(PixelFormat::Rgba8.is_lossless(to));
+        let there = convert(&rgba, PixelFormat::Rgba8, to);
+        assert!(there.len() == pixels.len() * to.bytes_per_pixel());
+        let back = to_rgba(&there, to);
+        assert!(back == pixels, "{:?}", to);
+    }
+
+    // Lossy ones stay within their precision
+    for to in [
+        PixelFormat::Rgb565,
+        PixelFormat::Rgba4444,
+        PixelFormat::Rgba5551,
+    ] {
+        assert!(!PixelFormat::Rgba8.is_lossless(to));
+        let back = to_rgba(&convert(&rgba, PixelFormat::Rgba8, to), to);
+        for (a, b) in pixels.iter().zip(&back) {
+            assert!((a.r as i32 - b.r as i32).abs() <= 9, "{:?}", to);
+        }
+    }
+
+    assert!(PixelFormat::Rgb565.is_lossless(PixelFormat::Rgba8));
+    assert!(!PixelFormat::Rgba32f.is_lossless(PixelFormat::Rgba16f));
+    assert!(PixelFormat::Rgba16f.is_lossless(PixelFormat::Rgba32f));
+    assert!(!PixelFormat::Rgba8.is_lossless(PixelFormat::R8));
+}
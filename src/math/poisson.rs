@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+
+use crate::math::random::Random;
+use crate::math::vector::*;
+
+// Candidates tried around an active sample before it is retired (Bridson's k)
+const ATTEMPTS: usize = 30;
+
+pub trait Region<const N: usize> {
+    fn bounds(&self) -> (VecN<f32, N>, VecN<f32, N>);
+    fn contains(&self, p: VecN<f32, N>) -> bool;
+}
+
+pub struct Rect {
+    pub min: Vec2f,
+    pub max: Vec2f,
+}
+
+pub struct Circle {
+    pub center: Vec2f,
+    pub radius: f32,
+}
+
+// Any simple polygon, convex or not, winding order doesn't matter
+pub struct Polygon {
+    pub points: Vec<Vec2f>,
+}
+
+pub struct Cuboid {
+    pub min: Vec3f,
+    pub max: Vec3f,
+}
+
+pub struct Sphere {
+    pub center: Vec3f,
+    pub radius: f32,
+}
+
+impl Rect {
+    pub fn new<T: Into<Vec2f>>(min: T, max: T) -> Self {
+        Self {
+            min: min.into(),
+            max: max.into(),
+        }
+    }
+}
+
+impl Circle {
+    pub fn new<T: Into<Vec2f>>(center: T, radius: f32) -> Self {
+        Self {
+            center: center.into(),
+            radius,
+        }
+    }
+}
+
+impl Polygon {
+    pub fn new<T: Into<Vec2f>>(points: Vec<T>) -> Self {
+        Self {
+            points: points.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Cuboid {
+    pub fn new<T: Into<Vec3f>>(min: T, max: T) -> Self {
+        Self {
+            min: min.into(),
+            max: max.into(),
+        }
+    }
+}
+
+impl Sphere {
+    pub fn new<T: Into<Vec3f>>(center: T, radius: f32) -> Self {
+        Self {
+            center: center.into(),
+            radius,
+        }
+    }
+}
+
+impl Region<2> for Rect {
+    fn bounds(&self) -> (Vec2f, Vec2f) {
+        (self.min, self.max)
+    }
+
+    fn contains(&self, p: Vec2f) -> bool {
+        p[X] >= self.min[X] && p[X] <= self.max[X] && p[Y] >= self.min[Y] && p[Y] <= self.max[Y]
+    }
+}
+
+impl Region<2> for Circle {
+    fn bounds(&self) -> (Vec2f, Vec2f) {
+        let r = Vec2f::from([self.radius, self.radius]);
+        (self.center - r, self.center + r)
+    }
+
+    fn contains(&self, p: Vec2f) -> bool {
+        (p - self.center).mag() <= self.radius
+    }
+}
+
+impl Region<2> for Polygon {
+    fn bounds(&self) -> (Vec2f, Vec2f) {
+        let mut min = Vec2f::from([f32::MAX, f32::MAX]);
+        let mut max = Vec2f::from([f32::MIN, f32::MIN]);
+        for p in &self.points {
+            min = Vec2f::from([min[X].min(p[X]), min[Y].min(p[Y])]);
+            max = Vec2f::from([max[X].max(p[X]), max[Y].max(p[Y])]);
+        }
+        (min, max)
+    }
+
+    // Even-odd rule
+    fn contains(&self, p: Vec2f) -> bool {
+        let n = self.points.len();
+        let mut inside = false;
+        let mut j = n.wrapping_sub(1);
+        for i in 0..n {
+            let a = self.points[i];
+            let b = self.points[j];
+            if (a[Y] > p[Y]) != (b[Y] > p[Y])
+                && p[X] < (b[X] - a[X]) * (p[Y] - a[Y]) / (b[Y] - a[Y]) + a[X]
+            {
+                inside = !inside;
+            }
+            j = i;
+        }
+        inside
+    }
+}
+
+impl Region<3> for Cuboid {
+    fn bounds(&self) -> (Vec3f, Vec3f) {
+        (self.min, self.max)
+    }
+
+    fn contains(&self, p: Vec3f) -> bool {
+        (0..3).all(|i| p[i] >= self.min[i] && p[i] <= self.max[i])
+    }
+}
+
+impl Region<3> for Sphere {
+    fn bounds(&self) -> (Vec3f, Vec3f) {
+        let r = Vec3f::from([self.radius, self.radius, self.radius]);
+        (self.center - r, self.center + r)
+    }
+
+    fn contains(&self, p: Vec3f) -> bool {
+        (p - self.center).mag() <= self.radius
+    }
+}
+
+// Evenly spaced points with no two closer than radius
+pub fn poisson_disk<R, const N: usize>(
+    region: &R,
+    radius: f32,
+    rng: &mut Random,
+) -> Vec<VecN<f32, N>>
+where
+    R: Region<N>,
+{
+    poisson_disk_variable(region, radius, radius, |_| 1.0, rng)
+}
+
+// density maps a point to [0, 1], 0 spaces samples max_radius apart and 1
+// packs them min_radius apart
+pub fn poisson_disk_variable<R, F, const N: usize>(
+    region: &R,
+    min_radius: f32,
+    max_radius: f32,
+    density: F,
+    rng: &mut Random,
+) -> Vec<VecN<f32, N>>
+where
+    R: Region<N>,
+    F: Fn(VecN<f32, N>) -> f32,
+{
+    if N == 0 || min_radius.is_nan() || min_radius <= 0.0 || max_radius < min_radius {
+        return Vec::new();
+    }
+
+    let radius_at = |p: VecN<f32, N>| {
+        let d = density(p).clamp(0.0, 1.0);
+        max_radius + (min_radius - max_radius) * d
+    };
+
+    let mut grid = Grid::new(region.bounds(), min_radius / (N as f32).sqrt());
+    if grid.is_empty() {
+        return Vec::new();
+    }
+    let reach = (max_radius / grid.cell).ceil() as i64;
+
+    let mut samples: Vec<VecN<f32, N>> = Vec::new();
+    let mut radii: Vec<f32> = Vec::new();
+    let mut active: Vec<usize> = Vec::new();
+
+    let first = (0..ATTEMPTS * ATTEMPTS)
+        .map(|_| {
+            let mut p = VecN::<f32, N>::zero();
+            for i in 0..N {
+                p[i] = rng.range_f32(grid.min[i], grid.max[i]);
+            }
+            p
+        })
+        .find(|p| region.contains(*p));
+
+    match first {
+        Some(p) => {
+            grid.insert(p, 0);
+            samples.push(p);
+            radii.push(radius_at(p));
+            active.push(0);
+        }
+        None => return samples,
+    }
+
+    while !active.is_empty() {
+        let slot = rng.range_usize(0, active.len());
+        let index = active[slot];
+        let origin = samples[index];
+        let r = radii[index];
+
+        let mut found = false;
+        for _ in 0..ATTEMPTS {
+            let dist = r * (1.0 + rng.next_f32());
+            let candidate = origin + unit_vector::<N>(rng) * dist;
+
+            if !grid.in_bounds(candidate) || !region.contains(candidate) {
+                continue;
+            }
+
+            let cr = radius_at(candidate);
+            let clear = grid.neighbours(candidate, reach).all(|other| {
+                let min_dist = cr.max(radii[other]);
+                (samples[other] - candidate).mag() >= min_dist
+            });
+
+            if clear {
+                let new_index = samples.len();
+                grid.insert(candidate, new_index);
+                samples.push(candidate);
+                radii.push(cr);
+                active.push(new_index);
+                found = true;
+                break;
+            }
+        }
+
+        if !found {
+            active.swap_remove(slot);
+        }
+    }
+
+    samples
+}
+
+fn unit_vector<const N: usize>(rng: &mut Random) -> VecN<f32, N> {
+    loop {
+        let mut v = VecN::<f32, N>::zero();
+        for i in 0..N {
+            v[i] = rng.range_f32(-1.0, 1.0);
+        }
+        let mag = v.mag();
+        if mag > 1e-4 && mag <= 1.0 {
+            return v / mag;
+        }
+    }
+}
+
+// Past this many cells the grid only stores the filled ones, so a huge
+// region with a tiny radius costs memory per sample rather than per cell
+const MAX_DENSE_CELLS: usize = 1 << 22;
+
+enum Cells<const N: usize> {
+    Dense(Vec<Option<usize>>),
+    Sparse(HashMap<[i64; N], usize>),
+}
+
+// Background acceleration grid, cells are small enough to hold at most one
+// sample each
+struct Grid<const N: usize> {
+    min: VecN<f32, N>,
+    max: VecN<f32, N>,
+    cell: f32,
+    dims: [usize; N],
+    cells: Cells<N>,
+}
+
+impl<const N: usize> Grid<N> {
+    fn new(bounds: (VecN<f32, N>, VecN<f32, N>), cell: f32) -> Self {
+        let (min, max) = bounds;
+        let mut dims = [0; N];
+        let mut total = 1usize;
+        for i in 0..N {
+            let extent = max[i] - min[i];
+            dims[i] = if extent >= 0.0 {
+                ((extent / cell).ceil() as usize).clamp(1, i64::MAX as usize)
+            } else {
+                0
+            };
+            total = total.saturating_mul(dims[i]);
+        }
+        let cells = if total <= MAX_DENSE_CELLS {
+            Cells::Dense(vec![None; total])
+        } else {
+            Cells::Sparse(HashMap::new())
+        };
+        Self {
+            min,
+            max,
+            cell,
+            dims,
+            cells,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.dims.contains(&0)
+    }
+
+    fn in_bounds(&self, p: VecN<f32, N>) -> bool {
+        (0..N).all(|i| p[i] >= self.min[i] && p[i] <= self.max[i])
+    }
+
+    fn coord(&self, p: VecN<f32, N>) -> [i64; N] {
+        let mut c = [0; N];
+        for (i, c) in c.iter_mut().enumerate() {
+            let v = ((p[i] - self.min[i]) / self.cell) as i64;
+            *c = v.clamp(0, self.dims[i] as i64 - 1);
+        }
+        c
+    }
+
+    fn flat(&self, coord: [i64; N]) -> Option<usize> {
+        let mut index = 0usize;
+        for i in (0..N).rev() {
+            if coord[i] < 0 || coord[i] >= self.dims[i] as i64 {
+                return None;
+            }
+            index = index * self.dims[i] + coord[i] as usize;
+        }
+        Some(index)
+    }
+
+    fn get(&self, coord: [i64; N]) -> Option<usize> {
+        match &self.cells {
+            Cells::Dense(cells) => self.flat(coord).and_then(|i| cells[i]),
+            Cells::Sparse(cells) => cells.get(&coord).copied(),
+        }
+    }
+
+    fn insert(&mut self, p: VecN<f32, N>, sample: usize) {
+        let coord = self.coord(p);
+        // Only dense grids are small enough for flat indices not to overflow
+        let index = match self.cells {
+            Cells::Dense(_) => self.flat(coord),
+            Cells::Sparse(_) => None,
+        };
+        match &mut self.cells {
+            Cells::Dense(cells) => {
+                if let Some(i) = index {
+                    cells[i] = Some(sample);
+                }
+            }
+            Cells::Sparse(cells) => {
+                cells.insert(coord, sample);
+            }
+        }
+    }
+
+    fn neighbours(&self, p: VecN<f32, N>, reach: i64) -> impl Iterator<Item = usize> + '_ {
+        let center = self.coord(p);
+        let side = (2 * reach + 1) as usize;
+        let count = side.pow(N as u32);
+        (0..count).filter_map(move |mut n| {
+            let mut coord = center;
+            for c in coord.iter_mut() {
+                *c += (n % side) as i64 - reach;
+                n /= side;
+            }
+            self.get(coord)
+        })
+    }
+}
+
+#[cfg(test)]
+fn min_spacing<const N: usize>(points: &[VecN<f32, N>]) -> f32 {
+    let mut min = f32::MAX;
+    for i in 0..points.len() {
+        for j in i + 1..points.len() {
+            min = min.min((points[i] - points[j]).mag());
+        }
+    }
+    min
+}
+
+#[test]
+fn test_poisson_rect() {
+    let mut rng = Random::from_seed(1);
+    let rect = Rect::new([0.0, 0.0], [100.0, 50.0]);
+    let points: Vec<Vec2f> = poisson_disk(&rect, 5.0, &mut rng);
+    assert!(points.len() > 50);
+    assert!(min_spacing(&points) >= 5.0 - 1e-4);
+    assert!(points.iter().all(|p| rect.contains(*p)));
+
+    let mut again = Random::from_seed(1);
+    let same: Vec<Vec2f> = poisson_disk(&rect, 5.0, &mut again);
+    assert!(points == same);
+}
+
+#[test]
+fn test_poisson_circle_polygon() {
+    let mut rng = Random::from_seed(2);
+    let circle = Circle::new([0.0, 0.0], 20.0);
+    let points = poisson_disk(&circle, 2.0, &mut rng);
+    assert!(!points.is_empty());
+    assert!(points.iter().all(|p| p.mag() <= 20.0));
+
+    // L shape
+    let poly = Polygon::new(vec![
+        [0.0, 0.0],
+        [20.0, 0.0],
+        [20.0, 5.0],
+        [5.0, 5.0],
+        [5.0, 20.0],
+        [0.0, 20.0],
+    ]);
+    let points = poisson_disk(&poly, 1.0, &mut rng);
+    assert!(!points.is_empty());
+    assert!(points.iter().all(|p| p[X] <= 5.0 || p[Y] <= 5.0));
+    assert!(min_spacing(&points) >= 1.0 - 1e-4);
+}
+
+#[test]
+fn test_poisson_3d_variable() {
+    let mut rng = Random::from_seed(3);
+    let sphere = Sphere::new([0.0, 0.0, 0.0], 10.0);
+    let points: Vec<Vec3f> = poisson_disk(&sphere, 2.0, &mut rng);
+    assert!(!points.is_empty());
+    assert!(min_spacing(&points) >= 2.0 - 1e-4);
+
+    // Dense on the left, sparse on the right
+    let cuboid = Cuboid::new([0.0, 0.0, 0.0], [20.0, 10.0, 10.0]);
+    let points = poisson_disk_variable(
+        &cuboid,
+        1.0,
+        3.0,
+        |p: Vec3f| if p[X] < 10.0 { 1.0 } else { 0.0 },
+        &mut rng,
+    );
+    let left = points.iter().filter(|p| p[X] < 9.0).count();
+    let right = points.iter().filter(|p| p[X] > 11.0).count();
+    assert!(left > right * 3);
+    assert!(min_spacing(&points) >= 1.0 - 1e-4);
+}
+
+#[test]
+fn test_poisson_huge_bounds() {
+    // Around 10^18 cells, far more than a dense grid could allocate. The
+    // sparse one only pays for samples.
+    struct Speck(f32);
+    impl Region<2> for Speck {
+        fn bounds(&self) -> (Vec2f, Vec2f) {
+            ([0.0, 0.0].into(), [1e9, 1e9].into())
+        }
+        fn contains(&self, p: Vec2f) -> bool {
+            p[X] < self.0 && p[Y] < self.0
+        }
+    }
+    // With no area there's nothing to find
+    let mut rng = Random::from_seed(4);
+    let points: Vec<Vec2f> = poisson_disk(&Speck(0.0), 1.0, &mut rng);
+    assert!(points.is_empty());
+
+    // Whatever the search finds in a speck it has to be in it
+    let points: Vec<Vec2f> = poisson_disk(&Speck(4.0), 1.0, &mut rng);
+    assert!(points.iter().all(|p| Speck(4.0).contains(*p)));
+    assert!(min_spacing(&points) >= 1.0 - 1e-4);
+}
@@ -1 +1,124 @@
+use std::time::{SystemTime, UNIX_EPOCH};
 
+const MULTIPLIER: u64 = 6364136223846793005;
+const INCREMENT: u64 = 1442695040888963407;
+
+// PCG32, small and fast with good statistical quality. The same seed always
+// produces the same sequence so anything built on it can be reproduced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Random {
+    state: u64,
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Random {
+    pub fn new() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Self::from_seed(nanos)
+    }
+
+    pub fn from_seed(seed: u64) -> Self {
+        let mut rng = Self { state: 0 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(INCREMENT);
+        let shifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        shifted.rotate_right(rot)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    // [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    // [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn next_bool(&mut self) -> bool {
+        self.next_u32() & 1 == 1
+    }
+
+    // [min, max)
+    pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    // [min, max)
+    pub fn range_f64(&mut self, min: f64, max: f64) -> f64 {
+        min + (max - min) * self.next_f64()
+    }
+
+    // [min, max), returns min when the range is empty
+    pub fn range_usize(&mut self, min: usize, max: usize) -> usize {
+        if max <= min {
+            return min;
+        }
+        min + (self.next_u64() % (max - min) as u64) as usize
+    }
+
+    // [min, max), returns min when the range is empty
+    pub fn range_i32(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+        (min as i64 + (self.next_u64() % (max as i64 - min as i64) as u64) as i64) as i32
+    }
+}
+
+#[test]
+fn test_random_seeded() {
+    let mut a = Random::from_seed(42);
+    let mut b = Random::from_seed(42);
+    for _ in 0..1000 {
+        assert!(a.next_u32() == b.next_u32());
+    }
+
+    let mut c = Random::from_seed(43);
+    assert!(a.next_u64() != c.next_u64());
+}
+
+#[test]
+fn test_random_ranges() {
+    let mut rng = Random::from_seed(7);
+    for _ in 0..10_000 {
+        let f = rng.next_f32();
+        assert!((0.0..1.0).contains(&f));
+        let f = rng.range_f32(-2.0, 3.0);
+        assert!((-2.0..3.0).contains(&f));
+        let i = rng.range_i32(-5, 5);
+        assert!((-5..5).contains(&i));
+        let u = rng.range_usize(3, 4);
+        assert!(u == 3);
+    }
+
+    // Spans wider than i32::MAX must not overflow
+    let (mut low, mut high) = (false, false);
+    for _ in 0..1000 {
+        let i = rng.range_i32(i32::MIN, i32::MAX);
+        assert!(i < i32::MAX);
+        low |= i < -1_000_000_000;
+        high |= i > 1_000_000_000;
+    }
+    assert!(low && high);
+}
@@ -0,0 +1,296 @@
+use crate::math::vector::*;
+
+// Signed distance functions, negative inside the shape, zero on the surface
+// and positive outside. Shapes are centered on the origin, move them by
+// offsetting p before calling.
+
+pub fn circle(p: Vec2f, radius: f32) -> f32 {
+    p.mag() - radius
+}
+
+pub fn sphere(p: Vec3f, radius: f32) -> f32 {
+    p.mag() - radius
+}
+
+// half is the distance from the center to each side
+pub fn rect(p: Vec2f, half: Vec2f) -> f32 {
+    aabb(p, half)
+}
+
+pub fn cuboid(p: Vec3f, half: Vec3f) -> f32 {
+    aabb(p, half)
+}
+
+pub fn rounded_rect(p: Vec2f, half: Vec2f, radius: f32) -> f32 {
+    aabb(p, half - Vec2f::from([radius, radius])) - radius
+}
+
+pub fn rounded_cuboid(p: Vec3f, half: Vec3f, radius: f32) -> f32 {
+    aabb(p, half - Vec3f::from([radius, radius, radius])) - radius
+}
+
+// Distance to the line segment a-b, in any dimension
+pub fn segment<const N: usize>(p: VecN<f32, N>, a: VecN<f32, N>, b: VecN<f32, N>) -> f32 {
+    let pa = p - a;
+    let ba = b - a;
+    let len = ba.dot(ba);
+    let h = if len > 0.0 {
+        (pa.dot(ba) / len).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (pa - ba * h).mag()
+}
+
+pub fn capsule<const N: usize>(
+    p: VecN<f32, N>,
+    a: VecN<f32, N>,
+    b: VecN<f32, N>,
+    radius: f32,
+) -> f32 {
+    segment(p, a, b) - radius
+}
+
+// Torus lying in the XZ plane, major is the ring radius and minor the tube's
+pub fn torus(p: Vec3f, major: f32, minor: f32) -> f32 {
+    let q = Vec2f::from([Vec2f::from([p[X], p[Z]]).mag() - major, p[Y]]);
+    q.mag() - minor
+}
+
+// Any simple polygon, the sign uses the even-odd rule so winding order
+// doesn't matter
+pub fn polygon(p: Vec2f, points: &[Vec2f]) -> f32 {
+    if points.is_empty() {
+        return f32::MAX;
+    }
+
+    let first = p - points[0];
+    let mut dist = first.dot(first);
+    let mut sign = 1.0;
+    let mut j = points.len() - 1;
+    for i in 0..points.len() {
+        let e = points[j] - points[i];
+        let w = p - points[i];
+        let len = e.dot(e);
+        let h = if len > 0.0 {
+            (w.dot(e) / len).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let b = w - e * h;
+        dist = dist.min(b.dot(b));
+
+        let c = [
+            p[Y] >= points[i][Y],
+            p[Y] < points[j][Y],
+            e[X] * w[Y] > e[Y] * w[X],
+        ];
+        if c.iter().all(|c| *c) || c.iter().all(|c| !*c) {
+            sign = -sign;
+        }
+        j = i;
+    }
+    sign * dist.sqrt()
+}
+
+pub fn aabb<const N: usize>(p: VecN<f32, N>, half: VecN<f32, N>) -> f32 {
+    let q = p.abs() - half;
+    let mut outside = q;
+    let mut inside = f32::MIN;
+    for i in 0..N {
+        outside[i] = q[i].max(0.0);
+        inside = inside.max(q[i]);
+    }
+    outside.mag() + inside.min(0.0)
+}
+
+pub fn union(a: f32, b: f32) -> f32 {
+    a.min(b)
+}
+
+// Carves b out of a
+pub fn subtract(a: f32, b: f32) -> f32 {
+    a.max(-b)
+}
+
+pub fn intersect(a: f32, b: f32) -> f32 {
+    a.max(b)
+}
+
+// Polynomial smooth minimum, k is the width of the blend
+pub fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+pub fn smooth_union(a: f32, b: f32, k: f32) -> f32 {
+    smooth_min(a, b, k)
+}
+
+pub fn smooth_subtract(a: f32, b: f32, k: f32) -> f32 {
+    -smooth_min(-a, b, k)
+}
+
+pub fn smooth_intersect(a: f32, b: f32, k: f32) -> f32 {
+    -smooth_min(-a, -b, k)
+}
+
+// Gradient of the field by central differences, normalized
+pub fn normal<F, const N: usize>(sdf: F, p: VecN<f32, N>, epsilon: f32) -> VecN<f32, N>
+where
+    F: Fn(VecN<f32, N>) -> f32,
+{
+    let mut n = VecN::<f32, N>::zero();
+    for i in 0..N {
+        let mut offset = VecN::<f32, N>::zero();
+        offset[i] = epsilon;
+        n[i] = sdf(p + offset) - sdf(p - offset);
+    }
+    n.norm()
+}
+
+pub struct Hit<const N: usize> {
+    pub position: VecN<f32, N>,
+    pub normal: VecN<f32, N>,
+    pub distance: f32,
+    pub steps: usize,
+}
+
+// Sphere tracer, steps along the ray by the distance to the nearest surface
+pub struct Marcher {
+    pub max_steps: usize,
+    pub max_distance: f32,
+    pub epsilon: f32,
+}
+
+impl Default for Marcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Marcher {
+    pub fn new() -> Self {
+        Self {
+            max_steps: 128,
+            max_distance: 1000.0,
+            epsilon: 1e-4,
+        }
+    }
+
+    pub fn set_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn set_max_distance(mut self, max_distance: f32) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+
+    pub fn set_epsilon(mut self, epsilon: f32) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    pub fn march<F, const N: usize>(
+        &self,
+        sdf: F,
+        origin: VecN<f32, N>,
+        dir: VecN<f32, N>,
+    ) -> Option<Hit<N>>
+    where
+        F: Fn(VecN<f32, N>) -> f32,
+    {
+        let dir = dir.norm();
+        let mut t = 0.0;
+        for steps in 0..self.max_steps {
+            let position = origin + dir * t;
+            let dist = sdf(position);
+            if dist.abs() < self.epsilon {
+                return Some(Hit {
+                    position,
+                    normal: normal(&sdf, position, self.epsilon),
+                    distance: t,
+                    steps: steps + 1,
+                });
+            }
+            t += dist.abs();
+            if t > self.max_distance {
+                break;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+fn near(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-3
+}
+
+#[test]
+fn test_sdf_primitives() {
+    assert!(near(circle(Vec2f::from([3.0, 0.0]), 1.0), 2.0));
+    assert!(near(sphere(Vec3f::zero(), 2.0), -2.0));
+    assert!(near(rect(Vec2f::from([3.0, 0.0]), Vec2f::one()), 2.0));
+    assert!(near(rect(Vec2f::from([0.5, 0.0]), Vec2f::one()), -0.5));
+    assert!(near(rect(Vec2f::from([4.0, 5.0]), Vec2f::one()), 5.0));
+    assert!(near(
+        rounded_cuboid(Vec3f::from([0.0, 3.0, 0.0]), Vec3f::one(), 0.5),
+        2.0
+    ));
+    assert!(near(
+        capsule(
+            Vec2f::from([0.0, 2.0]),
+            Vec2f::from([-1.0, 0.0]),
+            Vec2f::zero(),
+            0.5
+        ),
+        1.5
+    ));
+    assert!(near(torus(Vec3f::from([2.0, 0.0, 0.0]), 2.0, 0.5), -0.5));
+    assert!(near(torus(Vec3f::zero(), 2.0, 0.5), 1.5));
+
+    let square = [
+        Vec2f::from([-1.0, -1.0]),
+        Vec2f::from([1.0, -1.0]),
+        Vec2f::from([1.0, 1.0]),
+        Vec2f::from([-1.0, 1.0]),
+    ];
+    assert!(near(polygon(Vec2f::from([3.0, 0.0]), &square), 2.0));
+    assert!(near(polygon(Vec2f::zero(), &square), -1.0));
+}
+
+#[test]
+fn test_sdf_csg() {
+    assert!(union(1.0, -1.0) == -1.0);
+    assert!(subtract(-1.0, -0.5) == 0.5);
+    assert!(intersect(-1.0, 0.5) == 0.5);
+    assert!(smooth_min(1.0, 1.0, 0.5) < 1.0);
+    assert!(near(smooth_min(0.0, 10.0, 0.5), 0.0));
+    assert!(near(smooth_subtract(-1.0, 10.0, 0.5), -1.0));
+    assert!(near(smooth_intersect(-1.0, -10.0, 0.5), -1.0));
+}
+
+#[test]
+fn test_sdf_march() {
+    let scene = |p: Vec3f| sphere(p - Vec3f::from([0.0, 0.0, -5.0]), 1.0);
+    let hit = Marcher::new()
+        .march(scene, Vec3f::zero(), Vec3f::forward())
+        .unwrap();
+    assert!(near(hit.distance, 4.0));
+    assert!(near(hit.position[Z], -4.0));
+    assert!(near(hit.normal[Z], 1.0));
+
+    let miss = Marcher::new()
+        .set_max_distance(50.0)
+        .march(scene, Vec3f::zero(), Vec3f::backward());
+    assert!(miss.is_none());
+
+    let n = normal(|p| rect(p, Vec2f::one()), Vec2f::from([2.0, 0.0]), 1e-3);
+    assert!(near(n[X], 1.0) && near(n[Y], 0.0));
+}
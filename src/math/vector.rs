@@ -1,7 +1,7 @@
 pub use crate::math::*;
 use std::{fmt::Display, ops::*};
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct VecN<T, const N: usize> {
     inner: [T; N],
 }
@@ -89,6 +89,37 @@ where
         }
         new
     }
+
+    pub fn dot(self, b: Self) -> T {
+        let mut total = T::zero();
+        for i in 0..N {
+            total = self[i] * b[i] + total;
+        }
+        total
+    }
+
+    pub fn abs(self) -> Self {
+        let mut new = self;
+        for i in 0..N {
+            new[i] = new[i].abs();
+        }
+        new
+    }
+}
+
+impl<T> VecN<T, 3>
+where
+    T: Mul<Output = T> + Sub<Output = T> + Copy,
+{
+    pub fn cross(self, b: Self) -> Self {
+        Self {
+            inner: [
+                self[Y] * b[Z] - self[Z] * b[Y],
+                self[Z] * b[X] - self[X] * b[Z],
+                self[X] * b[Y] - self[Y] * b[X],
+            ],
+        }
+    }
 }
 
 impl<T, const N: usize> Zero for VecN<T, N>
@@ -303,6 +334,23 @@ where
     }
 }
 
+impl<T, const N: usize> Neg for VecN<T, N>
+where
+    T: Neg<Output = T> + Copy,
+{
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        let mut inner = self.inner;
+
+        for i in 0..N {
+            inner[i] = -self[i];
+        }
+
+        Self { inner }
+    }
+}
+
 impl<T, const N: usize> Mul<T> for VecN<T, N>
 where
     T: Mul<Output = T> + Copy,
@@ -386,6 +434,17 @@ fn test_clamp() {
     println!("{}", v);
 }
 
+#[test]
+fn test_dot_cross() {
+    let a = Vec3f::from([1.0, 0.0, 0.0]);
+    let b = Vec3f::from([0.0, 1.0, 0.0]);
+    assert!(a.dot(b) == 0.0);
+    assert!(a.dot(a) == 1.0);
+    assert!(a.cross(b) == [0.0, 0.0, 1.0]);
+    assert!(-a == [-1.0, 0.0, 0.0]);
+    assert!(Vec2f::from([-3.0, 2.0]).abs() == [3.0, 2.0]);
+}
+
 #[test]
 fn test_dirs() {
     let v = Vec2f::left();
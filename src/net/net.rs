@@ -1,16 +1,24 @@
 #![allow(unused)]
 
 use std::{
-    borrow::Cow,
     collections::HashMap,
-    io::*,
+    convert::TryFrom,
+    io::{BufRead, BufReader, Read, Write},
     net::{TcpListener, TcpStream},
-    sync::{atomic::AtomicBool, Arc, Mutex},
+    sync::atomic::{AtomicBool, Ordering},
+    sync::{Arc, Mutex},
     thread::{spawn, JoinHandle},
+    time::Duration,
 };
 
+use crate::error::{Error, Result};
+
 const IP: &str = "127.0.0.1";
 const PORT: &str = "8720";
+// How often connection threads wake to check if the server is stopping
+const POLL: Duration = Duration::from_millis(50);
+// Longest request line, a client going past it is cut off
+const MAX_LINE: usize = 1 << 16;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Variant {
@@ -38,6 +46,36 @@ impl Variant {
             Variant::String(_) => "s",
         }
     }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Variant::Int(_) => "int",
+            Variant::Float(_) => "float",
+            Variant::Bool(_) => "bool",
+            Variant::String(_) => "string",
+        }
+    }
+
+    // Inverse of kind() and to_string()
+    pub fn parse(kind: &str, value: &str) -> Result<Variant> {
+        let invalid = || Error::Protocol(format!("invalid {} value '{}'", kind, value));
+        match kind {
+            "i" => value
+                .parse::<i32>()
+                .map(Variant::Int)
+                .map_err(|_| invalid()),
+            "f" => value
+                .parse::<f32>()
+                .map(Variant::Float)
+                .map_err(|_| invalid()),
+            "b" => value
+                .parse::<bool>()
+                .map(Variant::Bool)
+                .map_err(|_| invalid()),
+            "s" => Ok(Variant::String(value.to_string())),
+            _ => Err(Error::Protocol(format!("unknown kind '{}'", kind))),
+        }
+    }
 }
 
 impl std::fmt::Display for Variant {
@@ -79,101 +117,277 @@ impl From<String> for Variant {
     }
 }
 
+macro_rules! impl_try_from_variant {
+    ($t:ty, $name:ident, $type_name:expr) => {
+        impl TryFrom<Variant> for $t {
+            type Error = Error;
+
+            fn try_from(v: Variant) -> Result<$t> {
+                match v {
+                    Variant::$name(v) => Ok(v),
+                    other => Err(Error::TypeMismatch {
+                        expected: $type_name,
+                        found: other.type_name(),
+                    }),
+                }
+            }
+        }
+    };
+}
+
+impl_try_from_variant!(i32, Int, "int");
+impl_try_from_variant!(f32, Float, "float");
+impl_try_from_variant!(bool, Bool, "bool");
+impl_try_from_variant!(String, String, "string");
+
+// One request per line, each gets a one line reply
+//   a:<kind>:<name>:<value>  add, replies ok
+//   g:<name>                 get, replies v:<kind>:<value> or none
+//   r:<name>                 remove, replies like get
+//   q                        stops the server
+// Failures reply e:<message> and the connection stays usable.
+enum Request {
+    Add(String, Variant),
+    Get(String),
+    Remove(String),
+    Quit,
+}
+
+impl Request {
+    fn parse(line: &str) -> Result<Request> {
+        let mut splits = line.splitn(4, ':');
+        let missing = |what: &str| Error::Protocol(format!("missing {} in '{}'", what, line));
+        match splits.next() {
+            Some("a") => {
+                let kind = splits.next().ok_or_else(|| missing("kind"))?;
+                let name = splits.next().ok_or_else(|| missing("name"))?;
+                let value = splits.next().ok_or_else(|| missing("value"))?;
+                Ok(Request::Add(name.to_string(), Variant::parse(kind, value)?))
+            }
+            Some("g") => Ok(Request::Get(
+                splits.next().ok_or_else(|| missing("name"))?.to_string(),
+            )),
+            Some("r") => Ok(Request::Remove(
+                splits.next().ok_or_else(|| missing("name"))?.to_string(),
+            )),
+            Some("q") => Ok(Request::Quit),
+            _ => Err(Error::Protocol(format!("unknown request '{}'", line))),
+        }
+    }
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if name.contains(':') || name.contains('\n') {
+        Err(Error::Protocol(format!("invalid name '{}'", name)))
+    } else {
+        Ok(())
+    }
+}
+
+fn encode_reply(value: Option<Variant>) -> String {
+    match value {
+        Some(v) => format!("v:{}:{}", v.kind(), v),
+        None => "none".to_string(),
+    }
+}
+
+fn decode_reply(line: &str) -> Result<Option<Variant>> {
+    if let Some(message) = line.strip_prefix("e:") {
+        return Err(Error::Protocol(message.to_string()));
+    }
+    match line.splitn(3, ':').collect::<Vec<_>>().as_slice() {
+        ["none"] => Ok(None),
+        ["v", kind, value] => Variant::parse(kind, value).map(Some),
+        _ => Err(Error::Protocol(format!("unexpected reply '{}'", line))),
+    }
+}
+
+// A value that was set, or removed when value is None
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyChange {
+    pub name: String,
+    pub value: Option<Variant>,
+}
+
+// None until someone asks for changes, so an unread log can't grow forever
+type ChangeLog = Mutex<Option<Vec<KeyChange>>>;
+
+fn log_change(changes: &ChangeLog, name: &str, value: Option<Variant>) {
+    if let Ok(mut guard) = changes.lock() {
+        if let Some(log) = guard.as_mut() {
+            log.push(KeyChange {
+                name: name.to_string(),
+                value,
+            });
+        }
+    }
+}
+
 pub struct Server {
     data: Arc<Mutex<HashMap<String, Variant>>>,
+    changes: Arc<ChangeLog>,
+    stop: Arc<AtomicBool>,
     handle: Option<JoinHandle<()>>,
 }
 
 impl Drop for Server {
     fn drop(&mut self) {
-        self.handle.take().unwrap().join().unwrap()
+        let _ = self.shutdown();
     }
 }
 
 impl Server {
-    pub fn start() -> Server {
+    pub fn start() -> Result<Server> {
         let data = Arc::new(Mutex::new(HashMap::new()));
+        let changes = Arc::new(Mutex::new(None));
+        let stop = Arc::new(AtomicBool::new(false));
         let thread_data = data.clone();
+        let thread_changes = changes.clone();
+        let thread_stop = stop.clone();
+        let listener = TcpListener::bind(format!("{}:{}", IP, PORT))?;
 
-        Server {
+        Ok(Server {
             data,
+            changes,
+            stop,
             handle: Some(spawn(move || {
-                let data = thread_data;
-                let listener = TcpListener::bind(format!("{}:{}", IP, PORT)).unwrap();
-
-                loop {
-                    if let Ok((mut stream, addr)) = listener.accept() {
-                        let mut vec = Vec::new();
-                        stream.read_to_end(&mut vec).unwrap();
-                        if let Cow::Borrowed(s) = String::from_utf8_lossy(&vec) {
-                            match s {
-                                s if s.starts_with("a") => {
-                                    let mut splits = s.split(":").skip(1); //skip first because we know its "a" aka add
-                                    let kind = splits.next();
-                                    let name = splits.next().unwrap();
-                                    let variant = splits.next().unwrap();
-                                    match kind {
-                                        Some("i") => {
-                                            if let Ok(mut guard) = data.lock() {
-                                                guard.insert(
-                                                    name.to_string(),
-                                                    variant.parse::<i32>().unwrap().into(),
-                                                );
-                                            }
-                                        }
-                                        Some("f") => {
-                                            if let Ok(mut guard) = data.lock() {
-                                                guard.insert(
-                                                    name.to_string(),
-                                                    variant.parse::<f32>().unwrap().into(),
-                                                );
-                                            }
-                                        }
-                                        Some("b") => {
-                                            if let Ok(mut guard) = data.lock() {
-                                                guard.insert(
-                                                    name.to_string(),
-                                                    variant.parse::<bool>().unwrap().into(),
-                                                );
-                                            }
-                                        }
-                                        Some("s") => {
-                                            if let Ok(mut guard) = data.lock() {
-                                                guard.insert(
-                                                    name.to_string(),
-                                                    variant.to_string().into(),
-                                                );
-                                            }
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                                s if s.starts_with("q") => break,
-                                _ => {}
-                            }
-                        }
+                // Every connection gets its own thread, so one client can't
+                // hold up another or the shutdown. A client that misbehaves
+                // or drops only loses its own connection.
+                let mut connections = Vec::new();
+                for stream in listener.incoming().flatten() {
+                    if thread_stop.load(Ordering::SeqCst) {
+                        break;
                     }
+                    let (data, changes, stop) = (
+                        thread_data.clone(),
+                        thread_changes.clone(),
+                        thread_stop.clone(),
+                    );
+                    connections.push(spawn(move || {
+                        if let Ok(true) = Server::serve(stream, &data, &changes, &stop) {
+                            Server::stop(&stop);
+                        }
+                    }));
+                    connections.retain(|c: &JoinHandle<()>| !c.is_finished());
+                }
+                for connection in connections {
+                    let _ = connection.join();
                 }
             })),
+        })
+    }
+
+    // Flags every thread to finish and wakes the accept loop to notice
+    fn stop(stop: &AtomicBool) {
+        stop.store(true, Ordering::SeqCst);
+        let _ = TcpStream::connect(format!("{}:{}", IP, PORT));
+    }
+
+    // Handles one connection until it closes or the server stops, returns
+    // true on quit
+    fn serve(
+        stream: TcpStream,
+        data: &Mutex<HashMap<String, Variant>>,
+        changes: &ChangeLog,
+        stop: &AtomicBool,
+    ) -> Result<bool> {
+        stream.set_read_timeout(Some(POLL))?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        // Bytes read before a timeout stay here until the rest of the line
+        let mut buffer = Vec::new();
+        loop {
+            if stop.load(Ordering::SeqCst) {
+                return Ok(false);
+            }
+            let limit = (MAX_LINE - buffer.len()) as u64;
+            match (&mut reader).take(limit).read_until(b'\n', &mut buffer) {
+                Ok(_) if buffer.ends_with(b"\n") => {}
+                Ok(_) if buffer.len() >= MAX_LINE => {
+                    writeln!(writer, "e:line longer than {} bytes", MAX_LINE)?;
+                    return Ok(false);
+                }
+                // Closed, maybe partway through a line
+                Ok(_) => return Ok(false),
+                Err(e)
+                    if e.kind() == std::io::ErrorKind::WouldBlock
+                        || e.kind() == std::io::ErrorKind::TimedOut =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e.into()),
+            }
+            let line = String::from_utf8_lossy(&buffer).trim_end().to_string();
+            buffer.clear();
+            let reply = match Request::parse(&line) {
+                Ok(Request::Quit) => return Ok(true),
+                Ok(Request::Add(name, variant)) => {
+                    if let Ok(mut guard) = data.lock() {
+                        log_change(changes, &name, Some(variant.clone()));
+                        guard.insert(name, variant);
+                    }
+                    "ok".to_string()
+                }
+                Ok(Request::Get(name)) => {
+                    encode_reply(data.lock().ok().and_then(|g| g.get(&name).cloned()))
+                }
+                Ok(Request::Remove(name)) => {
+                    let removed = data.lock().ok().and_then(|mut g| g.remove(&name));
+                    if removed.is_some() {
+                        log_change(changes, &name, None);
+                    }
+                    encode_reply(removed)
+                }
+                Err(e) => format!("e:{}", e),
+            };
+            writeln!(writer, "{}", reply)?;
         }
     }
+
+    // Returns once every connection thread has finished, even with clients
+    // still connected
+    fn shutdown(&mut self) -> Result<()> {
+        if let Some(handle) = self.handle.take() {
+            Server::stop(&self.stop);
+            handle
+                .join()
+                .map_err(|_| Error::Io(std::io::Error::other("server thread panicked")))?;
+        }
+        Ok(())
+    }
 }
 
 impl Server {
     pub fn add<T: Into<Variant>>(&mut self, s: &str, t: T) {
         if let Ok(mut guard) = self.data.lock() {
-            guard.insert(s.to_string(), t.into());
+            let variant = t.into();
+            log_change(&self.changes, s, Some(variant.clone()));
+            guard.insert(s.to_string(), variant);
         }
     }
 
     pub fn remove(&mut self, name: &str) -> Option<Variant> {
         if let Ok(mut guard) = self.data.lock() {
-            guard.remove(name)
+            let removed = guard.remove(name);
+            if removed.is_some() {
+                log_change(&self.changes, name, None);
+            }
+            removed
         } else {
             None
         }
     }
 
+    // Changes from this side and from clients since the last call, oldest
+    // first. Nothing is logged before the first call.
+    pub fn take_changes(&self) -> Vec<KeyChange> {
+        match self.changes.lock() {
+            Ok(mut guard) => guard.replace(Vec::new()).unwrap_or_default(),
+            Err(_) => Vec::new(),
+        }
+    }
+
     pub fn get(&self, name: &str) -> Option<Variant> {
         if let Ok(guard) = self.data.lock() {
             if let Some(data) = guard.get(name) {
@@ -186,48 +400,72 @@ impl Server {
         }
     }
 
-    pub fn quit(self) {
-        let mut connection = TcpStream::connect(format!("{}:{}", IP, PORT)).unwrap();
-        connection.write(b"q").unwrap();
+    // Errors when the value exists but holds a different type
+    pub fn get_as<T: TryFrom<Variant, Error = Error>>(&self, name: &str) -> Result<Option<T>> {
+        self.get(name).map(T::try_from).transpose()
+    }
+
+    pub fn quit(mut self) -> Result<()> {
+        self.shutdown()
     }
 }
 
 pub struct ServerConnection {
     connection: TcpStream,
+    reader: BufReader<TcpStream>,
 }
 
 impl ServerConnection {
-    pub fn new() -> Self {
-        Self {
-            connection: TcpStream::connect(format!("{}:{}", IP, PORT))
-                .expect("Failed to connect to server"),
-        }
+    pub fn new() -> Result<Self> {
+        let connection = TcpStream::connect(format!("{}:{}", IP, PORT))?;
+        let reader = BufReader::new(connection.try_clone()?);
+        Ok(Self { connection, reader })
     }
 
-    fn add<T: Into<Variant>>(&mut self, name: &str, t: T) {
-        let variant = t.into();
-        self.connection
-            .write(format!("a:{}:{}:{}", &variant.kind(), name, variant).as_bytes())
-            .unwrap();
+    fn request(&mut self, request: &str) -> Result<String> {
+        writeln!(self.connection, "{}", request)?;
+        let mut reply = String::new();
+        if self.reader.read_line(&mut reply)? == 0 {
+            return Err(Error::Protocol("server closed the connection".to_string()));
+        }
+        Ok(reply.trim_end_matches('\n').to_string())
     }
 
-    fn remove(&mut self, name: &str) -> Option<Variant> {
-        todo!()
+    pub fn add<T: Into<Variant>>(&mut self, name: &str, t: T) -> Result<()> {
+        check_name(name)?;
+        let variant = t.into();
+        if let Variant::String(s) = &variant {
+            if s.contains('\n') {
+                return Err(Error::Protocol(
+                    "strings can't contain newlines".to_string(),
+                ));
+            }
+        }
+        let reply = self.request(&format!("a:{}:{}:{}", variant.kind(), name, variant))?;
+        match reply.as_str() {
+            "ok" => Ok(()),
+            reply => decode_reply(reply).map(|_| ()),
+        }
     }
 
-    fn get(&self, name: &str) -> Option<&Variant> {
-        todo!()
+    pub fn remove(&mut self, name: &str) -> Result<Option<Variant>> {
+        check_name(name)?;
+        let reply = self.request(&format!("r:{}", name))?;
+        decode_reply(&reply)
     }
 
-    fn get_mut(&mut self, name: &str) -> Option<&mut Variant> {
-        todo!()
+    pub fn get(&mut self, name: &str) -> Result<Option<Variant>> {
+        check_name(name)?;
+        let reply = self.request(&format!("g:{}", name))?;
+        decode_reply(&reply)
     }
 }
 
 #[test]
 fn server_get_add() {
-    let mut server = Server::start();
+    let mut server = Server::start().unwrap();
 
+    assert!(server.take_changes().is_empty());
     server.add("Health", 1i32);
     server.add("Magic", 123f32);
 
@@ -235,13 +473,64 @@ fn server_get_add() {
     assert!(Variant::from(1i32) == server.get("Health").unwrap());
 
     {
-        let mut c = ServerConnection::new();
-        c.add("Armor", 12);
+        let mut c = ServerConnection::new().unwrap();
+        c.add("Armor", 12).unwrap();
+
+        // Garbage gets an error reply, the server and connection survive
+        assert!(c.request("a:i:Broken:twelve").unwrap().starts_with("e:"));
+        assert!(c.request("nonsense").unwrap().starts_with("e:"));
+        assert!(matches!(
+            decode_reply(&c.request("g").unwrap()),
+            Err(Error::Protocol(_))
+        ));
+        assert!(c.get("Health").unwrap() == Some(Variant::from(1i32)));
+        assert!(c.remove("Magic").unwrap() == Some(Variant::from(123f32)));
+        assert!(c.get("Magic").unwrap().is_none());
     }
 
-    assert!(Variant::from(123f32) == server.get("Magic").unwrap());
+    let changes = server.take_changes();
+    let names: Vec<_> = changes.iter().map(|c| c.name.as_str()).collect();
+    assert!(names == ["Health", "Magic", "Armor", "Magic"]);
+    assert!(changes[2].value == Some(Variant::Int(12)) && changes[3].value.is_none());
+    assert!(server.take_changes().is_empty());
+
+    assert!(server.get("Magic").is_none());
     assert!(Variant::from(1i32) == server.get("Health").unwrap());
     assert!(Variant::from(12i32) == server.get("Armor").unwrap());
+    assert!(server.get_as::<i32>("Armor").unwrap() == Some(12));
+    assert!(matches!(
+        server.get_as::<bool>("Armor"),
+        Err(Error::TypeMismatch {
+            expected: "bool",
+            found: "int"
+        })
+    ));
 
-    server.quit();
+    // Connections are served side by side, and stopping doesn't wait for
+    // them to hang up
+    let mut a = ServerConnection::new().unwrap();
+    let mut b = ServerConnection::new().unwrap();
+    a.add("Shared", true).unwrap();
+    assert!(b.get("Shared").unwrap() == Some(Variant::Bool(true)));
+    assert!(a.get("Armor").unwrap() == Some(Variant::Int(12)));
+
+    // A line with no end is cut off rather than buffered forever
+    let mut endless = TcpStream::connect(format!("{}:{}", IP, PORT)).unwrap();
+    endless.write_all(&vec![b'x'; MAX_LINE]).unwrap();
+    let mut reply = String::new();
+    BufReader::new(endless).read_line(&mut reply).unwrap();
+    assert!(reply.starts_with("e:"));
+    assert!(a.get("Shared").unwrap() == Some(Variant::Bool(true)));
+
+    server.quit().unwrap();
+    assert!(a.get("Shared").is_err() && b.get("Shared").is_err());
+}
+
+#[test]
+fn variant_parse() {
+    assert!(Variant::parse("i", "12").unwrap() == Variant::Int(12));
+    assert!(Variant::parse("s", "a:b").unwrap() == Variant::String("a:b".to_string()));
+    assert!(matches!(Variant::parse("i", "x"), Err(Error::Protocol(_))));
+    assert!(matches!(Variant::parse("z", "1"), Err(Error::Protocol(_))));
+    assert!(i32::try_from(Variant::Int(3)).unwrap() == 3);
 }
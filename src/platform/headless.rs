@@ -0,0 +1,233 @@
+use crate::error::Result;
+use crate::math::color::ColorRGBA;
+use crate::math::vector::*;
+use crate::platform::Platform;
+use crate::window::{Event, EventIter, WindowBuilder, WindowError};
+
+// A window that only exists in memory. Draw into `buffer_mut`, `present`
+// copies it to the frame tests look at, and events are whatever was
+// injected.
+pub struct Headless {
+    title: String,
+    size: Vec2i,
+    open: bool,
+    content_scale: Vec2f,
+    clipboard: Option<String>,
+    time: f64,
+    pending: Vec<(f64, Event)>,
+    back: Vec<ColorRGBA>,
+    front: Vec<ColorRGBA>,
+    frames: u64,
+}
+
+impl Headless {
+    // Panics if the size has more pixels than fit in memory, `create`
+    // returns an error instead
+    pub fn new<T: Into<Vec2i>>(size: T) -> Self {
+        let size = size.into();
+        let len = pixel_count(size).expect("headless window too large");
+        Self {
+            title: String::new(),
+            size,
+            open: true,
+            content_scale: [1.0, 1.0].into(),
+            clipboard: None,
+            time: 0.0,
+            pending: Vec::new(),
+            back: vec![ColorRGBA::default(); len],
+            front: vec![ColorRGBA::default(); len],
+            frames: 0,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    // Timestamp given to injected events, there's no real clock
+    pub fn set_time(&mut self, time: f64) {
+        self.time = time;
+    }
+
+    pub fn inject(&mut self, event: Event) {
+        self.pending.push((self.time, event));
+    }
+
+    pub fn inject_all<I: IntoIterator<Item = (f64, Event)>>(&mut self, events: I) {
+        self.pending.extend(events);
+    }
+
+    pub fn buffer_mut(&mut self) -> &mut [ColorRGBA] {
+        &mut self.back
+    }
+
+    // The last presented frame, row major from the top left
+    pub fn frame(&self) -> &[ColorRGBA] {
+        &self.front
+    }
+
+    pub fn pixel<T: Into<Vec2i>>(&self, pos: T) -> Option<ColorRGBA> {
+        let pos = pos.into();
+        if pos[X] < 0 || pos[Y] < 0 || pos[X] >= self.size[X] || pos[Y] >= self.size[Y] {
+            return None;
+        }
+        Some(self.front[pos[Y] as usize * self.size[X] as usize + pos[X] as usize])
+    }
+
+    // Frames presented so far
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // A size too big to hold is ignored, like a real window refusing it
+    fn resize(&mut self, size: Vec2i) {
+        let Some(len) = pixel_count(size) else {
+            return;
+        };
+        self.size = size;
+        self.back.resize(len, ColorRGBA::default());
+        self.front.resize(len, ColorRGBA::default());
+    }
+}
+
+// None if the buffer would be bigger than an allocation can be
+fn pixel_count(size: Vec2i) -> Option<usize> {
+    let len = (size[X].max(0) as usize).checked_mul(size[Y].max(0) as usize)?;
+    let bytes = len.checked_mul(std::mem::size_of::<ColorRGBA>())?;
+    (bytes <= isize::MAX as usize).then_some(len)
+}
+
+impl Platform for Headless {
+    fn create(builder: WindowBuilder) -> Result<Self> {
+        let size = builder.size();
+        if size[X] <= 0 || size[Y] <= 0 || pixel_count(size).is_none() {
+            return Err(WindowError::InvalidSize(size).into());
+        }
+        let mut headless = Headless::new(size);
+        headless.title = builder.title().to_string();
+        Ok(headless)
+    }
+
+    fn size(&self) -> Vec2i {
+        self.size
+    }
+
+    // Screen coordinates are pixels here, like Windows and X11
+    fn framebuffer_size(&self) -> Vec2i {
+        self.size
+    }
+
+    fn content_scale(&self) -> Vec2f {
+        self.content_scale
+    }
+
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn close(&mut self) {
+        self.open = false;
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    fn clipboard(&self) -> Option<String> {
+        self.clipboard.clone()
+    }
+
+    fn set_clipboard(&mut self, text: &str) {
+        self.clipboard = Some(text.to_string());
+    }
+
+    // Injected events come out in time order, with the same side effects a
+    // real window would have
+    fn poll(&mut self) -> EventIter<'_> {
+        let mut events = std::mem::take(&mut self.pending);
+        events.sort_by(|a, b| a.0.total_cmp(&b.0));
+        for (_, event) in &events {
+            match event {
+                Event::Close => self.open = false,
+                Event::Size(width, height) => self.resize([*width, *height].into()),
+                Event::ContentScale(x, y) => self.content_scale = [*x, *y].into(),
+                _ => {}
+            }
+        }
+        EventIter::from_events(events)
+    }
+
+    fn present(&mut self) {
+        self.front.copy_from_slice(&self.back);
+        self.frames += 1;
+    }
+}
+
+// A small game loop written against the trait: escape quits, each frame
+// fills the screen with a shade that counts the frames
+#[cfg(test)]
+fn run<P: Platform>(platform: &mut P, mut draw: impl FnMut(&mut P, u8)) -> u8 {
+    use crate::input::Input;
+
+    let mut input = Input::new();
+    let mut frame = 0;
+    while platform.is_open() {
+        input.update(platform.poll());
+        if input.just_pressed(glfw::Key::Escape) {
+            platform.close();
+        }
+        frame += 1;
+        draw(platform, frame);
+        platform.present();
+    }
+    frame
+}
+
+#[test]
+fn test_headless_loop() {
+    use glfw::{Action, Key, Modifiers};
+
+    let builder = WindowBuilder::new().set_title("test").set_size([4, 3]);
+    let mut window = Headless::create(builder).unwrap();
+    assert!(window.title() == "test" && window.frame().len() == 12);
+
+    // Frame 1 sees the resize, frame 2 the escape key
+    window.inject(Event::Size(2, 2));
+    let frames = run(&mut window, |w, frame| {
+        let shade = ColorRGBA::from_rgba(frame, frame, frame, 255);
+        w.buffer_mut().fill(shade);
+        if frame == 1 {
+            w.inject(Event::Key(
+                Key::Escape,
+                0,
+                Action::Press,
+                Modifiers::empty(),
+            ));
+        }
+    });
+    assert!(frames == 2 && window.frames() == 2);
+    assert!(window.size() == [2, 2]);
+    assert!(window.pixel([1, 1]) == Some(ColorRGBA::from_rgba(2, 2, 2, 255)));
+    assert!(window.pixel([2, 0]).is_none());
+
+    window.inject(Event::ContentScale(1.5, 1.5));
+    window.poll().count();
+    assert!(window.content_scale() == [1.5, 1.5] && window.framebuffer_size() == [2, 2]);
+
+    window.inject_all([(2.0, Event::Refresh), (1.0, Event::Close)]);
+    let events: Vec<_> = window.poll().collect();
+    assert!(events[0] == (1.0, Event::Close));
+    assert!(!window.is_open());
+
+    assert!(Headless::create(WindowBuilder::new().set_size([0, 0])).is_err());
+    let huge = WindowBuilder::new().set_size([i32::MAX, i32::MAX]);
+    assert!(Headless::create(huge).is_err());
+    window.inject(Event::Size(i32::MAX, i32::MAX));
+    window.poll().count();
+    assert!(window.size() == [2, 2] && window.frame().len() == 4);
+
+    assert!(window.clipboard().is_none());
+    window.set_clipboard("copied");
+    window.set_title("renamed");
+    assert!(window.clipboard().as_deref() == Some("copied") && window.title() == "renamed");
+}
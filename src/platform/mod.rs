@@ -0,0 +1,80 @@
+pub mod headless;
+
+use crate::error::Result;
+use crate::math::vector::*;
+use crate::window::{EventIter, Window, WindowBuilder};
+
+// What a game loop needs from a window, so the same loop runs on GLFW or on
+// the headless backend in tests
+pub trait Platform {
+    fn create(builder: WindowBuilder) -> Result<Self>
+    where
+        Self: Sized;
+
+    fn size(&self) -> Vec2i;
+
+    fn framebuffer_size(&self) -> Vec2i;
+
+    fn content_scale(&self) -> Vec2f;
+
+    fn is_open(&self) -> bool;
+
+    fn close(&mut self);
+
+    fn set_title(&mut self, title: &str);
+
+    fn clipboard(&self) -> Option<String>;
+
+    fn set_clipboard(&mut self, text: &str);
+
+    fn poll(&mut self) -> EventIter<'_>;
+
+    // Shows the frame that was just drawn
+    fn present(&mut self);
+}
+
+impl Platform for Window {
+    fn create(builder: WindowBuilder) -> Result<Self> {
+        builder.build()
+    }
+
+    fn size(&self) -> Vec2i {
+        Window::size(self)
+    }
+
+    fn framebuffer_size(&self) -> Vec2i {
+        Window::framebuffer_size(self)
+    }
+
+    fn content_scale(&self) -> Vec2f {
+        Window::content_scale(self)
+    }
+
+    fn is_open(&self) -> bool {
+        Window::is_open(self)
+    }
+
+    fn close(&mut self) {
+        Window::close(self)
+    }
+
+    fn set_title(&mut self, title: &str) {
+        Window::set_title(self, title)
+    }
+
+    fn clipboard(&self) -> Option<String> {
+        Window::clipboard(self)
+    }
+
+    fn set_clipboard(&mut self, text: &str) {
+        Window::set_clipboard(self, text)
+    }
+
+    fn poll(&mut self) -> EventIter<'_> {
+        Window::poll(self)
+    }
+
+    fn present(&mut self) {
+        self.draw()
+    }
+}
@@ -0,0 +1,903 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::math::color::ColorRGBA;
+use crate::math::vector::*;
+use crate::window::Image;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rect {
+    x: i32,
+    y: i32,
+    w: i32,
+    h: i32,
+}
+
+impl Rect {
+    fn contains(&self, o: &Rect) -> bool {
+        o.x >= self.x
+            && o.y >= self.y
+            && o.x + o.w <= self.x + self.w
+            && o.y + o.h <= self.y + self.h
+    }
+
+    fn overlaps(&self, o: &Rect) -> bool {
+        o.x < self.x + self.w && self.x < o.x + o.w && o.y < self.y + self.h && self.y < o.y + o.h
+    }
+}
+
+// Where a packer put a rectangle. Rotated ones were turned a quarter, so
+// their width and height are swapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub position: Vec2i,
+    pub rotated: bool,
+}
+
+// Keeps every maximal free rectangle and places each new one where it
+// leaves the shortest side over (best short side fit). Packs tighter than
+// Skyline but slows down with many rectangles.
+#[derive(Debug, Clone)]
+pub struct MaxRects {
+    free: Vec<Rect>,
+}
+
+impl MaxRects {
+    pub fn new<T: Into<Vec2i>>(size: T) -> Self {
+        let size = size.into();
+        Self {
+            free: vec![Rect {
+                x: 0,
+                y: 0,
+                w: size[X],
+                h: size[Y],
+            }],
+        }
+    }
+
+    pub fn insert<T: Into<Vec2i>>(&mut self, size: T, rotate: bool) -> Option<Placement> {
+        let size = size.into();
+        let mut best: Option<((i32, i32), Rect, bool)> = None;
+        for f in &self.free {
+            for rotated in [false, true] {
+                if rotated && !rotate {
+                    continue;
+                }
+                let (w, h) = if rotated {
+                    (size[Y], size[X])
+                } else {
+                    (size[X], size[Y])
+                };
+                if w > f.w || h > f.h {
+                    continue;
+                }
+                let (dw, dh) = (f.w - w, f.h - h);
+                let score = (dw.min(dh), dw.max(dh));
+                if best.is_none_or(|(b, _, _)| score < b) {
+                    best = Some((
+                        score,
+                        Rect {
+                            x: f.x,
+                            y: f.y,
+                            w,
+                            h,
+                        },
+                        rotated,
+                    ));
+                }
+            }
+        }
+        let (_, placed, rotated) = best?;
+        self.split(&placed);
+        Some(Placement {
+            position: [placed.x, placed.y].into(),
+            rotated,
+        })
+    }
+
+    // Cuts the placed rectangle out of every free one it overlaps, keeping
+    // the up to four maximal pieces left around it
+    fn split(&mut self, used: &Rect) {
+        let mut pieces = Vec::new();
+        self.free.retain(|f| {
+            if !f.overlaps(used) {
+                return true;
+            }
+            if used.x > f.x {
+                pieces.push(Rect {
+                    w: used.x - f.x,
+                    ..*f
+                });
+            }
+            if used.x + used.w < f.x + f.w {
+                pieces.push(Rect {
+                    x: used.x + used.w,
+                    w: f.x + f.w - used.x - used.w,
+                    ..*f
+                });
+            }
+            if used.y > f.y {
+                pieces.push(Rect {
+                    h: used.y - f.y,
+                    ..*f
+                });
+            }
+            if used.y + used.h < f.y + f.h {
+                pieces.push(Rect {
+                    y: used.y + used.h,
+                    h: f.y + f.h - used.y - used.h,
+                    ..*f
+                });
+            }
+            false
+        });
+        self.free.extend(pieces);
+
+        // Drop any free rectangle inside another, keeping one of duplicates
+        let mut i = 0;
+        while i < self.free.len() {
+            let inside = (0..self.free.len()).any(|j| {
+                j != i
+                    && self.free[j].contains(&self.free[i])
+                    && (self.free[i] != self.free[j] || j < i)
+            });
+            if inside {
+                self.free.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+// Tracks the height of the packed area across each column and drops each
+// rectangle where it ends up highest (bottom left, with y down). Fast, and
+// good when the rectangles are similar heights.
+#[derive(Debug, Clone)]
+pub struct Skyline {
+    size: Vec2i,
+    // x, y and width of each flat step, left to right
+    nodes: Vec<(i32, i32, i32)>,
+}
+
+impl Skyline {
+    pub fn new<T: Into<Vec2i>>(size: T) -> Self {
+        let size = size.into();
+        Self {
+            size,
+            nodes: vec![(0, 0, size[X])],
+        }
+    }
+
+    // The y a rectangle would rest at if its left edge sat on node i
+    fn fit(&self, i: usize, w: i32, h: i32) -> Option<i32> {
+        let x = self.nodes[i].0;
+        if x + w > self.size[X] {
+            return None;
+        }
+        let mut y = 0;
+        let mut left = w;
+        for &(_, node_y, node_w) in &self.nodes[i..] {
+            if left <= 0 {
+                break;
+            }
+            y = y.max(node_y);
+            left -= node_w;
+        }
+        (y + h <= self.size[Y]).then_some(y)
+    }
+
+    pub fn insert<T: Into<Vec2i>>(&mut self, size: T, rotate: bool) -> Option<Placement> {
+        let size = size.into();
+        // Lowest bottom edge, then narrowest step to leave fewer gaps
+        let mut best: Option<((i32, i32), usize, Rect, bool)> = None;
+        for i in 0..self.nodes.len() {
+            for rotated in [false, true] {
+                if rotated && !rotate {
+                    continue;
+                }
+                let (w, h) = if rotated {
+                    (size[Y], size[X])
+                } else {
+                    (size[X], size[Y])
+                };
+                if let Some(y) = self.fit(i, w, h) {
+                    let score = (y + h, self.nodes[i].2);
+                    if best.is_none_or(|(b, ..)| score < b) {
+                        let rect = Rect {
+                            x: self.nodes[i].0,
+                            y,
+                            w,
+                            h,
+                        };
+                        best = Some((score, i, rect, rotated));
+                    }
+                }
+            }
+        }
+        let (_, i, placed, rotated) = best?;
+        self.add(i, &placed);
+        Some(Placement {
+            position: [placed.x, placed.y].into(),
+            rotated,
+        })
+    }
+
+    fn add(&mut self, i: usize, r: &Rect) {
+        self.nodes.insert(i, (r.x, r.y + r.h, r.w));
+        // Trim or remove the steps now under the new one
+        let right = r.x + r.w;
+        while i + 1 < self.nodes.len() {
+            let (x, y, w) = self.nodes[i + 1];
+            if x >= right {
+                break;
+            }
+            if x + w <= right {
+                self.nodes.remove(i + 1);
+            } else {
+                self.nodes[i + 1] = (right, y, x + w - right);
+                break;
+            }
+        }
+        // Merge neighbours at the same height
+        let mut j = 0;
+        while j + 1 < self.nodes.len() {
+            if self.nodes[j].1 == self.nodes[j + 1].1 {
+                self.nodes[j].2 += self.nodes[j + 1].2;
+                self.nodes.remove(j + 1);
+            } else {
+                j += 1;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Packing {
+    #[default]
+    MaxRects,
+    Skyline,
+}
+
+enum Bin {
+    MaxRects(MaxRects),
+    Skyline(Skyline),
+}
+
+impl Bin {
+    fn insert(&mut self, size: Vec2i, rotate: bool) -> Option<Placement> {
+        match self {
+            Bin::MaxRects(b) => b.insert(size, rotate),
+            Bin::Skyline(b) => b.insert(size, rotate),
+        }
+    }
+}
+
+// Where an image ended up. position and size are the image's own pixels,
+// not counting padding or extrusion, with size swapped if it was rotated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub page: usize,
+    pub position: Vec2i,
+    pub size: Vec2i,
+    // Turned a quarter clockwise to fit
+    pub rotated: bool,
+    // Top left and bottom right, like Sprite::uv
+    pub uv: (Vec2f, Vec2f),
+}
+
+// The names, pages and rectangles of an atlas without the pixels. This is
+// what the metadata file holds.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AtlasLayout {
+    pages: Vec<Vec2i>,
+    regions: BTreeMap<String, Region>,
+}
+
+fn parse_error(line: usize, message: &str) -> Error {
+    Error::Parse {
+        line: line + 1,
+        message: message.to_string(),
+    }
+}
+
+impl AtlasLayout {
+    // Size of each page in order
+    pub fn pages(&self) -> &[Vec2i] {
+        &self.pages
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Region> {
+        self.regions.get(name)
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = (&str, &Region)> {
+        self.regions.iter().map(|(name, r)| (name.as_str(), r))
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    fn insert(&mut self, name: &str, page: usize, position: Vec2i, size: Vec2i, rotated: bool) {
+        let page_size = self.pages[page];
+        let uv = |p: Vec2i| -> Vec2f {
+            [
+                p[X] as f32 / page_size[X] as f32,
+                p[Y] as f32 / page_size[Y] as f32,
+            ]
+            .into()
+        };
+        let region = Region {
+            page,
+            position,
+            size,
+            rotated,
+            uv: (uv(position), uv(position + size)),
+        };
+        self.regions.insert(name.to_string(), region);
+    }
+
+    // "page width height" for each page in order, then one image per line
+    // as "name = page x y width height", with "rotated" on the end if it
+    // was. Lines starting with # are comments.
+    pub fn from_text(text: &str) -> Result<Self> {
+        let mut layout = Self::default();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((name, fields)) = line.split_once('=') {
+                let name = name.trim();
+                if name.is_empty() {
+                    return Err(parse_error(n, "missing image name"));
+                }
+                let mut fields: Vec<&str> = fields.split_whitespace().collect();
+                let rotated = fields.last() == Some(&"rotated");
+                if rotated {
+                    fields.pop();
+                }
+                let v: Vec<i32> = fields
+                    .iter()
+                    .map(|f| f.parse())
+                    .collect::<std::result::Result<_, _>>()
+                    .map_err(|_| parse_error(n, "expected page x y width height"))?;
+                if v.len() != 5 || v.iter().any(|&x| x < 0) {
+                    return Err(parse_error(n, "expected page x y width height"));
+                }
+                let page = match layout.pages.get(v[0] as usize) {
+                    Some(page) => *page,
+                    None => return Err(parse_error(n, "page not declared")),
+                };
+                // Added in i64 so huge numbers can't wrap back inside
+                let fits = |pos: i32, size: i32, page: i32| pos as i64 + size as i64 <= page as i64;
+                if !fits(v[1], v[3], page[X]) || !fits(v[2], v[4], page[Y]) {
+                    return Err(parse_error(n, "image outside its page"));
+                }
+                let (position, size) = ([v[1], v[2]].into(), [v[3], v[4]].into());
+                layout.insert(name, v[0] as usize, position, size, rotated);
+            } else if let Some(size) = line.strip_prefix("page ") {
+                let v: Vec<i32> = size
+                    .split_whitespace()
+                    .map(|f| f.parse())
+                    .collect::<std::result::Result<_, _>>()
+                    .map_err(|_| parse_error(n, "expected page width height"))?;
+                if v.len() != 2 || v[0] <= 0 || v[1] <= 0 {
+                    return Err(parse_error(n, "expected page width height"));
+                }
+                layout.pages.push([v[0], v[1]].into());
+            } else {
+                return Err(parse_error(n, "expected 'page' or 'name = ...'"));
+            }
+        }
+        Ok(layout)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for page in &self.pages {
+            writeln!(out, "page {} {}", page[X], page[Y]).expect("Write failed");
+        }
+        for (name, r) in &self.regions {
+            write!(
+                out,
+                "{} = {} {} {} {} {}",
+                name, r.page, r.position[X], r.position[Y], r.size[X], r.size[Y]
+            )
+            .expect("Write failed");
+            out.push_str(if r.rotated { " rotated\n" } else { "\n" });
+        }
+        out
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_text(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.to_text())?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Atlas {
+    pub pages: Vec<Image>,
+    pub layout: AtlasLayout,
+}
+
+// Uncompressed 32 bit TGA, top left origin. The header holds sizes up to
+// 65535.
+fn tga(image: &Image) -> Result<Vec<u8>> {
+    let size = (u16::try_from(image.width()), u16::try_from(image.height()));
+    let (Ok(w), Ok(h)) = size else {
+        return Err(Error::Render(format!(
+            "{}x{} page is too big for a TGA",
+            image.width(),
+            image.height()
+        )));
+    };
+    let mut out = vec![0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    out.extend_from_slice(&w.to_le_bytes());
+    out.extend_from_slice(&h.to_le_bytes());
+    out.extend_from_slice(&[32, 0x28]);
+    for c in image.pixels() {
+        out.extend_from_slice(&[c.b, c.g, c.r, c.a]);
+    }
+    Ok(out)
+}
+
+impl Atlas {
+    // Writes the layout to path and each page next to it as a TGA named
+    // after it, atlas.txt gives atlas_0.tga, atlas_1.tga and so on. Nothing
+    // is written if a page is too big for a TGA.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let pages = self.pages.iter().map(tga).collect::<Result<Vec<_>>>()?;
+        self.layout.save(path)?;
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("atlas");
+        for (i, page) in pages.iter().enumerate() {
+            std::fs::write(path.with_file_name(format!("{}_{}.tga", stem, i)), page)?;
+        }
+        Ok(())
+    }
+}
+
+// Packs named images into as few pages as it can
+#[derive(Debug, Clone)]
+pub struct AtlasBuilder {
+    images: Vec<(String, Image)>,
+    packing: Packing,
+    max_size: Vec2i,
+    // Empty pixels between neighbouring images
+    padding: i32,
+    // Pixels of each image's edge repeated outwards, so filtering at the
+    // border doesn't pull in a neighbour
+    extrude: i32,
+    rotate: bool,
+    power_of_two: bool,
+}
+
+impl Default for AtlasBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtlasBuilder {
+    pub fn new() -> Self {
+        Self {
+            images: Vec::new(),
+            packing: Packing::default(),
+            max_size: [2048, 2048].into(),
+            padding: 1,
+            extrude: 0,
+            rotate: false,
+            power_of_two: false,
+        }
+    }
+
+    pub fn set_packing(mut self, packing: Packing) -> Self {
+        self.packing = packing;
+        self
+    }
+
+    pub fn set_max_size<T: Into<Vec2i>>(mut self, max_size: T) -> Self {
+        self.max_size = max_size.into();
+        self
+    }
+
+    pub fn set_padding(mut self, padding: i32) -> Self {
+        self.padding = padding.max(0);
+        self
+    }
+
+    pub fn set_extrude(mut self, extrude: i32) -> Self {
+        self.extrude = extrude.max(0);
+        self
+    }
+
+    pub fn set_rotate(mut self, rotate: bool) -> Self {
+        self.rotate = rotate;
+        self
+    }
+
+    pub fn set_power_of_two(mut self, power_of_two: bool) -> Self {
+        self.power_of_two = power_of_two;
+        self
+    }
+
+    // A later image with the same name replaces the earlier one. Names the
+    // layout file couldn't read back are refused: empty, starting with #,
+    // with spaces at either end, or holding = or a line break.
+    pub fn add(&mut self, name: &str, image: Image) -> Result<()> {
+        let invalid = name.is_empty()
+            || name.starts_with('#')
+            || name.trim() != name
+            || name.contains(['=', '\n', '\r']);
+        if invalid {
+            return Err(Error::Render(format!(
+                "invalid atlas image name {:?}",
+                name
+            )));
+        }
+        self.images.retain(|(n, _)| n != name);
+        self.images.push((name.to_string(), image));
+        Ok(())
+    }
+
+    // Each page is cropped to what's on it, then grown to a power of two if
+    // asked, never past max_size. Fails if an image can't fit on a page by
+    // itself.
+    pub fn build(&self) -> Result<Atlas> {
+        // Biggest first packs better
+        let mut order: Vec<usize> = (0..self.images.len()).collect();
+        order.sort_by_key(|&i| {
+            let image = &self.images[i].1;
+            let (w, h) = (image.width(), image.height());
+            (std::cmp::Reverse(w.max(h)), std::cmp::Reverse(w * h))
+        });
+
+        // Padding trails each cell, so the bins get room for the last one's
+        let border = self.extrude * 2 + self.padding;
+        let mut max_size = self.max_size;
+        if self.power_of_two {
+            // Rounding the crop up must not pass max_size, so pack into the
+            // largest power of two inside it
+            for i in 0..2 {
+                let v = max_size[i].max(1) as u32;
+                max_size[i] = (1u32 << (31 - v.leading_zeros())) as i32;
+            }
+        }
+        let bin_size = max_size + Vec2i::from([self.padding, self.padding]);
+        let new_bin = || match self.packing {
+            Packing::MaxRects => Bin::MaxRects(MaxRects::new(bin_size)),
+            Packing::Skyline => Bin::Skyline(Skyline::new(bin_size)),
+        };
+
+        let mut bins: Vec<Bin> = Vec::new();
+        let mut placed = Vec::new();
+        for i in order {
+            let (name, image) = &self.images[i];
+            let cell: Vec2i = [
+                image.width() as i32 + border,
+                image.height() as i32 + border,
+            ]
+            .into();
+            let found = bins
+                .iter_mut()
+                .enumerate()
+                .find_map(|(page, bin)| bin.insert(cell, self.rotate).map(|p| (page, p)));
+            let (page, placement) = match found {
+                Some(found) => found,
+                None => {
+                    let mut bin = new_bin();
+                    let placement = bin.insert(cell, self.rotate).ok_or_else(|| {
+                        Error::Render(format!("image '{}' is too big for an atlas page", name))
+                    })?;
+                    bins.push(bin);
+                    (bins.len() - 1, placement)
+                }
+            };
+            placed.push((i, page, placement));
+        }
+
+        // Crop each page to the furthest image edge
+        let mut extents = vec![Vec2i::from([1, 1]); bins.len()];
+        for &(i, page, p) in &placed {
+            let image = &self.images[i].1;
+            let (w, h) = (image.width() as i32, image.height() as i32);
+            let size: Vec2i = if p.rotated { [h, w] } else { [w, h] }.into();
+            let e = &mut extents[page];
+            e[X] = e[X].max(p.position[X] + size[X] + self.extrude * 2);
+            e[Y] = e[Y].max(p.position[Y] + size[Y] + self.extrude * 2);
+        }
+        if self.power_of_two {
+            for e in &mut extents {
+                *e = [
+                    (e[X] as u32).next_power_of_two() as i32,
+                    (e[Y] as u32).next_power_of_two() as i32,
+                ]
+                .into();
+            }
+        }
+
+        let mut pixels: Vec<Vec<ColorRGBA>> = extents
+            .iter()
+            .map(|e| vec![ColorRGBA::default(); (e[X] * e[Y]) as usize])
+            .collect();
+        let mut layout = AtlasLayout {
+            pages: extents.clone(),
+            regions: BTreeMap::new(),
+        };
+        for (i, page, p) in placed {
+            let (name, image) = &self.images[i];
+            let (w, h) = (image.width() as i32, image.height() as i32);
+            let size: Vec2i = if p.rotated { [h, w] } else { [w, h] }.into();
+            // Source pixel for a spot in the placed image, clamped so the
+            // extruded border repeats the edge
+            let source = |x: i32, y: i32| {
+                let (x, y) = (x.clamp(0, size[X] - 1), y.clamp(0, size[Y] - 1));
+                let (sx, sy) = if p.rotated { (y, h - 1 - x) } else { (x, y) };
+                image.pixels()[(sy * w + sx) as usize]
+            };
+            let page_width = extents[page][X];
+            let e = self.extrude;
+            for y in -e..size[Y] + e {
+                for x in -e..size[X] + e {
+                    let (px, py) = (p.position[X] + e + x, p.position[Y] + e + y);
+                    pixels[page][(py * page_width + px) as usize] = source(x, y);
+                }
+            }
+            let position = p.position + Vec2i::from([e, e]);
+            layout.insert(name, page, position, size, p.rotated);
+        }
+
+        let pages = pixels
+            .into_iter()
+            .zip(&extents)
+            .map(|(pixels, e)| Image::new(e[X] as u32, e[Y] as u32, pixels))
+            .collect::<Result<_>>()?;
+        Ok(Atlas { pages, layout })
+    }
+}
+
+#[cfg(test)]
+fn check_no_overlap(rects: &[Rect], size: Vec2i) {
+    let bounds = Rect {
+        x: 0,
+        y: 0,
+        w: size[X],
+        h: size[Y],
+    };
+    for (i, a) in rects.iter().enumerate() {
+        assert!(bounds.contains(a));
+        assert!(rects[i + 1..].iter().all(|b| !a.overlaps(b)));
+    }
+}
+
+#[test]
+fn test_bin_packers() {
+    let sizes = [
+        [30, 10],
+        [10, 30],
+        [20, 20],
+        [15, 5],
+        [40, 8],
+        [8, 8],
+        [25, 12],
+    ];
+    for packing in [Packing::MaxRects, Packing::Skyline] {
+        let mut bin = match packing {
+            Packing::MaxRects => Bin::MaxRects(MaxRects::new([64, 64])),
+            Packing::Skyline => Bin::Skyline(Skyline::new([64, 64])),
+        };
+        let mut rects = Vec::new();
+        for s in sizes {
+            let p = bin.insert(s.into(), false).unwrap();
+            assert!(!p.rotated);
+            rects.push(Rect {
+                x: p.position[X],
+                y: p.position[Y],
+                w: s[0],
+                h: s[1],
+            });
+        }
+        check_no_overlap(&rects, [64, 64].into());
+        // Too big either way round
+        assert!(bin.insert([65, 1].into(), true).is_none());
+    }
+
+    // A tall strip only goes in a short wide bin turned
+    let mut bin = MaxRects::new([32, 8]);
+    assert!(bin.insert([8, 32], false).is_none());
+    assert!(
+        bin.insert([8, 32], true)
+            == Some(Placement {
+                position: [0, 0].into(),
+                rotated: true
+            })
+    );
+    let mut bin = Skyline::new([32, 8]);
+    assert!(bin.insert([8, 32], true).is_some_and(|p| p.rotated));
+}
+
+#[test]
+fn test_atlas_build() {
+    let red = ColorRGBA::from_rgba(255, 0, 0, 255);
+    let green = ColorRGBA::from_rgba(0, 255, 0, 255);
+    let blue = ColorRGBA::from_rgba(0, 0, 255, 255);
+
+    let mut builder = AtlasBuilder::new()
+        .set_padding(1)
+        .set_extrude(1)
+        .set_power_of_two(true)
+        .set_max_size([16, 16]);
+    builder
+        .add("red", Image::new(4, 4, vec![red; 16]).unwrap())
+        .unwrap();
+    builder
+        .add("green", Image::new(6, 2, vec![green; 12]).unwrap())
+        .unwrap();
+    // Two wide, with a blue left column
+    builder
+        .add("tall", Image::new(2, 12, [blue, red].repeat(12)).unwrap())
+        .unwrap();
+    let atlas = builder.build().unwrap();
+
+    assert!(atlas.pages.len() == 1 && atlas.layout.len() == 3);
+    let page = &atlas.pages[0];
+    assert!(page.width() == 16 && page.height() == 16);
+    let at = |p: Vec2i| page.pixels()[(p[Y] * 16 + p[X]) as usize];
+
+    for (name, color) in [("red", red), ("green", green)] {
+        let r = atlas.layout.get(name).unwrap();
+        let (a, b) = (r.position, r.position + r.size - Vec2i::from([1, 1]));
+        assert!(at(a) == color && at(b) == color);
+        // The extruded edge copies it, the padding past that stays clear
+        assert!(at(a - Vec2i::from([1, 1])) == color && at(b + Vec2i::from([1, 1])) == color);
+        assert!(at(b + Vec2i::from([2, 2])) == ColorRGBA::default());
+        let uv = (r.position[X] as f32 / 16.0, r.position[Y] as f32 / 16.0);
+        assert!(r.uv.0 == [uv.0, uv.1] && r.page == 0 && !r.rotated);
+    }
+    let regions: Vec<_> = atlas.layout.regions().map(|(_, r)| r).collect();
+    for (i, a) in regions.iter().enumerate() {
+        for b in &regions[i + 1..] {
+            // The extruded borders are at least the padding apart
+            let ra = Rect {
+                x: a.position[X] - 2,
+                y: a.position[Y] - 2,
+                w: a.size[X] + 4,
+                h: a.size[Y] + 4,
+            };
+            let rb = Rect {
+                x: b.position[X] - 1,
+                y: b.position[Y] - 1,
+                w: b.size[X] + 2,
+                h: b.size[Y] + 2,
+            };
+            assert!(!ra.overlaps(&rb));
+        }
+    }
+
+    // Too big for the page without turning, and turned clockwise the blue
+    // left column becomes the top row
+    let mut builder = AtlasBuilder::new()
+        .set_padding(0)
+        .set_max_size([12, 4])
+        .set_rotate(true);
+    builder
+        .add("tall", Image::new(2, 12, [blue, red].repeat(12)).unwrap())
+        .unwrap();
+    let atlas = builder.build().unwrap();
+    let r = *atlas.layout.get("tall").unwrap();
+    assert!(r.rotated && r.size == [12, 2]);
+    let page = &atlas.pages[0];
+    assert!(page.pixels()[0] == blue && page.pixels()[11] == blue && page.pixels()[12] == red);
+
+    // Spilling onto a second page, and failing when even one won't fit
+    let mut builder = AtlasBuilder::new().set_padding(0).set_max_size([4, 4]);
+    builder
+        .add("a", Image::new(4, 4, vec![red; 16]).unwrap())
+        .unwrap();
+    builder
+        .add("b", Image::new(4, 4, vec![green; 16]).unwrap())
+        .unwrap();
+    builder
+        .add("c", Image::new(2, 2, vec![blue; 4]).unwrap())
+        .unwrap();
+    let atlas = builder.build().unwrap();
+    assert!(atlas.pages.len() == 3 && atlas.layout.get("c").unwrap().page == 2);
+    assert!(atlas.pages[2].width() == 2);
+    builder
+        .add("d", Image::new(5, 1, vec![blue; 5]).unwrap())
+        .unwrap();
+    assert!(builder.build().is_err());
+
+    // Rounded up to a power of two, pages stay within a max_size that isn't
+    let mut builder = AtlasBuilder::new()
+        .set_padding(0)
+        .set_max_size([12, 12])
+        .set_power_of_two(true);
+    builder
+        .add("a", Image::new(6, 6, vec![red; 36]).unwrap())
+        .unwrap();
+    builder
+        .add("b", Image::new(6, 6, vec![green; 36]).unwrap())
+        .unwrap();
+    let atlas = builder.build().unwrap();
+    assert!(atlas
+        .pages
+        .iter()
+        .all(|p| p.width() <= 12 && p.height() <= 12));
+    builder
+        .add("c", Image::new(9, 1, vec![blue; 9]).unwrap())
+        .unwrap();
+    assert!(builder.build().is_err());
+
+    let mut builder = AtlasBuilder::new().set_packing(Packing::Skyline);
+    for i in 0..20 {
+        builder
+            .add(
+                &i.to_string(),
+                Image::new(
+                    3 + i % 5,
+                    2 + i % 3,
+                    vec![red; ((3 + i % 5) * (2 + i % 3)) as usize],
+                )
+                .unwrap(),
+            )
+            .unwrap();
+    }
+    assert!(builder.build().unwrap().layout.len() == 20);
+}
+
+#[test]
+fn test_atlas_layout_text() {
+    let text =
+        "# made by hand\npage 64 32\npage 16 16\nhero = 0 1 2 16 8\nsword = 1 0 0 4 12 rotated\n";
+    let layout = AtlasLayout::from_text(text).unwrap();
+    let hero = layout.get("hero").unwrap();
+    assert!(hero.uv.0 == [1.0 / 64.0, 2.0 / 32.0] && hero.uv.1 == [17.0 / 64.0, 10.0 / 32.0]);
+    assert!(layout.get("sword").unwrap().rotated);
+    assert!(AtlasLayout::from_text(&layout.to_text()).unwrap() == layout);
+
+    match AtlasLayout::from_text("page 8 8\nhero = 1 0 0 4 4") {
+        Err(Error::Parse { line, .. }) => assert!(line == 2),
+        _ => panic!(),
+    }
+    assert!(AtlasLayout::from_text("hero 0 0 0 4 4").is_err());
+    assert!(AtlasLayout::from_text("page 8\n").is_err());
+    // Regions past the edge of their page would give uvs past 1
+    assert!(AtlasLayout::from_text("page 8 8\nhero = 0 4 0 5 4").is_err());
+    assert!(AtlasLayout::from_text("page 8 8\nhero = 0 0 0 4 9").is_err());
+    assert!(AtlasLayout::from_text("page 8 8\nhero = 0 2147483647 0 2147483647 1").is_err());
+    assert!(AtlasLayout::from_text("page 8 8\nhero = 0 4 4 4 4").is_ok());
+
+    // A page the TGA header can't describe
+    let wide = Image::new(65536, 1, vec![ColorRGBA::default(); 65536]).unwrap();
+    assert!(tga(&wide).is_err());
+
+    // Whatever add accepts survives a trip through the file
+    let red = ColorRGBA::from_rgba(255, 0, 0, 255);
+    let mut builder = AtlasBuilder::new();
+    for name in ["ui/button: hover", "a#b", "page 1 2", "rotated"] {
+        builder
+            .add(name, Image::new(1, 1, vec![red]).unwrap())
+            .unwrap();
+    }
+    for name in ["", "#hero", " hero", "hero ", "a=b", "a\nb"] {
+        assert!(builder
+            .add(name, Image::new(1, 1, vec![red]).unwrap())
+            .is_err());
+    }
+    let layout = builder.build().unwrap().layout;
+    assert!(layout.len() == 4 && AtlasLayout::from_text(&layout.to_text()).unwrap() == layout);
+}
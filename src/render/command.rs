@@ -0,0 +1,502 @@
+use crate::error::{Error, Result};
+use crate::math::color::ColorRGBA;
+use crate::math::matrix::*;
+use crate::math::vector::*;
+use crate::render::framebuffer::Framebuffer;
+use crate::render::Vertex;
+use crate::window::Image;
+
+// Handles to resources a backend owns, only meaningful to the backend that
+// made them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextureId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshId(pub u32);
+
+// An axis aligned quad in camera space, y down like the screen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sprite {
+    pub texture: Option<TextureId>,
+    pub position: Vec2f,
+    pub size: Vec2f,
+    // Top left and bottom right of the texture to show
+    pub uv: (Vec2f, Vec2f),
+    pub color: ColorRGBA,
+    pub layer: i32,
+}
+
+impl Sprite {
+    pub fn new<T: Into<Vec2f>>(position: T, size: T) -> Self {
+        Self {
+            texture: None,
+            position: position.into(),
+            size: size.into(),
+            uv: ([0.0, 0.0].into(), [1.0, 1.0].into()),
+            color: ColorRGBA::from_rgba(255, 255, 255, 255),
+            layer: 0,
+        }
+    }
+
+    pub fn set_texture(mut self, texture: TextureId) -> Self {
+        self.texture = Some(texture);
+        self
+    }
+
+    pub fn set_uv<T: Into<Vec2f>>(mut self, min: T, max: T) -> Self {
+        self.uv = (min.into(), max.into());
+        self
+    }
+
+    pub fn set_color(mut self, color: ColorRGBA) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn set_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    // Top left, top right, bottom right, bottom left. Draw as 0 1 2, 0 2 3.
+    pub fn vertices(&self) -> [Vertex; 4] {
+        let (p, s) = (self.position, self.size);
+        let (a, b) = self.uv;
+        let v = |x: f32, y: f32, u: f32, w: f32| Vertex::new([x, y, 0.0], self.color, [u, w]);
+        [
+            v(p[X], p[Y], a[X], a[Y]),
+            v(p[X] + s[X], p[Y], b[X], a[Y]),
+            v(p[X] + s[X], p[Y] + s[Y], b[X], b[Y]),
+            v(p[X], p[Y] + s[Y], a[X], b[Y]),
+        ]
+    }
+}
+
+pub const QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mesh {
+    pub mesh: MeshId,
+    pub texture: Option<TextureId>,
+    pub transform: Mat3f,
+    pub layer: i32,
+}
+
+// Triangles in camera space built fresh each frame, like a sprite batch.
+// Drawn without the depth test, in index order.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Batch {
+    pub texture: Option<TextureId>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    pub layer: i32,
+}
+
+// Monospaced text from a font texture laid out as a 16 by 16 grid of the
+// first 256 code points. Anything past them shows as '?'.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Text {
+    pub font: TextureId,
+    pub text: String,
+    pub position: Vec2f,
+    pub glyph_size: Vec2f,
+    pub color: ColorRGBA,
+    pub layer: i32,
+}
+
+impl Text {
+    // One sprite per visible glyph, '\n' starts a new line
+    pub fn sprites(&self) -> Vec<Sprite> {
+        let mut sprites = Vec::new();
+        let mut pos = self.position;
+        for c in self.text.chars() {
+            if c == '\n' {
+                pos = [self.position[X], pos[Y] + self.glyph_size[Y]].into();
+                continue;
+            }
+            let code = if (c as u32) < 256 {
+                c as u32
+            } else {
+                '?' as u32
+            };
+            if c != ' ' {
+                let cell: Vec2f = [(code % 16) as f32 / 16.0, (code / 16) as f32 / 16.0].into();
+                let step: Vec2f = [1.0 / 16.0, 1.0 / 16.0].into();
+                sprites.push(Sprite {
+                    texture: Some(self.font),
+                    position: pos,
+                    size: self.glyph_size,
+                    uv: (cell, cell + step),
+                    color: self.color,
+                    layer: self.layer,
+                });
+            }
+            pos[X] += self.glyph_size[X];
+        }
+        sprites
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    // Color and depth of the whole target, any scissor is ignored
+    Clear(ColorRGBA),
+    // World to clip space for the draws after it
+    SetCamera(Mat3f),
+    // Position and size in pixels from the top left, None for everything
+    SetScissor(Option<(Vec2i, Vec2i)>),
+    Sprite(Sprite),
+    Mesh(Mesh),
+    Text(Text),
+    Batch(Batch),
+}
+
+impl Command {
+    // Draws sort by layer, then texture, then mesh. State changes have no
+    // key and stay where they were pushed.
+    pub fn key(&self) -> Option<(i32, Option<TextureId>, Option<MeshId>)> {
+        match self {
+            Command::Sprite(s) => Some((s.layer, s.texture, None)),
+            Command::Mesh(m) => Some((m.layer, m.texture, Some(m.mesh))),
+            Command::Text(t) => Some((t.layer, Some(t.font), None)),
+            Command::Batch(b) => Some((b.layer, b.texture, None)),
+            _ => None,
+        }
+    }
+}
+
+// A frame's worth of commands. Fill it, `sort` it, submit it, `reset` it.
+#[derive(Debug, Clone, Default)]
+pub struct CommandList {
+    commands: Vec<Command>,
+}
+
+impl CommandList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, command: Command) {
+        self.commands.push(command);
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn reset(&mut self) {
+        self.commands.clear();
+    }
+
+    // Sorts each run of draws between state changes by key. The sort is
+    // stable, so draws with the same key keep the order they were pushed.
+    pub fn sort(&mut self) {
+        for run in self.commands.split_mut(|c| c.key().is_none()) {
+            run.sort_by_key(|c| c.key());
+        }
+    }
+
+    pub fn submit<B: Backend + ?Sized>(&self, backend: &mut B) {
+        for command in &self.commands {
+            backend.execute(command);
+        }
+    }
+}
+
+// Something that draws command lists
+pub trait Backend {
+    fn create_texture(&mut self, image: &Image) -> Result<TextureId>;
+
+    fn create_mesh(&mut self, vertices: &[Vertex], indices: &[u32]) -> Result<MeshId>;
+
+    fn execute(&mut self, command: &Command);
+}
+
+// A y down camera with the origin at the top left and one unit per pixel
+pub fn screen_camera<T: Into<Vec2i>>(size: T) -> Mat3f {
+    let size = size.into();
+    Mat3f::orthographic(0.0, size[X] as f32, size[Y] as f32, 0.0, -1.0, 1.0)
+}
+
+pub(crate) fn check_indices(vertices: &[Vertex], indices: &[u32]) -> Result<()> {
+    match indices.iter().find(|&&i| i as usize >= vertices.len()) {
+        Some(i) => Err(Error::Render(format!(
+            "index {} out of range for {} vertices",
+            i,
+            vertices.len()
+        ))),
+        None => Ok(()),
+    }
+}
+
+// Draws into a Framebuffer on the CPU
+#[derive(Debug, Clone)]
+pub struct SoftwareBackend {
+    framebuffer: Framebuffer,
+    textures: Vec<Image>,
+    meshes: Vec<(Vec<Vertex>, Vec<u32>)>,
+    camera: Mat3f,
+}
+
+impl SoftwareBackend {
+    // The camera starts as screen_camera
+    pub fn new<T: Into<Vec2i>>(size: T) -> Self {
+        let framebuffer = Framebuffer::new(size);
+        Self {
+            camera: screen_camera(framebuffer.size()),
+            framebuffer,
+            textures: Vec::new(),
+            meshes: Vec::new(),
+        }
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn framebuffer_mut(&mut self) -> &mut Framebuffer {
+        &mut self.framebuffer
+    }
+
+    // Sprites overlap in painter's order, so they skip the depth test
+    fn flat(&mut self, vertices: &[Vertex], indices: &[u32], texture: Option<TextureId>) {
+        let texture = texture.and_then(|id| self.textures.get(id.0 as usize));
+        let depth_test = self.framebuffer.depth_test;
+        self.framebuffer.depth_test = false;
+        self.framebuffer
+            .mesh(vertices, indices, &self.camera, texture);
+        self.framebuffer.depth_test = depth_test;
+    }
+
+    fn sprite(&mut self, sprite: &Sprite) {
+        self.flat(&sprite.vertices(), &QUAD_INDICES, sprite.texture);
+    }
+}
+
+impl Backend for SoftwareBackend {
+    fn create_texture(&mut self, image: &Image) -> Result<TextureId> {
+        self.textures.push(image.clone());
+        Ok(TextureId(self.textures.len() as u32 - 1))
+    }
+
+    fn create_mesh(&mut self, vertices: &[Vertex], indices: &[u32]) -> Result<MeshId> {
+        check_indices(vertices, indices)?;
+        self.meshes.push((vertices.to_vec(), indices.to_vec()));
+        Ok(MeshId(self.meshes.len() as u32 - 1))
+    }
+
+    fn execute(&mut self, command: &Command) {
+        match command {
+            Command::Clear(color) => {
+                self.framebuffer.clear(*color);
+                self.framebuffer.clear_depth();
+            }
+            Command::SetCamera(camera) => self.camera = *camera,
+            Command::SetScissor(scissor) => self.framebuffer.set_clip(*scissor),
+            Command::Sprite(sprite) => self.sprite(sprite),
+            Command::Mesh(mesh) => {
+                if let Some((vertices, indices)) = self.meshes.get(mesh.mesh.0 as usize) {
+                    let texture = mesh.texture.and_then(|id| self.textures.get(id.0 as usize));
+                    let transform = self.camera * mesh.transform;
+                    self.framebuffer
+                        .mesh(vertices, indices, &transform, texture);
+                }
+            }
+            Command::Text(text) => {
+                for sprite in text.sprites() {
+                    self.sprite(&sprite);
+                }
+            }
+            Command::Batch(batch) => {
+                if check_indices(&batch.vertices, &batch.indices).is_ok() {
+                    self.flat(&batch.vertices, &batch.indices, batch.texture);
+                }
+            }
+        }
+    }
+}
+
+// Keeps everything it is given, for tests to check what would have drawn
+#[derive(Debug, Clone, Default)]
+pub struct RecordingBackend {
+    commands: Vec<Command>,
+    textures: Vec<Vec2i>,
+    meshes: Vec<(usize, usize)>,
+}
+
+impl RecordingBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    pub fn take(&mut self) -> Vec<Command> {
+        std::mem::take(&mut self.commands)
+    }
+
+    // Size of each texture made, in order of id
+    pub fn textures(&self) -> &[Vec2i] {
+        &self.textures
+    }
+
+    // Vertex and index count of each mesh made
+    pub fn meshes(&self) -> &[(usize, usize)] {
+        &self.meshes
+    }
+
+    pub fn draw_count(&self) -> usize {
+        self.commands.iter().filter(|c| c.key().is_some()).count()
+    }
+}
+
+impl Backend for RecordingBackend {
+    fn create_texture(&mut self, image: &Image) -> Result<TextureId> {
+        self.textures
+            .push([image.width() as i32, image.height() as i32].into());
+        Ok(TextureId(self.textures.len() as u32 - 1))
+    }
+
+    fn create_mesh(&mut self, vertices: &[Vertex], indices: &[u32]) -> Result<MeshId> {
+        check_indices(vertices, indices)?;
+        self.meshes.push((vertices.len(), indices.len()));
+        Ok(MeshId(self.meshes.len() as u32 - 1))
+    }
+
+    fn execute(&mut self, command: &Command) {
+        self.commands.push(command.clone());
+    }
+}
+
+#[test]
+fn test_command_sort() {
+    let sprite = |layer: i32, texture: u32| {
+        Command::Sprite(
+            Sprite::new([0.0, 0.0], [1.0, 1.0])
+                .set_texture(TextureId(texture))
+                .set_layer(layer),
+        )
+    };
+    let mut list = CommandList::new();
+    list.push(Command::Clear(ColorRGBA::default()));
+    list.push(sprite(1, 0));
+    list.push(sprite(-1, 5));
+    list.push(sprite(0, 2));
+    list.push(sprite(0, 1));
+    list.push(sprite(0, 2));
+    list.push(Command::SetScissor(None));
+    list.push(sprite(-5, 0));
+    list.push(sprite(2, 65536));
+    list.push(sprite(2, 1));
+    list.sort();
+
+    let keys: Vec<_> = list
+        .commands()
+        .iter()
+        .map(|c| match c {
+            Command::Sprite(s) => Some((s.layer, s.texture.unwrap().0)),
+            _ => None,
+        })
+        .collect();
+    // Nothing crosses the scissor change, which stays put
+    assert!(
+        keys == [
+            None,
+            Some((-1, 5)),
+            Some((0, 1)),
+            Some((0, 2)),
+            Some((0, 2)),
+            Some((1, 0)),
+            None,
+            Some((-5, 0)),
+            Some((2, 1)),
+            Some((2, 65536)),
+        ]
+    );
+
+    let mut recorder = RecordingBackend::new();
+    list.submit(&mut recorder);
+    assert!(recorder.commands() == list.commands() && recorder.draw_count() == 8);
+    let vertex = Vertex::new([0.0, 0.0, 0.0], ColorRGBA::default(), [0.0, 0.0]);
+    assert!(recorder.create_mesh(&[vertex], &[0, 0, 1]).is_err());
+}
+
+#[test]
+fn test_software_backend() {
+    let red = ColorRGBA::from_rgba(255, 0, 0, 255);
+    let white = ColorRGBA::from_rgba(255, 255, 255, 255);
+    let black = ColorRGBA::from_rgba(0, 0, 0, 255);
+    let mut backend = SoftwareBackend::new([32, 32]);
+
+    // A font where every glyph cell is solid white
+    let font = Image::new(16, 16, vec![white; 256]).unwrap();
+    let font = backend.create_texture(&font).unwrap();
+
+    let mut list = CommandList::new();
+    list.push(Command::Clear(black));
+    list.push(Command::Sprite(
+        Sprite::new([4.0, 4.0], [8.0, 8.0])
+            .set_color(red)
+            .set_layer(1),
+    ));
+    list.push(Command::Text(Text {
+        font,
+        text: "a b\nc".to_string(),
+        position: [0.0, 16.0].into(),
+        glyph_size: [4.0, 4.0].into(),
+        color: white,
+        layer: 0,
+    }));
+    list.push(Command::SetScissor(Some(([0, 0].into(), [6, 32].into()))));
+    list.push(Command::Sprite(Sprite::new([0.0, 0.0], [32.0, 2.0])));
+    list.sort();
+    list.submit(&mut backend);
+
+    let fb = backend.framebuffer();
+    assert!(fb.get([4, 4]) == Some(red) && fb.get([11, 11]) == Some(red));
+    assert!(fb.get([12, 12]) == Some(black));
+    // The glyphs, the space and the second line
+    assert!(fb.get([1, 17]) == Some(white) && fb.get([5, 17]) == Some(black));
+    assert!(fb.get([9, 17]) == Some(white) && fb.get([1, 21]) == Some(white));
+    assert!(fb.get([5, 0]) == Some(white) && fb.get([6, 0]) == Some(black));
+
+    // The scissor is still set, but a clear reaches past it
+    list.reset();
+    list.push(Command::Clear(red));
+    list.submit(&mut backend);
+    let fb = backend.framebuffer();
+    assert!(fb.get([0, 0]) == Some(red) && fb.get([31, 31]) == Some(red));
+
+    // A mesh with a transform, under a camera looking at it
+    let vertices = [
+        Vertex::new([0.0, 0.0, 0.0], red, [0.0, 0.0]),
+        Vertex::new([1.0, 0.0, 0.0], red, [0.0, 0.0]),
+        Vertex::new([1.0, 1.0, 0.0], red, [0.0, 0.0]),
+        Vertex::new([0.0, 1.0, 0.0], red, [0.0, 0.0]),
+    ];
+    let mesh = backend.create_mesh(&vertices, &QUAD_INDICES).unwrap();
+    list.reset();
+    list.push(Command::SetScissor(None));
+    list.push(Command::Clear(black));
+    list.push(Command::SetCamera(Mat3f::identity()));
+    list.push(Command::Mesh(Mesh {
+        mesh,
+        texture: None,
+        transform: Mat3f::translation([-1.0, -1.0, 0.0].into()),
+        layer: 0,
+    }));
+    list.submit(&mut backend);
+    let fb = backend.framebuffer();
+    // The bottom left quarter of clip space
+    assert!(fb.get([8, 24]) == Some(red) && fb.get([24, 8]) == Some(black));
+}
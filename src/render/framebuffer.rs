@@ -0,0 +1,585 @@
+use crate::error::Result;
+use crate::math::color::ColorRGBA;
+use crate::math::matrix::*;
+use crate::math::vector::*;
+use crate::render::{Blend, Vertex};
+use crate::window::Image;
+
+// Color as 0 to 1 floats, for interpolating
+fn to_f32(c: ColorRGBA) -> [f32; 4] {
+    [c.r, c.g, c.b, c.a].map(|v| v as f32 / 255.0)
+}
+
+fn from_f32(c: [f32; 4]) -> ColorRGBA {
+    let [r, g, b, a] = c.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8);
+    ColorRGBA::from_rgba(r, g, b, a)
+}
+
+// Nearest texel, repeating outside 0 to 1
+pub fn sample(texture: &Image, uv: Vec2f) -> ColorRGBA {
+    let (w, h) = (texture.width() as i32, texture.height() as i32);
+    let x = ((uv[X] * w as f32).floor() as i32).rem_euclid(w);
+    let y = ((uv[Y] * h as f32).floor() as i32).rem_euclid(h);
+    texture.pixels()[y as usize * w as usize + x as usize]
+}
+
+// Twice the signed area of a, b, p. Positive when p is on the inside of the
+// edge for the winding raster uses.
+fn edge(a: Vec2f, b: Vec2f, p: Vec2f) -> f32 {
+    (p[X] - a[X]) * (b[Y] - a[Y]) - (p[Y] - a[Y]) * (b[X] - a[X])
+}
+
+// Pixels exactly on a shared edge belong to the triangle the edge is a top
+// or left edge of, so neither triangle draws them twice
+fn is_top_left(a: Vec2f, b: Vec2f) -> bool {
+    let d = b - a;
+    d[Y] > 0.0 || (d[Y] == 0.0 && d[X] < 0.0)
+}
+
+// A vertex after the viewport transform
+#[derive(Debug, Clone, Copy)]
+struct Screen {
+    pos: Vec2f,
+    // 0 near to 1 far
+    depth: f32,
+    // 1 / w, 1 for flat triangles
+    inv_w: f32,
+    color: [f32; 4],
+    uv: Vec2f,
+}
+
+// A color and depth buffer in memory, row major from the top left, so
+// rendering runs and can be checked without a GPU
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    size: Vec2i,
+    color: Vec<ColorRGBA>,
+    depth: Vec<f32>,
+    // Pixels outside this are left alone, as min and max corners
+    clip: (Vec2i, Vec2i),
+    pub blend: Blend,
+    // Only used by triangle_3d and mesh, everything else draws on top
+    pub depth_test: bool,
+}
+
+impl Framebuffer {
+    pub fn new<T: Into<Vec2i>>(size: T) -> Self {
+        let size = size.into();
+        let size: Vec2i = [size[X].max(0), size[Y].max(0)].into();
+        let len = size[X] as usize * size[Y] as usize;
+        Self {
+            size,
+            color: vec![ColorRGBA::default(); len],
+            depth: vec![1.0; len],
+            clip: ([0, 0].into(), size),
+            blend: Blend::Alpha,
+            depth_test: true,
+        }
+    }
+
+    pub fn set_blend(mut self, blend: Blend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn set_depth_test(mut self, depth_test: bool) -> Self {
+        self.depth_test = depth_test;
+        self
+    }
+
+    pub fn size(&self) -> Vec2i {
+        self.size
+    }
+
+    pub fn pixels(&self) -> &[ColorRGBA] {
+        &self.color
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [ColorRGBA] {
+        &mut self.color
+    }
+
+    pub fn to_image(&self) -> Result<Image> {
+        Image::new(self.size[X] as u32, self.size[Y] as u32, self.color.clone())
+    }
+
+    fn index(&self, pos: Vec2i) -> Option<usize> {
+        let (min, max) = self.clip;
+        if pos[X] < min[X] || pos[Y] < min[Y] || pos[X] >= max[X] || pos[Y] >= max[Y] {
+            return None;
+        }
+        Some(pos[Y] as usize * self.size[X] as usize + pos[X] as usize)
+    }
+
+    pub fn get<T: Into<Vec2i>>(&self, pos: T) -> Option<ColorRGBA> {
+        let pos = pos.into();
+        if pos[X] < 0 || pos[Y] < 0 || pos[X] >= self.size[X] || pos[Y] >= self.size[Y] {
+            return None;
+        }
+        Some(self.color[pos[Y] as usize * self.size[X] as usize + pos[X] as usize])
+    }
+
+    pub fn depth<T: Into<Vec2i>>(&self, pos: T) -> Option<f32> {
+        let pos = pos.into();
+        if pos[X] < 0 || pos[Y] < 0 || pos[X] >= self.size[X] || pos[Y] >= self.size[Y] {
+            return None;
+        }
+        Some(self.depth[pos[Y] as usize * self.size[X] as usize + pos[X] as usize])
+    }
+
+    // None clips to the whole buffer
+    pub fn set_clip(&mut self, clip: Option<(Vec2i, Vec2i)>) {
+        let full: (Vec2i, Vec2i) = ([0, 0].into(), self.size);
+        self.clip = match clip {
+            Some((pos, size)) => {
+                let min: Vec2i = [pos[X].max(0), pos[Y].max(0)].into();
+                let max = pos + size;
+                let max: Vec2i = [max[X].min(self.size[X]), max[Y].min(self.size[Y])].into();
+                (min, [max[X].max(min[X]), max[Y].max(min[Y])].into())
+            }
+            None => full,
+        };
+    }
+
+    // Ignores the clip rectangle and blend mode
+    pub fn clear(&mut self, color: ColorRGBA) {
+        self.color.fill(color);
+    }
+
+    pub fn clear_depth(&mut self) {
+        self.depth.fill(1.0);
+    }
+
+    pub fn pixel<T: Into<Vec2i>>(&mut self, pos: T, color: ColorRGBA) {
+        if let Some(i) = self.index(pos.into()) {
+            self.color[i] = self.blend.apply(self.color[i], color);
+        }
+    }
+
+    // Partly covered pixels always blend, even with Blend::Replace
+    fn pixel_coverage(&mut self, pos: Vec2i, color: ColorRGBA, coverage: f32) {
+        if let Some(i) = self.index(pos) {
+            let mut color = color;
+            color.a = (color.a as f32 * coverage.clamp(0.0, 1.0)).round() as u8;
+            let blend = match self.blend {
+                Blend::Replace => Blend::Alpha,
+                blend => blend,
+            };
+            self.color[i] = blend.apply(self.color[i], color);
+        }
+    }
+
+    // Bresenham, both ends included
+    pub fn line<T: Into<Vec2i>>(&mut self, a: T, b: T, color: ColorRGBA) {
+        let (mut p, b) = (a.into(), b.into());
+        let dx = (b[X] - p[X]).abs();
+        let dy = -(b[Y] - p[Y]).abs();
+        let sx = if p[X] < b[X] { 1 } else { -1 };
+        let sy = if p[Y] < b[Y] { 1 } else { -1 };
+        let mut err = dx + dy;
+        loop {
+            self.pixel(p, color);
+            if p == b {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                p[X] += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                p[Y] += sy;
+            }
+        }
+    }
+
+    // Xiaolin Wu's line, whole numbers are pixel centers
+    pub fn line_aa<T: Into<Vec2f>>(&mut self, a: T, b: T, color: ColorRGBA) {
+        let (mut a, mut b) = (a.into(), b.into());
+        let steep = (b[Y] - a[Y]).abs() > (b[X] - a[X]).abs();
+        if steep {
+            a = [a[Y], a[X]].into();
+            b = [b[Y], b[X]].into();
+        }
+        if a[X] > b[X] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        let dx = b[X] - a[X];
+        let gradient = if dx == 0.0 { 1.0 } else { (b[Y] - a[Y]) / dx };
+        let plot = |fb: &mut Self, x: i32, y: i32, coverage: f32| {
+            let pos = if steep { [y, x] } else { [x, y] };
+            fb.pixel_coverage(pos.into(), color, coverage);
+        };
+
+        let (x0, x1) = (a[X].round() as i32, b[X].round() as i32);
+        for x in x0..=x1 {
+            let y = a[Y] + gradient * (x as f32 - a[X]);
+            // Ends only get the part of the pixel the line covers
+            let end = if x == x0 {
+                0.5 - (a[X] - x0 as f32)
+            } else if x == x1 {
+                0.5 + (b[X] - x1 as f32)
+            } else {
+                1.0
+            };
+            let end = if x0 == x1 { dx.max(1.0) } else { end };
+            let (base, frac) = (y.floor(), y - y.floor());
+            plot(self, x, base as i32, (1.0 - frac) * end);
+            plot(self, x, base as i32 + 1, frac * end);
+        }
+    }
+
+    pub fn rect<T: Into<Vec2i>>(&mut self, pos: T, size: T, color: ColorRGBA) {
+        let (pos, size) = (pos.into(), size.into());
+        if size[X] <= 0 || size[Y] <= 0 {
+            return;
+        }
+        let max = pos + size - Vec2i::from([1, 1]);
+        for x in pos[X]..=max[X] {
+            self.pixel([x, pos[Y]], color);
+            if max[Y] != pos[Y] {
+                self.pixel([x, max[Y]], color);
+            }
+        }
+        for y in pos[Y] + 1..max[Y] {
+            self.pixel([pos[X], y], color);
+            if max[X] != pos[X] {
+                self.pixel([max[X], y], color);
+            }
+        }
+    }
+
+    pub fn fill_rect<T: Into<Vec2i>>(&mut self, pos: T, size: T, color: ColorRGBA) {
+        let (pos, size) = (pos.into(), size.into());
+        let (min, max) = self.clip;
+        let (x0, y0) = (pos[X].max(min[X]), pos[Y].max(min[Y]));
+        let (x1, y1) = (
+            (pos[X] + size[X]).min(max[X]),
+            (pos[Y] + size[Y]).min(max[Y]),
+        );
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.pixel([x, y], color);
+            }
+        }
+    }
+
+    // Midpoint circle
+    pub fn circle<T: Into<Vec2i>>(&mut self, center: T, radius: i32, color: ColorRGBA) {
+        let c = center.into();
+        let (mut x, mut y, mut err) = (radius, 0, 1 - radius);
+        while x >= y {
+            // The octants meet on the diagonals and axes, don't blend twice
+            let mut points = vec![
+                [x, y],
+                [y, x],
+                [-y, x],
+                [-x, y],
+                [-x, -y],
+                [-y, -x],
+                [y, -x],
+                [x, -y],
+            ];
+            points.sort();
+            points.dedup();
+            for [px, py] in points {
+                self.pixel([c[X] + px, c[Y] + py], color);
+            }
+            y += 1;
+            if err < 0 {
+                err += 2 * y + 1;
+            } else {
+                x -= 1;
+                err += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    pub fn fill_circle<T: Into<Vec2i>>(&mut self, center: T, radius: i32, color: ColorRGBA) {
+        let c = center.into();
+        for dy in -radius..=radius {
+            let dx = ((radius * radius - dy * dy) as f32).sqrt() as i32;
+            for x in c[X] - dx..=c[X] + dx {
+                self.pixel([x, c[Y] + dy], color);
+            }
+        }
+    }
+
+    // Flat triangle in pixel coordinates
+    pub fn fill_triangle<T: Into<Vec2f>>(&mut self, a: T, b: T, c: T, color: ColorRGBA) {
+        let v = |p: Vec2f| Vertex::new([p[X], p[Y], 0.0], color, [0.0, 0.0]);
+        self.triangle([v(a.into()), v(b.into()), v(c.into())], None);
+    }
+
+    // Positions in pixel coordinates with z ignored. Color and uv are
+    // interpolated, the texture is multiplied by the color.
+    pub fn triangle(&mut self, vertices: [Vertex; 3], texture: Option<&Image>) {
+        let screen = vertices.map(|v| Screen {
+            pos: [v.position[X], v.position[Y]].into(),
+            depth: 0.0,
+            inv_w: 1.0,
+            color: to_f32(v.color),
+            uv: v.uv,
+        });
+        self.raster(screen, texture, false);
+    }
+
+    // Positions go through transform to GL style clip space, then are
+    // clipped to the near plane and drawn perspective correct
+    pub fn triangle_3d(
+        &mut self,
+        vertices: [Vertex; 3],
+        transform: &Mat3f,
+        texture: Option<&Image>,
+    ) {
+        let clip = vertices.map(|v| {
+            let p = v.position;
+            (*transform * Vec4f::from([p[X], p[Y], p[Z], 1.0]), v)
+        });
+
+        // Sutherland-Hodgman against z > -w, which gives at most a quad
+        let inside = |p: &Vec4f| p[Z] + p[W] >= 0.0;
+        let mut polygon: Vec<(Vec4f, Vertex)> = Vec::with_capacity(4);
+        for i in 0..3 {
+            let (a, b) = (clip[i], clip[(i + 1) % 3]);
+            if inside(&a.0) {
+                polygon.push(a);
+            }
+            if inside(&a.0) != inside(&b.0) {
+                let (da, db) = (a.0[Z] + a.0[W], b.0[Z] + b.0[W]);
+                let t = da / (da - db);
+                let lerp = |x: f32, y: f32| x + (y - x) * t;
+                let ca = to_f32(a.1.color);
+                let cb = to_f32(b.1.color);
+                let color = from_f32([0, 1, 2, 3].map(|k| lerp(ca[k], cb[k])));
+                let vertex = Vertex {
+                    position: a.1.position,
+                    color,
+                    uv: a.1.uv.lerp(b.1.uv, t),
+                };
+                polygon.push((a.0.lerp(b.0, t), vertex));
+            }
+        }
+
+        let (w, h) = (self.size[X] as f32, self.size[Y] as f32);
+        let screen: Vec<Screen> = polygon
+            .iter()
+            .map(|(p, v)| {
+                let inv_w = 1.0 / p[W];
+                Screen {
+                    // Clip space y is up, rows go down
+                    pos: [
+                        (p[X] * inv_w + 1.0) * 0.5 * w,
+                        (1.0 - p[Y] * inv_w) * 0.5 * h,
+                    ]
+                    .into(),
+                    depth: (p[Z] * inv_w + 1.0) * 0.5,
+                    inv_w,
+                    color: to_f32(v.color),
+                    uv: v.uv,
+                }
+            })
+            .collect();
+        for i in 1..screen.len().saturating_sub(1) {
+            self.raster([screen[0], screen[i], screen[i + 1]], texture, true);
+        }
+    }
+
+    // Triangles from every three indices
+    pub fn mesh(
+        &mut self,
+        vertices: &[Vertex],
+        indices: &[u32],
+        transform: &Mat3f,
+        texture: Option<&Image>,
+    ) {
+        for tri in indices.chunks_exact(3) {
+            let get = |i: u32| vertices.get(i as usize).copied();
+            if let (Some(a), Some(b), Some(c)) = (get(tri[0]), get(tri[1]), get(tri[2])) {
+                self.triangle_3d([a, b, c], transform, texture);
+            }
+        }
+    }
+
+    fn raster(&mut self, v: [Screen; 3], texture: Option<&Image>, depth: bool) {
+        let [a, mut b, mut c] = v;
+        let mut area = edge(a.pos, b.pos, c.pos);
+        if area == 0.0 || !area.is_finite() {
+            return;
+        }
+        if area < 0.0 {
+            std::mem::swap(&mut b, &mut c);
+            area = -area;
+        }
+
+        let (min, max) = self.clip;
+        let lo = |k: usize| a.pos[k].min(b.pos[k]).min(c.pos[k]).floor() as i32;
+        let hi = |k: usize| a.pos[k].max(b.pos[k]).max(c.pos[k]).ceil() as i32;
+        let (x0, x1) = (lo(0).max(min[X]), hi(0).min(max[X]));
+        let (y0, y1) = (lo(1).max(min[Y]), hi(1).min(max[Y]));
+        let edges = [(b.pos, c.pos), (c.pos, a.pos), (a.pos, b.pos)];
+        let top_left = edges.map(|(p, q)| is_top_left(p, q));
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p: Vec2f = [x as f32 + 0.5, y as f32 + 0.5].into();
+                let w = edges.map(|(p0, p1)| edge(p0, p1, p));
+                if (0..3).any(|k| w[k] < 0.0 || (w[k] == 0.0 && !top_left[k])) {
+                    continue;
+                }
+                let l = w.map(|e| e / area);
+                let i = y as usize * self.size[X] as usize + x as usize;
+
+                let z = l[0] * a.depth + l[1] * b.depth + l[2] * c.depth;
+                if depth && self.depth_test {
+                    if !(0.0..=1.0).contains(&z) || z >= self.depth[i] {
+                        continue;
+                    }
+                    self.depth[i] = z;
+                }
+
+                // Weights divided by w interpolate correctly in perspective
+                let pw = [l[0] * a.inv_w, l[1] * b.inv_w, l[2] * c.inv_w];
+                let sum = pw[0] + pw[1] + pw[2];
+                let pw = pw.map(|v| v / sum);
+                let mut color = [0, 1, 2, 3]
+                    .map(|k| pw[0] * a.color[k] + pw[1] * b.color[k] + pw[2] * c.color[k]);
+                if let Some(texture) = texture {
+                    let uv = a.uv * pw[0] + b.uv * pw[1] + c.uv * pw[2];
+                    let texel = to_f32(sample(texture, uv));
+                    color = [0, 1, 2, 3].map(|k| color[k] * texel[k]);
+                }
+                self.color[i] = self.blend.apply(self.color[i], from_f32(color));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn count(fb: &Framebuffer, color: ColorRGBA) -> usize {
+    fb.pixels().iter().filter(|&&c| c == color).count()
+}
+
+#[test]
+fn test_framebuffer_shapes() {
+    let red = ColorRGBA::from_rgba(255, 0, 0, 255);
+    let mut fb = Framebuffer::new([16, 16]);
+
+    fb.line([0, 0], [15, 5], red);
+    assert!(count(&fb, red) == 16);
+    assert!(fb.get([0, 0]) == Some(red) && fb.get([15, 5]) == Some(red));
+
+    fb.clear(ColorRGBA::default());
+    fb.rect([2, 2], [4, 3], red);
+    assert!(count(&fb, red) == 10);
+    fb.fill_rect([-4, -4], [6, 6], red);
+    assert!(count(&fb, red) == 14);
+
+    fb.clear(ColorRGBA::default());
+    fb.circle([8, 8], 5, red);
+    assert!(fb.get([13, 8]) == Some(red) && fb.get([8, 3]) == Some(red));
+    assert!(fb.get([8, 8]) == Some(ColorRGBA::default()));
+    fb.fill_circle([8, 8], 5, red);
+    assert!(fb.get([8, 8]) == Some(red));
+
+    // Nothing lands outside the clip
+    fb.clear(ColorRGBA::default());
+    fb.set_clip(Some(([4, 4].into(), [4, 4].into())));
+    fb.fill_rect([0, 0], [16, 16], red);
+    assert!(count(&fb, red) == 16 && fb.get([3, 4]) == Some(ColorRGBA::default()));
+    fb.set_clip(None);
+
+    // Anti-aliased lines split coverage between the two rows they pass
+    fb.clear(ColorRGBA::default());
+    fb.line_aa([0.0, 3.5], [15.0, 3.5], red);
+    let top = fb.get([7, 3]).unwrap();
+    let bottom = fb.get([7, 4]).unwrap();
+    assert!((126..=129).contains(&top.a) && (126..=129).contains(&bottom.a));
+}
+
+#[test]
+fn test_framebuffer_triangles() {
+    let white = ColorRGBA::from_rgba(255, 255, 255, 255);
+    let mut fb = Framebuffer::new([8, 8]).set_blend(Blend::Additive);
+
+    // Two triangles sharing a diagonal touch every pixel exactly once
+    let dim = ColorRGBA::from_rgba(10, 10, 10, 255);
+    fb.fill_triangle([0.0, 0.0], [8.0, 0.0], [0.0, 8.0], dim);
+    fb.fill_triangle([8.0, 0.0], [8.0, 8.0], [0.0, 8.0], dim);
+    assert!(count(&fb, dim) == 64);
+
+    // Color interpolates from the vertices
+    let mut fb = Framebuffer::new([8, 8]).set_blend(Blend::Replace);
+    let v = |x: f32, y: f32, c: ColorRGBA| Vertex::new([x, y, 0.0], c, [x / 8.0, y / 8.0]);
+    let black = ColorRGBA::from_rgba(0, 0, 0, 255);
+    fb.triangle(
+        [v(0.0, 0.0, black), v(8.0, 0.0, white), v(0.0, 8.0, black)],
+        None,
+    );
+    let left = fb.get([0, 0]).unwrap().r;
+    let right = fb.get([6, 0]).unwrap().r;
+    assert!(left < 20 && right > 180);
+
+    // A checker texture, sampled through the uvs
+    let checker: Vec<_> = (0..4)
+        .map(|i| if (i + i / 2) % 2 == 0 { white } else { black })
+        .collect();
+    let texture = Image::new(2, 2, checker).unwrap();
+    fb.triangle(
+        [v(0.0, 0.0, white), v(8.0, 0.0, white), v(0.0, 8.0, white)],
+        Some(&texture),
+    );
+    assert!(fb.get([1, 1]) == Some(white) && fb.get([5, 1]) == Some(black));
+}
+
+#[test]
+fn test_framebuffer_3d() {
+    use crate::math::angle::Degrees;
+
+    let red = ColorRGBA::from_rgba(255, 0, 0, 255);
+    let blue = ColorRGBA::from_rgba(0, 0, 255, 255);
+    let mut fb = Framebuffer::new([32, 32]).set_blend(Blend::Replace);
+    let projection = Mat3f::perspective(Degrees(90.0), 1.0, 0.1, 100.0);
+
+    let quad = |z: f32, color: ColorRGBA| {
+        let v = |x: f32, y: f32| Vertex::new([x, y, z], color, [(x + 1.0) / 2.0, (y + 1.0) / 2.0]);
+        [v(-1.0, -1.0), v(1.0, -1.0), v(1.0, 1.0), v(-1.0, 1.0)]
+    };
+    let indices = [0, 1, 2, 0, 2, 3];
+
+    // The near quad wins whatever order they're drawn in
+    fb.mesh(&quad(-2.0, red), &indices, &projection, None);
+    fb.mesh(&quad(-4.0, blue), &indices, &projection, None);
+    assert!(fb.get([16, 16]) == Some(red));
+    assert!(fb.get([2, 2]) == Some(ColorRGBA::default()));
+    assert!(fb.depth([16, 16]).unwrap() < 1.0);
+
+    // Crossing the near plane is clipped rather than wrapping around
+    fb.clear(ColorRGBA::default());
+    fb.clear_depth();
+    let v = |x: f32, z: f32| Vertex::new([x, -0.5, z], blue, [0.0, 0.0]);
+    fb.triangle_3d(
+        [v(-1.0, -2.0), v(1.0, -2.0), v(0.0, 1.0)],
+        &projection,
+        None,
+    );
+    assert!(count(&fb, blue) > 0 && fb.get([16, 31]) == Some(blue));
+
+    // A receding floor, blue for the nearest quarter of the texture. Affine
+    // interpolation would put red halfway down the floor.
+    fb.clear(ColorRGBA::default());
+    fb.clear_depth();
+    let pixels: Vec<_> = (0..16).map(|i| if i < 12 { red } else { blue }).collect();
+    let texture = Image::new(1, 16, pixels).unwrap();
+    let white = ColorRGBA::from_rgba(255, 255, 255, 255);
+    let v = |x: f32, z: f32, v: f32| Vertex::new([x, -1.0, z], white, [0.0, v]);
+    let floor = [
+        v(-10.0, -1.0, 0.99),
+        v(10.0, -1.0, 0.99),
+        v(10.0, -21.0, 0.0),
+        v(-10.0, -21.0, 0.0),
+    ];
+    fb.mesh(&floor, &indices, &projection, Some(&texture));
+    assert!(fb.get([16, 24]) == Some(blue) && fb.get([16, 18]) == Some(red));
+}
@@ -0,0 +1,236 @@
+use super::{AttributeType, Buffer, BufferKind, Filter, Gl, Program, Texture, Usage};
+use super::{VertexArray, VertexLayout};
+use crate::error::Result;
+use crate::math::color::ColorRGBA;
+use crate::math::matrix::*;
+use crate::math::vector::*;
+use crate::render::command::*;
+use crate::render::Vertex;
+use crate::window::{Image, Window};
+
+const VERTEX_SHADER: &str = "#version 330 core
+layout(location = 0) in vec3 a_position;
+layout(location = 1) in vec4 a_color;
+layout(location = 2) in vec2 a_uv;
+uniform mat4 u_transform;
+out vec4 v_color;
+out vec2 v_uv;
+void main() {
+    gl_Position = u_transform * vec4(a_position, 1.0);
+    v_color = a_color;
+    v_uv = a_uv;
+}
+";
+
+const FRAGMENT_SHADER: &str = "#version 330 core
+in vec4 v_color;
+in vec2 v_uv;
+uniform sampler2D u_texture;
+out vec4 color;
+void main() {
+    color = v_color * texture(u_texture, v_uv);
+}
+";
+
+// Position as 3 floats, color as 4 normalized bytes, uv as 2 floats
+const STRIDE: usize = 24;
+
+fn pack(vertices: &[Vertex]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(vertices.len() * STRIDE);
+    for v in vertices {
+        for f in v.position.as_arr() {
+            bytes.extend_from_slice(&f.to_ne_bytes());
+        }
+        bytes.extend_from_slice(&[v.color.r, v.color.g, v.color.b, v.color.a]);
+        for f in v.uv.as_arr() {
+            bytes.extend_from_slice(&f.to_ne_bytes());
+        }
+    }
+    bytes
+}
+
+// Matches pack, in the shader's attribute locations
+pub fn vertex_layout() -> VertexLayout {
+    VertexLayout::new()
+        .push(3, AttributeType::Float, false)
+        .push(4, AttributeType::UnsignedByte, true)
+        .push(2, AttributeType::Float, false)
+}
+
+struct GlMesh {
+    array: VertexArray,
+    vertices: Buffer,
+    indices: Buffer,
+    count: usize,
+}
+
+fn create_mesh(gl: &Gl, vertices: &[Vertex], indices: &[u32], usage: Usage) -> GlMesh {
+    let array = VertexArray::new(gl);
+    let mut vertex_buffer = Buffer::new(gl, BufferKind::Vertex, usage);
+    vertex_buffer.upload(&pack(vertices));
+    let mut index_buffer = Buffer::new(gl, BufferKind::Index, usage);
+    index_buffer.upload(indices);
+    array.set_layout(&vertex_buffer, &vertex_layout());
+    array.set_indices(&index_buffer);
+    GlMesh {
+        array,
+        vertices: vertex_buffer,
+        indices: index_buffer,
+        count: indices.len(),
+    }
+}
+
+// Draws with OpenGL 3.3 through the window's context
+pub struct GlBackend {
+    gl: Gl,
+    program: Program,
+    // Bound for draws without a texture
+    white: Texture,
+    textures: Vec<Texture>,
+    meshes: Vec<GlMesh>,
+    // Rewritten for every sprite
+    quad: GlMesh,
+    // Rewritten for every batch, growing to fit
+    stream: GlMesh,
+    camera: Mat3f,
+    size: Vec2i,
+}
+
+impl GlBackend {
+    pub fn new(window: &mut Window) -> Result<Self> {
+        let gl = window.load_gl();
+        let size = window.framebuffer_size();
+        let program = Program::new(&gl, VERTEX_SHADER, FRAGMENT_SHADER)?;
+        program.set("u_texture", 0);
+        let white = ColorRGBA::from_rgba(255, 255, 255, 255);
+        let white = Texture::from_image(&gl, &Image::new(1, 1, vec![white])?, Filter::Nearest);
+        let quad = Sprite::new([0.0, 0.0], [0.0, 0.0]).vertices();
+        let quad = create_mesh(&gl, &quad, &QUAD_INDICES, Usage::Dynamic);
+        let stream = create_mesh(&gl, &[], &[], Usage::Stream);
+        unsafe {
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::DepthFunc(gl::LESS);
+            gl::Viewport(0, 0, size[X], size[Y]);
+        }
+        Ok(Self {
+            gl,
+            program,
+            white,
+            textures: Vec::new(),
+            meshes: Vec::new(),
+            quad,
+            stream,
+            camera: screen_camera(size),
+            size,
+        })
+    }
+
+    pub fn gl(&self) -> Gl {
+        self.gl.clone()
+    }
+
+    // Call when the framebuffer changes size, the camera is left alone
+    pub fn resize<T: Into<Vec2i>>(&mut self, size: T) {
+        self.size = size.into();
+        unsafe { gl::Viewport(0, 0, self.size[X], self.size[Y]) };
+    }
+
+    fn draw(&self, mesh: &GlMesh, texture: Option<TextureId>, transform: &Mat3f) {
+        let texture = texture
+            .and_then(|t| self.textures.get(t.0 as usize))
+            .unwrap_or(&self.white);
+        self.program.set("u_transform", *transform);
+        texture.bind(0);
+        mesh.array.draw(mesh.count);
+    }
+
+    fn sprite(&mut self, sprite: &Sprite) {
+        // The quad buffer always holds exactly four vertices
+        let _ = self.quad.vertices.update(0, &pack(&sprite.vertices()));
+        unsafe { gl::Disable(gl::DEPTH_TEST) };
+        self.draw(&self.quad, sprite.texture, &self.camera);
+    }
+
+    fn batch(&mut self, batch: &Batch) {
+        if check_indices(&batch.vertices, &batch.indices).is_err() {
+            return;
+        }
+        self.stream.vertices.upload(&pack(&batch.vertices));
+        self.stream.indices.upload(&batch.indices);
+        self.stream.count = batch.indices.len();
+        unsafe { gl::Disable(gl::DEPTH_TEST) };
+        self.draw(&self.stream, batch.texture, &self.camera);
+    }
+}
+
+impl Backend for GlBackend {
+    fn create_texture(&mut self, image: &Image) -> Result<TextureId> {
+        let texture = Texture::from_image(&self.gl, image, Filter::Nearest);
+        self.textures.push(texture);
+        Ok(TextureId(self.textures.len() as u32 - 1))
+    }
+
+    fn create_mesh(&mut self, vertices: &[Vertex], indices: &[u32]) -> Result<MeshId> {
+        check_indices(vertices, indices)?;
+        let mesh = create_mesh(&self.gl, vertices, indices, Usage::Static);
+        self.meshes.push(mesh);
+        Ok(MeshId(self.meshes.len() as u32 - 1))
+    }
+
+    fn execute(&mut self, command: &Command) {
+        unsafe {
+            match command {
+                Command::Clear(c) => {
+                    gl::ClearColor(
+                        c.r as f32 / 255.0,
+                        c.g as f32 / 255.0,
+                        c.b as f32 / 255.0,
+                        c.a as f32 / 255.0,
+                    );
+                    // glClear keeps inside the scissor, Clear means everything
+                    let scissor = gl::IsEnabled(gl::SCISSOR_TEST) == gl::TRUE;
+                    gl::Disable(gl::SCISSOR_TEST);
+                    gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+                    if scissor {
+                        gl::Enable(gl::SCISSOR_TEST);
+                    }
+                }
+                Command::SetCamera(camera) => self.camera = *camera,
+                // GL counts scissor rows from the bottom
+                Command::SetScissor(Some((pos, size))) => {
+                    gl::Enable(gl::SCISSOR_TEST);
+                    gl::Scissor(pos[X], self.size[Y] - pos[Y] - size[Y], size[X], size[Y]);
+                }
+                Command::SetScissor(None) => gl::Disable(gl::SCISSOR_TEST),
+                Command::Sprite(sprite) => self.sprite(sprite),
+                Command::Mesh(mesh) => {
+                    if let Some(m) = self.meshes.get(mesh.mesh.0 as usize) {
+                        gl::Enable(gl::DEPTH_TEST);
+                        self.draw(m, mesh.texture, &(self.camera * mesh.transform));
+                    }
+                }
+                Command::Text(text) => {
+                    for sprite in text.sprites() {
+                        self.sprite(&sprite);
+                    }
+                }
+                Command::Batch(batch) => self.batch(batch),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_gl_packing() {
+    let v = Vertex::new(
+        [1.0, 2.0, 3.0],
+        ColorRGBA::from_rgba(1, 2, 3, 4),
+        [0.5, 0.25],
+    );
+    let bytes = pack(&[v, v]);
+    assert!(bytes.len() == 2 * STRIDE);
+    assert!(bytes[4..8] == 2.0f32.to_ne_bytes() && bytes[12..16] == [1, 2, 3, 4]);
+    assert!(bytes[20..24] == 0.25f32.to_ne_bytes());
+    assert!(vertex_layout().stride() == STRIDE);
+}
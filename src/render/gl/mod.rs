@@ -0,0 +1,1002 @@
+pub mod backend;
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::fmt::Display;
+use std::ptr;
+use std::rc::Rc;
+
+use gl::types::*;
+
+use crate::error::{Error, Result};
+use crate::math::color::{ColorRGB, ColorRGBA};
+use crate::math::matrix::*;
+use crate::math::vector::*;
+use crate::window::Image;
+
+// The context of one window. The window marks it lost as it closes, which
+// frees every object made in it.
+#[derive(Debug)]
+pub(crate) struct GlContext {
+    window: *mut glfw::ffi::GLFWwindow,
+    alive: Cell<bool>,
+}
+
+impl GlContext {
+    pub(crate) fn new(window: *mut glfw::ffi::GLFWwindow) -> Self {
+        Self {
+            window,
+            alive: Cell::new(true),
+        }
+    }
+
+    pub(crate) fn lose(&self) {
+        self.alive.set(false);
+    }
+}
+
+// Proof the gl functions are loaded, and a share of the context they were
+// loaded for. Only `Window::load_gl` makes one, and it can't leave the
+// thread. Every wrapper below keeps one, so none of them can leave it
+// either, and each is deleted in its own context whenever it's dropped.
+#[derive(Debug, Clone)]
+pub struct Gl {
+    context: Rc<GlContext>,
+}
+
+impl Gl {
+    pub(crate) fn new(context: Rc<GlContext>) -> Self {
+        Self { context }
+    }
+
+    // Runs f with this context current, then puts back whichever was. Does
+    // nothing once the window is gone, as its objects went with it.
+    fn with_current<F: FnOnce()>(&self, f: F) {
+        if !self.context.alive.get() {
+            return;
+        }
+        unsafe {
+            let previous = glfw::ffi::glfwGetCurrentContext();
+            if previous != self.context.window {
+                glfw::ffi::glfwMakeContextCurrent(self.context.window);
+            }
+            f();
+            if previous != self.context.window {
+                glfw::ffi::glfwMakeContextCurrent(previous);
+            }
+        }
+    }
+
+    fn string(&self, name: GLenum) -> String {
+        unsafe {
+            let s = gl::GetString(name);
+            if s.is_null() {
+                return String::new();
+            }
+            CStr::from_ptr(s as *const _).to_string_lossy().into_owned()
+        }
+    }
+
+    pub fn version(&self) -> String {
+        self.string(gl::VERSION)
+    }
+
+    pub fn renderer(&self) -> String {
+        self.string(gl::RENDERER)
+    }
+
+    // The oldest error gl recorded since the last check, if any
+    pub fn check_error(&self) -> Result<()> {
+        let error = unsafe { gl::GetError() };
+        let name = match error {
+            gl::NO_ERROR => return Ok(()),
+            gl::INVALID_ENUM => "invalid enum",
+            gl::INVALID_VALUE => "invalid value",
+            gl::INVALID_OPERATION => "invalid operation",
+            gl::INVALID_FRAMEBUFFER_OPERATION => "invalid framebuffer operation",
+            gl::OUT_OF_MEMORY => "out of memory",
+            _ => "unknown error",
+        };
+        Err(Error::Render(format!("gl {} (0x{:x})", name, error)))
+    }
+}
+
+// One line of a compile or link log, with the source line it points at if
+// the driver said
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogMessage {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Display for LogMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+fn digits(s: &str) -> (&str, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+// Drivers each have their own log format, these cover the common ones:
+//   Mesa     0:12(5): error: `x' undeclared
+//   NVIDIA   0(12) : error C1008: undefined variable "x"
+//   AMD      ERROR: 0:12: 'x' : undeclared identifier
+pub fn parse_log(log: &str) -> Vec<LogMessage> {
+    let mut messages = Vec::new();
+    for line in log.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let (severity, rest) = match line.split_once(": ") {
+            Some((s, rest)) if s == "ERROR" || s == "WARNING" => (Some(s.to_lowercase()), rest),
+            _ => (None, line),
+        };
+
+        let (file, after) = digits(rest);
+        let position = if file.is_empty() {
+            None
+        } else if let Some(after) = after.strip_prefix('(') {
+            // NVIDIA
+            let (n, after) = digits(after);
+            after
+                .strip_prefix(')')
+                .and_then(|a| a.trim_start().strip_prefix(':'))
+                .map(|a| (n, a))
+        } else if let Some(after) = after.strip_prefix(':') {
+            // Mesa, with an optional column, or AMD
+            let (n, after) = digits(after);
+            let after = match after.strip_prefix('(') {
+                Some(a) => a.split_once(')').map_or(a, |(_, a)| a),
+                None => after,
+            };
+            after.strip_prefix(':').map(|a| (n, a))
+        } else {
+            None
+        };
+
+        let (line_number, message) = match position {
+            Some((n, message)) if !n.is_empty() => (n.parse().ok(), message.trim()),
+            _ => (None, rest),
+        };
+        let message = match severity {
+            Some(severity) => format!("{}: {}", severity, message),
+            None => message.to_string(),
+        };
+        messages.push(LogMessage {
+            line: line_number,
+            message,
+        });
+    }
+    messages
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Vertex,
+    Fragment,
+}
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Vertex => "vertex",
+            Stage::Fragment => "fragment",
+        }
+    }
+
+    fn gl(self) -> GLenum {
+        match self {
+            Stage::Vertex => gl::VERTEX_SHADER,
+            Stage::Fragment => gl::FRAGMENT_SHADER,
+        }
+    }
+}
+
+fn info_log(id: GLuint, program: bool) -> String {
+    unsafe {
+        let mut len = 0;
+        if program {
+            gl::GetProgramiv(id, gl::INFO_LOG_LENGTH, &mut len);
+        } else {
+            gl::GetShaderiv(id, gl::INFO_LOG_LENGTH, &mut len);
+        }
+        let mut log = vec![0u8; len.max(1) as usize];
+        let out = log.as_mut_ptr() as *mut GLchar;
+        if program {
+            gl::GetProgramInfoLog(id, len, ptr::null_mut(), out);
+        } else {
+            gl::GetShaderInfoLog(id, len, ptr::null_mut(), out);
+        }
+        String::from_utf8_lossy(&log)
+            .trim_end_matches('\0')
+            .to_string()
+    }
+}
+
+pub struct Shader {
+    id: GLuint,
+    stage: Stage,
+    gl: Gl,
+}
+
+impl Shader {
+    pub fn new(gl: &Gl, stage: Stage, source: &str) -> Result<Self> {
+        let source = CString::new(source).map_err(|e| Error::Render(e.to_string()))?;
+        unsafe {
+            let shader = Self {
+                id: gl::CreateShader(stage.gl()),
+                stage,
+                gl: gl.clone(),
+            };
+            gl::ShaderSource(shader.id, 1, &source.as_ptr(), ptr::null());
+            gl::CompileShader(shader.id);
+            let mut ok = 0;
+            gl::GetShaderiv(shader.id, gl::COMPILE_STATUS, &mut ok);
+            if ok == 0 {
+                return Err(Error::Shader {
+                    stage: stage.name(),
+                    messages: parse_log(&info_log(shader.id, false)),
+                });
+            }
+            Ok(shader)
+        }
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+}
+
+impl Drop for Shader {
+    fn drop(&mut self) {
+        self.gl
+            .with_current(|| unsafe { gl::DeleteShader(self.id) });
+    }
+}
+
+// Everything a uniform can be set to, packed the way gl wants it. Matrices
+// are column major. The crate's Mat2 is GLSL's mat3 and Mat3 is mat4.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Uniform {
+    Int(i32),
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    IVec2([i32; 2]),
+    IVec3([i32; 3]),
+    IVec4([i32; 4]),
+    Mat3([f32; 9]),
+    Mat4([f32; 16]),
+}
+
+impl Uniform {
+    // The program must be bound
+    unsafe fn set(&self, location: GLint) {
+        match self {
+            Uniform::Int(v) => gl::Uniform1i(location, *v),
+            Uniform::Float(v) => gl::Uniform1f(location, *v),
+            Uniform::Vec2(v) => gl::Uniform2fv(location, 1, v.as_ptr()),
+            Uniform::Vec3(v) => gl::Uniform3fv(location, 1, v.as_ptr()),
+            Uniform::Vec4(v) => gl::Uniform4fv(location, 1, v.as_ptr()),
+            Uniform::IVec2(v) => gl::Uniform2iv(location, 1, v.as_ptr()),
+            Uniform::IVec3(v) => gl::Uniform3iv(location, 1, v.as_ptr()),
+            Uniform::IVec4(v) => gl::Uniform4iv(location, 1, v.as_ptr()),
+            Uniform::Mat3(m) => gl::UniformMatrix3fv(location, 1, gl::FALSE, m.as_ptr()),
+            Uniform::Mat4(m) => gl::UniformMatrix4fv(location, 1, gl::FALSE, m.as_ptr()),
+        }
+    }
+}
+
+pub trait AsUniform {
+    fn as_uniform(&self) -> Uniform;
+}
+
+impl AsUniform for Uniform {
+    fn as_uniform(&self) -> Uniform {
+        *self
+    }
+}
+
+impl AsUniform for i32 {
+    fn as_uniform(&self) -> Uniform {
+        Uniform::Int(*self)
+    }
+}
+
+impl AsUniform for bool {
+    fn as_uniform(&self) -> Uniform {
+        Uniform::Int(*self as i32)
+    }
+}
+
+impl AsUniform for f32 {
+    fn as_uniform(&self) -> Uniform {
+        Uniform::Float(*self)
+    }
+}
+
+impl AsUniform for Vec2f {
+    fn as_uniform(&self) -> Uniform {
+        Uniform::Vec2(self.as_arr())
+    }
+}
+
+impl AsUniform for Vec3f {
+    fn as_uniform(&self) -> Uniform {
+        Uniform::Vec3(self.as_arr())
+    }
+}
+
+impl AsUniform for Vec4f {
+    fn as_uniform(&self) -> Uniform {
+        Uniform::Vec4(self.as_arr())
+    }
+}
+
+impl AsUniform for Vec2i {
+    fn as_uniform(&self) -> Uniform {
+        Uniform::IVec2([self[X], self[Y]])
+    }
+}
+
+impl AsUniform for Vec3i {
+    fn as_uniform(&self) -> Uniform {
+        Uniform::IVec3([self[X], self[Y], self[Z]])
+    }
+}
+
+impl AsUniform for Vec4i {
+    fn as_uniform(&self) -> Uniform {
+        Uniform::IVec4([self[X], self[Y], self[Z], self[W]])
+    }
+}
+
+impl AsUniform for Mat2f {
+    fn as_uniform(&self) -> Uniform {
+        let mut m = [0.0; 9];
+        for (i, column) in [self.x, self.y, self.z].iter().enumerate() {
+            m[i * 3..i * 3 + 3].copy_from_slice(&column.as_arr());
+        }
+        Uniform::Mat3(m)
+    }
+}
+
+impl AsUniform for Mat3f {
+    fn as_uniform(&self) -> Uniform {
+        let mut m = [0.0; 16];
+        for (i, column) in [self.x, self.y, self.z, self.w].iter().enumerate() {
+            m[i * 4..i * 4 + 4].copy_from_slice(&column.as_arr());
+        }
+        Uniform::Mat4(m)
+    }
+}
+
+// Colors go in as 0 to 1 vectors
+impl AsUniform for ColorRGBA {
+    fn as_uniform(&self) -> Uniform {
+        Uniform::Vec4([self.r, self.g, self.b, self.a].map(|c| c as f32 / 255.0))
+    }
+}
+
+impl AsUniform for ColorRGB {
+    fn as_uniform(&self) -> Uniform {
+        Uniform::Vec3([self.r, self.g, self.b].map(|c| c as f32 / 255.0))
+    }
+}
+
+pub struct Program {
+    id: GLuint,
+    // Looking names up every frame is slow, -1 marks ones that don't exist
+    locations: RefCell<HashMap<String, GLint>>,
+    gl: Gl,
+}
+
+impl Program {
+    pub fn new(gl: &Gl, vertex: &str, fragment: &str) -> Result<Self> {
+        let vertex = Shader::new(gl, Stage::Vertex, vertex)?;
+        let fragment = Shader::new(gl, Stage::Fragment, fragment)?;
+        Self::link(gl, &[&vertex, &fragment])
+    }
+
+    pub fn link(gl: &Gl, shaders: &[&Shader]) -> Result<Self> {
+        unsafe {
+            let program = Self {
+                id: gl::CreateProgram(),
+                locations: RefCell::new(HashMap::new()),
+                gl: gl.clone(),
+            };
+            for shader in shaders {
+                gl::AttachShader(program.id, shader.id);
+            }
+            gl::LinkProgram(program.id);
+            for shader in shaders {
+                gl::DetachShader(program.id, shader.id);
+            }
+            let mut ok = 0;
+            gl::GetProgramiv(program.id, gl::LINK_STATUS, &mut ok);
+            if ok == 0 {
+                return Err(Error::Shader {
+                    stage: "link",
+                    messages: parse_log(&info_log(program.id, true)),
+                });
+            }
+            Ok(program)
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn bind(&self) {
+        unsafe { gl::UseProgram(self.id) };
+    }
+
+    pub fn location(&self, name: &str) -> Option<GLint> {
+        let mut locations = self.locations.borrow_mut();
+        let location = *locations.entry(name.to_string()).or_insert_with(|| {
+            let name = CString::new(name).unwrap_or_default();
+            unsafe { gl::GetUniformLocation(self.id, name.as_ptr()) }
+        });
+        (location >= 0).then_some(location)
+    }
+
+    // Binds the program. Returns false if it has no such uniform, which
+    // includes ones the compiler dropped for being unused.
+    pub fn set<U: AsUniform>(&self, name: &str, value: U) -> bool {
+        match self.location(name) {
+            Some(location) => {
+                self.bind();
+                unsafe { value.as_uniform().set(location) };
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for Program {
+    fn drop(&mut self) {
+        self.gl
+            .with_current(|| unsafe { gl::DeleteProgram(self.id) });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeType {
+    Float,
+    Byte,
+    UnsignedByte,
+    Short,
+    UnsignedShort,
+    Int,
+    UnsignedInt,
+}
+
+impl AttributeType {
+    pub fn size(self) -> usize {
+        match self {
+            AttributeType::Byte | AttributeType::UnsignedByte => 1,
+            AttributeType::Short | AttributeType::UnsignedShort => 2,
+            AttributeType::Float | AttributeType::Int | AttributeType::UnsignedInt => 4,
+        }
+    }
+
+    fn gl(self) -> GLenum {
+        match self {
+            AttributeType::Float => gl::FLOAT,
+            AttributeType::Byte => gl::BYTE,
+            AttributeType::UnsignedByte => gl::UNSIGNED_BYTE,
+            AttributeType::Short => gl::SHORT,
+            AttributeType::UnsignedShort => gl::UNSIGNED_SHORT,
+            AttributeType::Int => gl::INT,
+            AttributeType::UnsignedInt => gl::UNSIGNED_INT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attribute {
+    pub components: usize,
+    pub kind: AttributeType,
+    // Integers read as 0 to 1 (or -1 to 1) floats
+    pub normalized: bool,
+    // Read by the shader as ints (ivec, uint) rather than converted to
+    // floats, which needs the integer pointer call
+    pub integer: bool,
+    // Bytes from the start of the vertex
+    pub offset: usize,
+}
+
+// Interleaved attributes in shader location order, offsets and stride
+// worked out as they're pushed
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VertexLayout {
+    attributes: Vec<Attribute>,
+    stride: usize,
+}
+
+impl VertexLayout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Read by the shader as floats, whatever the stored type
+    pub fn push(self, components: usize, kind: AttributeType, normalized: bool) -> Self {
+        self.push_attribute(components, kind, normalized, false)
+    }
+
+    // Read by the shader as ints, kind can't be Float
+    pub fn push_integer(self, components: usize, kind: AttributeType) -> Self {
+        assert!(
+            kind != AttributeType::Float,
+            "integer attribute stored as floats"
+        );
+        self.push_attribute(components, kind, false, true)
+    }
+
+    fn push_attribute(
+        mut self,
+        components: usize,
+        kind: AttributeType,
+        normalized: bool,
+        integer: bool,
+    ) -> Self {
+        // Keep each attribute aligned to its own type
+        let align = kind.size();
+        let offset = self.stride.div_ceil(align) * align;
+        self.attributes.push(Attribute {
+            components,
+            kind,
+            normalized,
+            integer,
+            offset,
+        });
+        self.stride = offset + components * kind.size();
+        self
+    }
+
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    // Rounded up so every vertex starts 4 byte aligned
+    pub fn stride(&self) -> usize {
+        self.stride.div_ceil(4) * 4
+    }
+}
+
+// Plain numbers with no padding, safe to hand gl as bytes
+pub trait BufferData: Copy {}
+
+impl BufferData for u8 {}
+impl BufferData for u16 {}
+impl BufferData for u32 {}
+impl BufferData for i32 {}
+impl BufferData for f32 {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferKind {
+    Vertex,
+    Index,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Usage {
+    // Set once, drawn many times
+    Static,
+    // Changed now and then
+    Dynamic,
+    // Changed every frame
+    Stream,
+}
+
+pub struct Buffer {
+    id: GLuint,
+    kind: BufferKind,
+    usage: Usage,
+    len: usize,
+    gl: Gl,
+}
+
+impl Buffer {
+    pub fn new(gl: &Gl, kind: BufferKind, usage: Usage) -> Self {
+        let mut id = 0;
+        unsafe { gl::GenBuffers(1, &mut id) };
+        Self {
+            id,
+            kind,
+            usage,
+            len: 0,
+            gl: gl.clone(),
+        }
+    }
+
+    fn target(&self) -> GLenum {
+        match self.kind {
+            BufferKind::Vertex => gl::ARRAY_BUFFER,
+            BufferKind::Index => gl::ELEMENT_ARRAY_BUFFER,
+        }
+    }
+
+    pub fn bind(&self) {
+        unsafe { gl::BindBuffer(self.target(), self.id) };
+    }
+
+    // Size in bytes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Replaces the contents, resizing to fit
+    pub fn upload<T: BufferData>(&mut self, data: &[T]) {
+        let usage = match self.usage {
+            Usage::Static => gl::STATIC_DRAW,
+            Usage::Dynamic => gl::DYNAMIC_DRAW,
+            Usage::Stream => gl::STREAM_DRAW,
+        };
+        self.len = std::mem::size_of_val(data);
+        self.bind();
+        unsafe {
+            gl::BufferData(
+                self.target(),
+                self.len as GLsizeiptr,
+                data.as_ptr() as *const _,
+                usage,
+            )
+        };
+    }
+
+    // Writes over part of the contents, in bytes from the start. Anything
+    // past the end is an error rather than a resize.
+    pub fn update<T: BufferData>(&mut self, offset: usize, data: &[T]) -> Result<()> {
+        let size = std::mem::size_of_val(data);
+        if offset + size > self.len {
+            return Err(Error::Render(format!(
+                "update of {} bytes at {} overruns a {} byte buffer",
+                size, offset, self.len
+            )));
+        }
+        self.bind();
+        unsafe {
+            gl::BufferSubData(
+                self.target(),
+                offset as GLintptr,
+                size as GLsizeiptr,
+                data.as_ptr() as *const _,
+            )
+        };
+        Ok(())
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.gl
+            .with_current(|| unsafe { gl::DeleteBuffers(1, &self.id) });
+    }
+}
+
+// Which buffers and layout a draw reads vertices from
+pub struct VertexArray {
+    id: GLuint,
+    gl: Gl,
+}
+
+impl VertexArray {
+    pub fn new(gl: &Gl) -> Self {
+        let mut id = 0;
+        unsafe { gl::GenVertexArrays(1, &mut id) };
+        Self { id, gl: gl.clone() }
+    }
+
+    pub fn bind(&self) {
+        unsafe { gl::BindVertexArray(self.id) };
+    }
+
+    // Attribute i of the layout goes to shader location i
+    pub fn set_layout(&self, vertices: &Buffer, layout: &VertexLayout) {
+        self.bind();
+        vertices.bind();
+        let stride = layout.stride() as GLsizei;
+        for (i, a) in layout.attributes().iter().enumerate() {
+            let (index, size, offset) = (i as GLuint, a.components as GLint, a.offset as *const _);
+            unsafe {
+                gl::EnableVertexAttribArray(index);
+                if a.integer {
+                    gl::VertexAttribIPointer(index, size, a.kind.gl(), stride, offset);
+                } else {
+                    let normalized = if a.normalized { gl::TRUE } else { gl::FALSE };
+                    gl::VertexAttribPointer(index, size, a.kind.gl(), normalized, stride, offset);
+                }
+            }
+        }
+        unsafe { gl::BindVertexArray(0) };
+    }
+
+    pub fn set_indices(&self, indices: &Buffer) {
+        self.bind();
+        indices.bind();
+        unsafe { gl::BindVertexArray(0) };
+    }
+
+    // Triangles from count u32 indices
+    pub fn draw(&self, count: usize) {
+        self.bind();
+        unsafe {
+            gl::DrawElements(
+                gl::TRIANGLES,
+                count as GLsizei,
+                gl::UNSIGNED_INT,
+                ptr::null(),
+            );
+            gl::BindVertexArray(0);
+        }
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        self.gl
+            .with_current(|| unsafe { gl::DeleteVertexArrays(1, &self.id) });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+pub struct Texture {
+    id: GLuint,
+    size: Vec2i,
+    gl: Gl,
+}
+
+impl Texture {
+    // Contents start undefined
+    pub fn new<T: Into<Vec2i>>(gl: &Gl, size: T, filter: Filter) -> Self {
+        let mut texture = Self {
+            id: 0,
+            size: size.into(),
+            gl: gl.clone(),
+        };
+        unsafe {
+            gl::GenTextures(1, &mut texture.id);
+            gl::BindTexture(gl::TEXTURE_2D, texture.id);
+            let filter = match filter {
+                Filter::Nearest => gl::NEAREST,
+                Filter::Linear => gl::LINEAR,
+            } as GLint;
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as GLint,
+                texture.size[X],
+                texture.size[Y],
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                ptr::null(),
+            );
+        }
+        texture
+    }
+
+    pub fn from_image(gl: &Gl, image: &Image, filter: Filter) -> Self {
+        let size = [image.width() as i32, image.height() as i32];
+        let mut texture = Self::new(gl, size, filter);
+        texture.upload(image);
+        texture
+    }
+
+    // Replaces the contents, resizing to the image
+    pub fn upload(&mut self, image: &Image) {
+        self.size = [image.width() as i32, image.height() as i32].into();
+        let bytes: Vec<u8> = image
+            .pixels()
+            .iter()
+            .flat_map(|c| [c.r, c.g, c.b, c.a])
+            .collect();
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as GLint,
+                self.size[X],
+                self.size[Y],
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                bytes.as_ptr() as *const _,
+            );
+        }
+    }
+
+    pub fn size(&self) -> Vec2i {
+        self.size
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn bind(&self, unit: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_2D, self.id);
+        }
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        self.gl
+            .with_current(|| unsafe { gl::DeleteTextures(1, &self.id) });
+    }
+}
+
+// An offscreen color texture and depth buffer to draw into
+pub struct RenderTarget {
+    framebuffer: GLuint,
+    depth: GLuint,
+    color: Texture,
+    gl: Gl,
+}
+
+impl RenderTarget {
+    pub fn new<T: Into<Vec2i>>(gl: &Gl, size: T, filter: Filter) -> Result<Self> {
+        let color = Texture::new(gl, size, filter);
+        let size = color.size();
+        let mut target = Self {
+            framebuffer: 0,
+            depth: 0,
+            color,
+            gl: gl.clone(),
+        };
+        unsafe {
+            gl::GenFramebuffers(1, &mut target.framebuffer);
+            gl::GenRenderbuffers(1, &mut target.depth);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, target.depth);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT24, size[X], size[Y]);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, target.framebuffer);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                target.color.id,
+                0,
+            );
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                gl::RENDERBUFFER,
+                target.depth,
+            );
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err(Error::Render(format!(
+                    "framebuffer incomplete (0x{:x})",
+                    status
+                )));
+            }
+        }
+        Ok(target)
+    }
+
+    // Draws go here until unbind, with the viewport set to fit
+    pub fn bind(&self) {
+        let size = self.color.size();
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            gl::Viewport(0, 0, size[X], size[Y]);
+        }
+    }
+
+    // Back to the window, whose size the viewport is set back to
+    pub fn unbind(&self, window_size: Vec2i) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, window_size[X], window_size[Y]);
+        }
+    }
+
+    pub fn color(&self) -> &Texture {
+        &self.color
+    }
+
+    pub fn size(&self) -> Vec2i {
+        self.color.size()
+    }
+}
+
+impl Drop for RenderTarget {
+    fn drop(&mut self) {
+        self.gl.with_current(|| unsafe {
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteRenderbuffers(1, &self.depth);
+        });
+    }
+}
+
+#[test]
+fn test_uniform_packing() {
+    let red = ColorRGBA::from_rgba(255, 0, 0, 51);
+    assert!(red.as_uniform() == Uniform::Vec4([1.0, 0.0, 0.0, 0.2]));
+    assert!(true.as_uniform() == Uniform::Int(1));
+    assert!(Vec3f::from([1.0, 2.0, 3.0]).as_uniform() == Uniform::Vec3([1.0, 2.0, 3.0]));
+    assert!(Vec2i::from([4, 5]).as_uniform() == Uniform::IVec2([4, 5]));
+
+    // Column major, translation in the last column
+    let m = Mat3f::translation([5.0, 6.0, 7.0].into()).as_uniform();
+    let Uniform::Mat4(m) = m else { panic!() };
+    assert!(m[12..16] == [5.0, 6.0, 7.0, 1.0] && m[0] == 1.0 && m[1] == 0.0);
+
+    let mut r = Mat2f::identity();
+    r.z = [2.0, 3.0, 1.0].into();
+    let Uniform::Mat3(m) = r.as_uniform() else {
+        panic!()
+    };
+    assert!(m == [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 2.0, 3.0, 1.0]);
+}
+
+#[test]
+fn test_vertex_layout() {
+    let layout = VertexLayout::new()
+        .push(3, AttributeType::Float, false)
+        .push(4, AttributeType::UnsignedByte, true)
+        .push(2, AttributeType::Float, false);
+    let offsets: Vec<_> = layout.attributes().iter().map(|a| a.offset).collect();
+    assert!(offsets == [0, 12, 16] && layout.stride() == 24);
+
+    // A float after a lone byte is pushed up to its alignment, and the
+    // stride rounds up to 4
+    let layout = VertexLayout::new()
+        .push(1, AttributeType::UnsignedByte, false)
+        .push(1, AttributeType::Float, false)
+        .push(1, AttributeType::UnsignedShort, false);
+    let offsets: Vec<_> = layout.attributes().iter().map(|a| a.offset).collect();
+    assert!(offsets == [0, 4, 8] && layout.stride() == 12);
+
+    // Only attributes pushed as integers skip the float conversion, the
+    // stored type alone doesn't decide it
+    let layout = VertexLayout::new()
+        .push(2, AttributeType::Short, false)
+        .push_integer(1, AttributeType::UnsignedInt)
+        .push(4, AttributeType::UnsignedByte, true);
+    let kinds: Vec<_> = layout.attributes().iter().map(|a| a.integer).collect();
+    assert!(kinds == [false, true, false]);
+    assert!(layout.attributes()[1].offset == 4 && layout.stride() == 12);
+}
+
+#[test]
+fn test_parse_log() {
+    let log = "0:12(5): error: `x' undeclared\n\
+               0(7) : error C1008: undefined variable \"y\"\n\
+               ERROR: 0:3: 'z' : undeclared identifier\n\
+               WARNING: 0:9: precision lost\n\
+               \n\
+               Linking failed";
+    let messages = parse_log(log);
+    let lines: Vec<_> = messages.iter().map(|m| m.line).collect();
+    assert!(lines == [Some(12), Some(7), Some(3), Some(9), None]);
+    assert!(messages[0].message == "error: `x' undeclared");
+    assert!(messages[1].message == "error C1008: undefined variable \"y\"");
+    assert!(messages[2].message == "error: 'z' : undeclared identifier");
+    assert!(messages[3].to_string() == "line 9: warning: precision lost");
+    assert!(messages[4].to_string() == "Linking failed");
+}
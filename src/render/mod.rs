@@ -0,0 +1,74 @@
+pub mod atlas;
+pub mod command;
+pub mod framebuffer;
+pub mod gl;
+pub mod sprite;
+
+use crate::math::color::ColorRGBA;
+use crate::math::vector::*;
+
+// How a drawn color combines with what is already there
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Blend {
+    Replace,
+    // Source over, weighted by the source alpha
+    #[default]
+    Alpha,
+    Additive,
+}
+
+impl Blend {
+    pub fn apply(self, dst: ColorRGBA, src: ColorRGBA) -> ColorRGBA {
+        match self {
+            Blend::Replace => src,
+            Blend::Alpha => {
+                let a = src.a as u32;
+                let mix = |s: u8, d: u8| ((s as u32 * a + d as u32 * (255 - a) + 127) / 255) as u8;
+                ColorRGBA::from_rgba(
+                    mix(src.r, dst.r),
+                    mix(src.g, dst.g),
+                    mix(src.b, dst.b),
+                    (a + (dst.a as u32 * (255 - a) + 127) / 255) as u8,
+                )
+            }
+            Blend::Additive => {
+                let a = src.a as u32;
+                let add = |s: u8, d: u8| (d as u32 + (s as u32 * a + 127) / 255).min(255) as u8;
+                ColorRGBA::from_rgba(
+                    add(src.r, dst.r),
+                    add(src.g, dst.g),
+                    add(src.b, dst.b),
+                    dst.a.max(src.a),
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+    pub position: Vec3f,
+    pub color: ColorRGBA,
+    pub uv: Vec2f,
+}
+
+impl Vertex {
+    pub fn new<P: Into<Vec3f>, U: Into<Vec2f>>(position: P, color: ColorRGBA, uv: U) -> Self {
+        Self {
+            position: position.into(),
+            color,
+            uv: uv.into(),
+        }
+    }
+}
+
+#[test]
+fn test_blend() {
+    let dst = ColorRGBA::from_rgba(0, 0, 200, 255);
+    let src = ColorRGBA::from_rgba(255, 0, 0, 128);
+    assert!(Blend::Replace.apply(dst, src) == src);
+    assert!(Blend::Alpha.apply(dst, src) == ColorRGBA::from_rgba(128, 0, 100, 255));
+    assert!(Blend::Additive.apply(dst, src) == ColorRGBA::from_rgba(128, 0, 200, 255));
+    let opaque = ColorRGBA::from_rgba(1, 2, 3, 255);
+    assert!(Blend::Alpha.apply(dst, opaque) == opaque);
+}
@@ -0,0 +1,261 @@
+use crate::math::angle::Radians;
+use crate::math::color::ColorRGBA;
+use crate::math::matrix::*;
+use crate::math::vector::*;
+use crate::render::command::{Backend, Batch, Command, TextureId, QUAD_INDICES};
+use crate::render::Vertex;
+
+// A sprite that can turn and scale about its origin, in camera space with
+// y down. Positive rotation turns clockwise on screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quad {
+    pub texture: Option<TextureId>,
+    // Where the origin lands
+    pub position: Vec2f,
+    pub size: Vec2f,
+    // The point rotated and scaled about, from the top left before scaling
+    pub origin: Vec2f,
+    pub rotation: Radians<f32>,
+    pub scale: Vec2f,
+    // Top left and bottom right of the texture to show
+    pub uv: (Vec2f, Vec2f),
+    pub color: ColorRGBA,
+    pub layer: i32,
+}
+
+impl Quad {
+    pub fn new<T: Into<Vec2f>>(position: T, size: T) -> Self {
+        Self {
+            texture: None,
+            position: position.into(),
+            size: size.into(),
+            origin: [0.0, 0.0].into(),
+            rotation: Radians(0.0),
+            scale: [1.0, 1.0].into(),
+            uv: ([0.0, 0.0].into(), [1.0, 1.0].into()),
+            color: ColorRGBA::from_rgba(255, 255, 255, 255),
+            layer: 0,
+        }
+    }
+
+    pub fn set_texture(mut self, texture: TextureId) -> Self {
+        self.texture = Some(texture);
+        self
+    }
+
+    pub fn set_origin<T: Into<Vec2f>>(mut self, origin: T) -> Self {
+        self.origin = origin.into();
+        self
+    }
+
+    // Puts the origin in the middle
+    pub fn centered(mut self) -> Self {
+        self.origin = self.size / 2.0;
+        self
+    }
+
+    pub fn set_rotation<A: Into<Radians<f32>>>(mut self, rotation: A) -> Self {
+        self.rotation = rotation.into();
+        self
+    }
+
+    pub fn set_scale<T: Into<Vec2f>>(mut self, scale: T) -> Self {
+        self.scale = scale.into();
+        self
+    }
+
+    pub fn set_uv<T: Into<Vec2f>>(mut self, min: T, max: T) -> Self {
+        self.uv = (min.into(), max.into());
+        self
+    }
+
+    pub fn set_color(mut self, color: ColorRGBA) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn set_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    // From the quad's own space, with the top left at 0 0, to camera space
+    pub fn transform(&self) -> Mat2f {
+        Mat2f::translation(self.position)
+            * Mat2f::rotation(self.rotation)
+            * Mat2f::scale(self.scale)
+            * Mat2f::translation(-self.origin)
+    }
+
+    // Top left, top right, bottom right, bottom left like Sprite::vertices
+    pub fn vertices(&self) -> [Vertex; 4] {
+        let m = self.transform();
+        let (s, (a, b)) = (self.size, self.uv);
+        let v = |x: f32, y: f32, u: f32, w: f32| {
+            let p = m * Vec3f::from([x, y, 1.0]);
+            Vertex::new([p[X], p[Y], 0.0], self.color, [u, w])
+        };
+        [
+            v(0.0, 0.0, a[X], a[Y]),
+            v(s[X], 0.0, b[X], a[Y]),
+            v(s[X], s[Y], b[X], b[Y]),
+            v(0.0, s[Y], a[X], b[Y]),
+        ]
+    }
+}
+
+// Collects quads over a frame and turns them into a few large draws, one
+// per run of quads sharing a layer and texture
+#[derive(Debug, Clone)]
+pub struct SpriteBatch {
+    quads: Vec<Quad>,
+    max_quads: usize,
+}
+
+impl Default for SpriteBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpriteBatch {
+    pub fn new() -> Self {
+        Self {
+            quads: Vec::new(),
+            max_quads: 16384,
+        }
+    }
+
+    // The most quads in one draw, longer runs are split
+    pub fn set_max_quads(mut self, max_quads: usize) -> Self {
+        self.max_quads = max_quads.max(1);
+        self
+    }
+
+    pub fn push(&mut self, quad: Quad) {
+        self.quads.push(quad);
+    }
+
+    pub fn len(&self) -> usize {
+        self.quads.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.quads.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.quads.clear();
+    }
+
+    // Ordered by layer then texture. Quads with both the same keep the order
+    // they were pushed.
+    pub fn batches(&self) -> Vec<Batch> {
+        let mut order: Vec<&Quad> = self.quads.iter().collect();
+        order.sort_by_key(|q| (q.layer, q.texture));
+
+        let mut batches: Vec<Batch> = Vec::new();
+        for quad in order {
+            let fits = batches.last().is_some_and(|b| {
+                b.layer == quad.layer
+                    && b.texture == quad.texture
+                    && b.vertices.len() < self.max_quads * 4
+            });
+            if !fits {
+                batches.push(Batch {
+                    texture: quad.texture,
+                    layer: quad.layer,
+                    ..Batch::default()
+                });
+            }
+            let batch = batches.last_mut().unwrap();
+            let first = batch.vertices.len() as u32;
+            batch.vertices.extend_from_slice(&quad.vertices());
+            batch.indices.extend(QUAD_INDICES.map(|i| first + i));
+        }
+        batches
+    }
+
+    // Draws everything pushed since the last flush, then empties the batch
+    pub fn flush<B: Backend + ?Sized>(&mut self, backend: &mut B) {
+        for batch in self.batches() {
+            backend.execute(&Command::Batch(batch));
+        }
+        self.clear();
+    }
+}
+
+#[test]
+fn test_quad_vertices() {
+    use crate::math::angle::Degrees;
+
+    let near = |v: &Vertex, x: f32, y: f32| {
+        (v.position[X] - x).abs() < 1e-4 && (v.position[Y] - y).abs() < 1e-4
+    };
+
+    let quad = Quad::new([10.0, 20.0], [4.0, 2.0]).set_uv([0.5, 0.0], [1.0, 0.5]);
+    let v = quad.vertices();
+    assert!(near(&v[0], 10.0, 20.0) && near(&v[2], 14.0, 22.0));
+    assert!(v[1].uv == [1.0, 0.0] && v[3].uv == [0.5, 0.5]);
+
+    // Turning a quarter about the center, the top left corner swings round
+    // to the top right
+    let quad = Quad::new([10.0, 10.0], [2.0, 2.0])
+        .centered()
+        .set_rotation(Degrees(90.0));
+    let v = quad.vertices();
+    assert!(near(&v[0], 11.0, 9.0) && near(&v[1], 11.0, 11.0));
+    assert!(near(&v[2], 9.0, 11.0) && near(&v[3], 9.0, 9.0));
+
+    // Scaling about the bottom middle keeps it on the ground
+    let quad = Quad::new([0.0, 0.0], [2.0, 4.0])
+        .set_origin([1.0, 4.0])
+        .set_scale([2.0, 0.5]);
+    let v = quad.vertices();
+    assert!(near(&v[0], -2.0, -2.0) && near(&v[2], 2.0, 0.0));
+}
+
+#[test]
+fn test_sprite_batch() {
+    use crate::render::command::{RecordingBackend, SoftwareBackend};
+
+    let quad = |layer: i32, texture: u32| {
+        Quad::new([0.0, 0.0], [1.0, 1.0])
+            .set_texture(TextureId(texture))
+            .set_layer(layer)
+    };
+    let mut batch = SpriteBatch::new().set_max_quads(2);
+    for q in [quad(1, 0), quad(0, 3), quad(0, 1), quad(0, 3), quad(0, 3)] {
+        batch.push(q);
+    }
+    let batches = batch.batches();
+    let keys: Vec<_> = batches
+        .iter()
+        .map(|b| (b.layer, b.texture.unwrap().0, b.vertices.len()))
+        .collect();
+    // Three quads on texture 3 split over two draws
+    assert!(keys == [(0, 1, 4), (0, 3, 8), (0, 3, 4), (1, 0, 4)]);
+    assert!(batches[1].indices == [0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7]);
+
+    let mut recorder = RecordingBackend::new();
+    batch.flush(&mut recorder);
+    assert!(recorder.draw_count() == 4 && batch.is_empty());
+
+    // Drawn for real, the later layer on top
+    let red = ColorRGBA::from_rgba(255, 0, 0, 255);
+    let blue = ColorRGBA::from_rgba(0, 0, 255, 255);
+    let mut backend = SoftwareBackend::new([16, 16]);
+    let mut batch = SpriteBatch::new();
+    batch.push(
+        Quad::new([8.0, 8.0], [8.0, 8.0])
+            .centered()
+            .set_color(blue)
+            .set_layer(1),
+    );
+    batch.push(Quad::new([0.0, 0.0], [16.0, 16.0]).set_color(red));
+    batch.flush(&mut backend);
+    let fb = backend.framebuffer();
+    assert!(fb.get([0, 0]) == Some(red) && fb.get([15, 15]) == Some(red));
+    assert!(fb.get([4, 4]) == Some(blue) && fb.get([11, 11]) == Some(blue));
+    assert!(fb.get([3, 8]) == Some(red));
+}
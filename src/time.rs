@@ -0,0 +1,315 @@
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::math::integrate::Accumulator;
+
+// Seconds since some fixed point, only differences matter
+pub trait Clock {
+    fn now(&self) -> f64;
+}
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+}
+
+// A clock that only moves when told to. Clones share the same time, so a
+// test can keep one and hand the other to the loop.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    time: Rc<Cell<f64>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, seconds: f64) {
+        self.time.set(self.time.get() + seconds);
+    }
+
+    pub fn set(&self, time: f64) {
+        self.time.set(time);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> f64 {
+        self.time.get()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Time {
+    // Scaled, zero while paused
+    pub delta: f64,
+    pub unscaled_delta: f64,
+    // Scaled time the game has been running
+    pub total: f64,
+    pub real_total: f64,
+    pub frame: u64,
+    pub scale: f64,
+    pub paused: bool,
+    // Length of each fixed update
+    pub fixed_delta: f64,
+    // How far between the last fixed update and the next one we are, for
+    // interpolating rendered positions
+    pub alpha: f64,
+}
+
+// Frame times of the last few frames
+#[derive(Debug, Clone)]
+pub struct FrameStats {
+    samples: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl FrameStats {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn push(&mut self, frame_time: f64) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(frame_time);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn average(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.samples.iter().sum::<f64>() / self.samples.len() as f64
+    }
+
+    pub fn fps(&self) -> f64 {
+        let average = self.average();
+        if average > 0.0 {
+            1.0 / average
+        } else {
+            0.0
+        }
+    }
+
+    pub fn min(&self) -> f64 {
+        self.samples.iter().copied().reduce(f64::min).unwrap_or(0.0)
+    }
+
+    pub fn max(&self) -> f64 {
+        self.samples.iter().copied().reduce(f64::max).unwrap_or(0.0)
+    }
+
+    // Nearest rank, so percentile(0.99) is the frame time 99% of frames
+    // beat, the usual measure of stutter
+    pub fn percentile(&self, p: f64) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        let mut sorted: Vec<f64> = self.samples.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let rank = (p.clamp(0.0, 1.0) * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+}
+
+// Call `begin_frame` at the top of each frame and run that many fixed
+// updates, then render with `time().alpha`.
+pub struct GameLoop<C: Clock = SystemClock> {
+    clock: C,
+    last: Option<f64>,
+    step: Accumulator<f64>,
+    time: Time,
+    stats: FrameStats,
+    // Frame times are clamped to this, so a breakpoint or a dragged window
+    // doesn't come back as one huge frame
+    pub max_frame_time: f64,
+}
+
+impl GameLoop<SystemClock> {
+    pub fn new(fixed_delta: f64) -> Self {
+        Self::with_clock(SystemClock::new(), fixed_delta)
+    }
+}
+
+impl<C: Clock> GameLoop<C> {
+    pub fn with_clock(clock: C, fixed_delta: f64) -> Self {
+        Self {
+            clock,
+            last: None,
+            step: Accumulator::new(fixed_delta),
+            time: Time {
+                delta: 0.0,
+                unscaled_delta: 0.0,
+                total: 0.0,
+                real_total: 0.0,
+                frame: 0,
+                scale: 1.0,
+                paused: false,
+                fixed_delta,
+                alpha: 0.0,
+            },
+            stats: FrameStats::new(120),
+            max_frame_time: 0.25,
+        }
+    }
+
+    // Fixed updates allowed per frame before the backlog is dropped, so a
+    // slow machine doesn't spiral
+    pub fn set_max_steps(mut self, max_steps: usize) -> Self {
+        self.step.max_steps = max_steps;
+        self
+    }
+
+    pub fn set_max_frame_time(mut self, seconds: f64) -> Self {
+        self.max_frame_time = seconds;
+        self
+    }
+
+    pub fn set_stats_capacity(mut self, frames: usize) -> Self {
+        self.stats = FrameStats::new(frames);
+        self
+    }
+
+    pub fn time(&self) -> &Time {
+        &self.time
+    }
+
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    pub fn set_time_scale(&mut self, scale: f64) {
+        self.time.scale = scale.max(0.0);
+    }
+
+    pub fn pause(&mut self) {
+        self.time.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.time.paused = false;
+    }
+
+    // Returns how many fixed updates to run this frame. The first frame has
+    // no delta.
+    pub fn begin_frame(&mut self) -> usize {
+        let now = self.clock.now();
+        let raw = self.last.map_or(0.0, |last| (now - last).max(0.0));
+        self.last = Some(now);
+        if self.time.frame > 0 {
+            self.stats.push(raw);
+        }
+
+        let unscaled = raw.min(self.max_frame_time);
+        let scale = if self.time.paused {
+            0.0
+        } else {
+            self.time.scale
+        };
+        self.time.frame += 1;
+        self.time.unscaled_delta = unscaled;
+        self.time.real_total += unscaled;
+        self.time.delta = unscaled * scale;
+        self.time.total += self.time.delta;
+
+        let steps = self.step.steps(self.time.delta);
+        self.time.alpha = self.step.alpha();
+        steps
+    }
+
+    // Runs the frame's fixed updates, handy when the update needs nothing
+    // else borrowed
+    pub fn tick<F: FnMut(&Time)>(&mut self, mut fixed_update: F) -> usize {
+        let steps = self.begin_frame();
+        for _ in 0..steps {
+            fixed_update(&self.time);
+        }
+        steps
+    }
+}
+
+#[test]
+fn test_game_loop_steps() {
+    let clock = ManualClock::new();
+    let mut game = GameLoop::with_clock(clock.clone(), 0.25)
+        .set_max_steps(4)
+        .set_max_frame_time(1.0);
+
+    assert!(game.begin_frame() == 0 && game.time().delta == 0.0);
+
+    clock.advance(0.6);
+    let mut updates = 0;
+    assert!(game.tick(|_| updates += 1) == 2 && updates == 2);
+    assert!((game.time().alpha - 0.4).abs() < 1e-9);
+    assert!((game.time().total - 0.6).abs() < 1e-9);
+
+    // A long hitch is clamped by max_frame_time, then by max_steps
+    clock.advance(10.0);
+    assert!(game.begin_frame() == 4);
+    assert!(game.time().unscaled_delta == 1.0);
+    let mut game = game.set_max_frame_time(10.0);
+    clock.advance(10.0);
+    assert!(game.begin_frame() == 4 && game.time().alpha == 0.0);
+
+    // Slow motion and pausing only touch scaled time
+    game.set_time_scale(0.5);
+    clock.advance(1.0);
+    assert!(game.begin_frame() == 2);
+    assert!(game.time().delta == 0.5 && game.time().unscaled_delta == 1.0);
+    game.pause();
+    clock.advance(1.0);
+    let total = game.time().total;
+    assert!(game.begin_frame() == 0 && game.time().total == total);
+    game.resume();
+    assert!(game.time().frame == 6);
+}
+
+#[test]
+fn test_frame_stats() {
+    let mut stats = FrameStats::new(4);
+    assert!(stats.fps() == 0.0 && stats.percentile(0.5) == 0.0);
+    for t in [0.5, 0.01, 0.02, 0.03, 0.04] {
+        stats.push(t);
+    }
+    // The oldest sample fell out
+    assert!(stats.len() == 4 && stats.max() == 0.04 && stats.min() == 0.01);
+    assert!((stats.average() - 0.025).abs() < 1e-12);
+    assert!((stats.fps() - 40.0).abs() < 1e-9);
+    assert!(stats.percentile(0.5) == 0.02);
+    assert!(stats.percentile(0.99) == 0.04);
+    assert!(stats.percentile(0.0) == 0.01);
+}
@@ -1,25 +1,458 @@
+use std::rc::Rc;
 use std::sync::mpsc::Receiver;
 
 use glfw::{Context, FlushedMessages};
 
+use crate::error::Result;
+use crate::input::gamepad::{RawJoystick, MAX_JOYSTICKS};
+use crate::math::color::ColorRGBA;
+use crate::math::pixel::{self, PixelFormat};
 use crate::math::vector::*;
+use crate::render::gl::{Gl, GlContext};
 
 pub type Event = glfw::WindowEvent;
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowError {
+    Init(glfw::InitError),
+    // Index into the connected monitors that doesn't exist
+    NoMonitor(usize),
+    InvalidSize(Vec2i),
+    // Pixel count that doesn't match the image size
+    InvalidImage(Vec2i, usize),
+    // GLFW couldn't make the window or context, usually no display or an
+    // OpenGL version the driver doesn't support
+    Create,
+}
+
+impl std::fmt::Display for WindowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WindowError::Init(e) => write!(f, "failed to init GLFW: {}", e),
+            WindowError::NoMonitor(i) => write!(f, "no monitor at index {}", i),
+            WindowError::InvalidSize(s) => write!(f, "invalid window size {}", s),
+            WindowError::InvalidImage(s, len) => {
+                write!(f, "{} pixels given for a {} image", len, s)
+            }
+            WindowError::Create => write!(f, "failed to create window"),
+        }
+    }
+}
+
+impl std::error::Error for WindowError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorMode {
+    Normal,
+    // Invisible over the window but free to leave it
+    Hidden,
+    // Invisible and locked to the window with unbounded movement, for
+    // mouse look. Uses raw motion where the platform has it.
+    Captured,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    Arrow,
+    IBeam,
+    Crosshair,
+    Hand,
+    HResize,
+    VResize,
+}
+
+// Pixels for cursors and icons, row major from the top left
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<ColorRGBA>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, pixels: Vec<ColorRGBA>) -> Result<Self> {
+        // In u64 so huge sizes are an error rather than an overflow
+        let len = width as u64 * height as u64;
+        if len == 0 || pixels.len() as u64 != len {
+            let size = [width as i32, height as i32].into();
+            return Err(WindowError::InvalidImage(size, pixels.len()).into());
+        }
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[ColorRGBA] {
+        &self.pixels
+    }
+
+    // GLFW wants RGBA bytes in memory order packed into u32s
+    fn to_glfw(&self) -> glfw::PixelImage {
+        let bytes = pixel::from_rgba(&self.pixels, PixelFormat::Rgba8);
+        glfw::PixelImage {
+            width: self.width,
+            height: self.height,
+            pixels: bytes
+                .chunks_exact(4)
+                .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+                .collect(),
+        }
+    }
+}
+
+// Which units positions and sizes in window events use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coordinates {
+    // What GLFW reports, pixels on most platforms but points on macOS
+    Screen,
+    // Framebuffer pixels, for drawing
+    Physical,
+    // Physical pixels divided by the content scale, so UI laid out in them
+    // is the same size on every monitor
+    Logical,
+}
+
+// How screen coordinates, framebuffer pixels and the OS scale setting relate
+// for a window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dpi {
+    pub window_size: Vec2i,
+    pub framebuffer_size: Vec2i,
+    pub content_scale: Vec2f,
+}
+
+impl Dpi {
+    pub fn new<A: Into<Vec2i>, B: Into<Vec2i>, C: Into<Vec2f>>(
+        window_size: A,
+        framebuffer_size: B,
+        content_scale: C,
+    ) -> Self {
+        Self {
+            window_size: window_size.into(),
+            framebuffer_size: framebuffer_size.into(),
+            content_scale: content_scale.into(),
+        }
+    }
+
+    // Framebuffer pixels per screen coordinate, 2 on a retina Mac
+    pub fn pixel_ratio(&self) -> Vec2d {
+        let ratio = |fb: i32, window: i32| {
+            if window > 0 && fb > 0 {
+                fb as f64 / window as f64
+            } else {
+                1.0
+            }
+        };
+        [
+            ratio(self.framebuffer_size[X], self.window_size[X]),
+            ratio(self.framebuffer_size[Y], self.window_size[Y]),
+        ]
+        .into()
+    }
+
+    fn scale(&self) -> Vec2d {
+        let scale = |s: f32| if s > 0.0 { s as f64 } else { 1.0 };
+        [scale(self.content_scale[X]), scale(self.content_scale[Y])].into()
+    }
+
+    pub fn logical_size(&self) -> Vec2d {
+        let scale = self.scale();
+        [
+            self.framebuffer_size[X] as f64 / scale[X],
+            self.framebuffer_size[Y] as f64 / scale[Y],
+        ]
+        .into()
+    }
+
+    pub fn to_physical(&self, screen: Vec2d) -> Vec2d {
+        let ratio = self.pixel_ratio();
+        [screen[X] * ratio[X], screen[Y] * ratio[Y]].into()
+    }
+
+    pub fn to_logical(&self, screen: Vec2d) -> Vec2d {
+        let physical = self.to_physical(screen);
+        let scale = self.scale();
+        [physical[X] / scale[X], physical[Y] / scale[Y]].into()
+    }
+
+    // Back to screen coordinates, e.g. to place the cursor
+    pub fn from_logical(&self, logical: Vec2d) -> Vec2d {
+        let ratio = self.pixel_ratio();
+        let scale = self.scale();
+        [
+            logical[X] * scale[X] / ratio[X],
+            logical[Y] * scale[Y] / ratio[Y],
+        ]
+        .into()
+    }
+
+    fn convert(&self, screen: Vec2d, to: Coordinates) -> Vec2d {
+        match to {
+            Coordinates::Screen => screen,
+            Coordinates::Physical => self.to_physical(screen),
+            Coordinates::Logical => self.to_logical(screen),
+        }
+    }
+
+    // Rewrites cursor positions and sizes into the given units. Scrolling
+    // and window positions on the desktop are left alone.
+    pub fn convert_event(&self, event: Event, to: Coordinates) -> Event {
+        match event {
+            Event::CursorPos(x, y) => {
+                let p = self.convert([x, y].into(), to);
+                Event::CursorPos(p[X], p[Y])
+            }
+            Event::Size(w, h) => {
+                let s = self.convert([w as f64, h as f64].into(), to);
+                Event::Size(s[X].round() as i32, s[Y].round() as i32)
+            }
+            Event::FramebufferSize(w, h) if to == Coordinates::Logical => {
+                let scale = self.scale();
+                Event::FramebufferSize(
+                    (w as f64 / scale[X]).round() as i32,
+                    (h as f64 / scale[Y]).round() as i32,
+                )
+            }
+            event => event,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlProfile {
+    Any,
+    Core,
+    Compat,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowBuilder {
+    title: String,
+    size: Vec2i,
+    pos: Option<Vec2i>,
+    resizable: bool,
+    decorated: bool,
+    swap_interval: u32,
+    // Index into the connected monitors, 0 is the primary
+    fullscreen: Option<usize>,
+    samples: Option<u32>,
+    gl_version: Option<(u32, u32)>,
+    gl_profile: GlProfile,
+    transparent: bool,
+    scale_to_monitor: bool,
+    coordinates: Coordinates,
+}
+
+impl Default for WindowBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WindowBuilder {
+    pub fn new() -> Self {
+        Self {
+            title: "Window".to_string(),
+            size: [800, 600].into(),
+            pos: None,
+            resizable: true,
+            decorated: true,
+            swap_interval: 1,
+            fullscreen: None,
+            samples: None,
+            gl_version: None,
+            gl_profile: GlProfile::Any,
+            transparent: false,
+            scale_to_monitor: false,
+            coordinates: Coordinates::Screen,
+        }
+    }
+
+    pub fn set_title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    pub fn set_size<T: Into<Vec2i>>(mut self, size: T) -> Self {
+        self.size = size.into();
+        self
+    }
+
+    pub fn set_pos<T: Into<Vec2i>>(mut self, pos: T) -> Self {
+        self.pos = Some(pos.into());
+        self
+    }
+
+    pub fn set_resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+
+    pub fn set_decorated(mut self, decorated: bool) -> Self {
+        self.decorated = decorated;
+        self
+    }
+
+    pub fn set_vsync(mut self, vsync: bool) -> Self {
+        self.swap_interval = vsync as u32;
+        self
+    }
+
+    // Frames to wait between buffer swaps, 0 disables vsync
+    pub fn set_swap_interval(mut self, interval: u32) -> Self {
+        self.swap_interval = interval;
+        self
+    }
+
+    // The window takes the monitor's closest video mode to its size
+    pub fn set_fullscreen(mut self, monitor: usize) -> Self {
+        self.fullscreen = Some(monitor);
+        self
+    }
+
+    pub fn set_windowed(mut self) -> Self {
+        self.fullscreen = None;
+        self
+    }
+
+    // MSAA samples, 0 turns multisampling off
+    pub fn set_samples(mut self, samples: u32) -> Self {
+        self.samples = Some(samples);
+        self
+    }
+
+    pub fn set_gl_version(mut self, major: u32, minor: u32) -> Self {
+        self.gl_version = Some((major, minor));
+        self
+    }
+
+    pub fn set_gl_profile(mut self, profile: GlProfile) -> Self {
+        self.gl_profile = profile;
+        self
+    }
+
+    pub fn set_transparent(mut self, transparent: bool) -> Self {
+        self.transparent = transparent;
+        self
+    }
+
+    // Resizes the window by the monitor's content scale on Windows and X11,
+    // so the size given is in logical pixels
+    pub fn set_scale_to_monitor(mut self, scale: bool) -> Self {
+        self.scale_to_monitor = scale;
+        self
+    }
+
+    pub fn set_coordinates(mut self, coordinates: Coordinates) -> Self {
+        self.coordinates = coordinates;
+        self
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn size(&self) -> Vec2i {
+        self.size
+    }
+
+    pub fn build(self) -> Result<Window> {
+        if self.size[X] <= 0 || self.size[Y] <= 0 {
+            return Err(WindowError::InvalidSize(self.size).into());
+        }
+
+        let mut glfw = glfw::init(glfw::LOG_ERRORS).map_err(WindowError::Init)?;
+
+        glfw.default_window_hints();
+        glfw.window_hint(glfw::WindowHint::Resizable(self.resizable));
+        glfw.window_hint(glfw::WindowHint::Decorated(self.decorated));
+        glfw.window_hint(glfw::WindowHint::TransparentFramebuffer(self.transparent));
+        glfw.window_hint(glfw::WindowHint::ScaleToMonitor(self.scale_to_monitor));
+        if let Some(samples) = self.samples {
+            let samples = if samples == 0 { None } else { Some(samples) };
+            glfw.window_hint(glfw::WindowHint::Samples(samples));
+        }
+        if let Some((major, minor)) = self.gl_version {
+            glfw.window_hint(glfw::WindowHint::ContextVersion(major, minor));
+        }
+        match self.gl_profile {
+            GlProfile::Any => {}
+            GlProfile::Core => {
+                glfw.window_hint(glfw::WindowHint::OpenGlProfile(
+                    glfw::OpenGlProfileHint::Core,
+                ));
+                // macOS only hands out core contexts that are forward compatible
+                glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
+            }
+            GlProfile::Compat => glfw.window_hint(glfw::WindowHint::OpenGlProfile(
+                glfw::OpenGlProfileHint::Compat,
+            )),
+        }
+
+        let (width, height) = (self.size[X] as u32, self.size[Y] as u32);
+        let created = match self.fullscreen {
+            Some(index) => glfw.with_connected_monitors(|glfw, monitors| {
+                monitors
+                    .get(index)
+                    .ok_or(WindowError::NoMonitor(index))
+                    .map(|m| {
+                        glfw.create_window(
+                            width,
+                            height,
+                            &self.title,
+                            glfw::WindowMode::FullScreen(m),
+                        )
+                    })
+            })?,
+            None => glfw.create_window(width, height, &self.title, glfw::WindowMode::Windowed),
+        };
+        let (mut handle, events) = created.ok_or(WindowError::Create)?;
+
+        if let (Some(pos), None) = (self.pos, self.fullscreen) {
+            handle.set_pos(pos[X], pos[Y]);
+        }
+        handle.make_current();
+        handle.set_all_polling(true);
+
+        let interval = match self.swap_interval {
+            0 => glfw::SwapInterval::None,
+            n => glfw::SwapInterval::Sync(n),
+        };
+        handle.glfw.set_swap_interval(interval);
+
+        Ok(Window {
+            context: Rc::new(GlContext::new(handle.window_ptr())),
+            handle,
+            events,
+            coordinates: self.coordinates,
+        })
+    }
+}
+
 pub struct Window {
     handle: glfw::Window,
+    context: Rc<GlContext>,
     events: Receiver<(f64, Event)>,
+    coordinates: Coordinates,
 }
 
 impl Window {
-    pub fn new() -> Self {
-        let init = glfw::init(glfw::FAIL_ON_ERRORS).expect("Failed to init system");
-        let (mut handle, events) = init
-            .create_window(800, 600, "Window", glfw::WindowMode::Windowed)
-            .expect("Failed to create window");
-        handle.make_current();
-        handle.set_all_polling(true);
-        Self { handle, events }
+    pub fn new() -> Result<Self> {
+        WindowBuilder::new().build()
+    }
+
+    pub fn builder() -> WindowBuilder {
+        WindowBuilder::new()
     }
 
     pub fn set_size<T: Into<Vec2i>>(mut self, size: T) -> Self {
@@ -34,36 +467,252 @@ impl Window {
         self
     }
 
+    pub fn size(&self) -> Vec2i {
+        let (width, height) = self.handle.get_size();
+        [width, height].into()
+    }
+
+    pub fn framebuffer_size(&self) -> Vec2i {
+        let (width, height) = self.handle.get_framebuffer_size();
+        [width, height].into()
+    }
+
+    // The OS scale setting for the monitor the window is on, 1.5 for 150%.
+    // Changes arrive as Event::ContentScale.
+    pub fn content_scale(&self) -> Vec2f {
+        let (x, y) = self.handle.get_content_scale();
+        [x, y].into()
+    }
+
+    pub fn dpi(&self) -> Dpi {
+        Dpi::new(self.size(), self.framebuffer_size(), self.content_scale())
+    }
+
+    pub fn logical_size(&self) -> Vec2d {
+        self.dpi().logical_size()
+    }
+
+    pub fn coordinates(&self) -> Coordinates {
+        self.coordinates
+    }
+
+    pub fn set_coordinates(&mut self, coordinates: Coordinates) {
+        self.coordinates = coordinates;
+    }
+
     pub fn is_open(&self) -> bool {
         self.handle.should_close() == false
     }
 
+    pub fn close(&mut self) {
+        self.handle.set_should_close(true)
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.handle.set_title(title)
+    }
+
+    pub fn set_cursor_mode(&mut self, mode: CursorMode) {
+        let raw = mode == CursorMode::Captured && self.handle.glfw.supports_raw_motion();
+        self.handle.set_cursor_mode(match mode {
+            CursorMode::Normal => glfw::CursorMode::Normal,
+            CursorMode::Hidden => glfw::CursorMode::Hidden,
+            CursorMode::Captured => glfw::CursorMode::Disabled,
+        });
+        self.handle.set_raw_mouse_motion(raw);
+    }
+
+    pub fn cursor_mode(&self) -> CursorMode {
+        match self.handle.get_cursor_mode() {
+            glfw::CursorMode::Normal => CursorMode::Normal,
+            glfw::CursorMode::Hidden => CursorMode::Hidden,
+            glfw::CursorMode::Disabled => CursorMode::Captured,
+        }
+    }
+
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        let shape = match shape {
+            CursorShape::Arrow => glfw::StandardCursor::Arrow,
+            CursorShape::IBeam => glfw::StandardCursor::IBeam,
+            CursorShape::Crosshair => glfw::StandardCursor::Crosshair,
+            CursorShape::Hand => glfw::StandardCursor::Hand,
+            CursorShape::HResize => glfw::StandardCursor::HResize,
+            CursorShape::VResize => glfw::StandardCursor::VResize,
+        };
+        self.handle.set_cursor(Some(glfw::Cursor::standard(shape)));
+    }
+
+    // The hotspot is the pixel that points, from the image's top left
+    pub fn set_cursor_image<T: Into<Vec2i>>(&mut self, image: &Image, hotspot: T) {
+        let hotspot = hotspot.into();
+        let cursor = glfw::Cursor::create_from_pixels(
+            image.to_glfw(),
+            hotspot[X].clamp(0, image.width as i32 - 1) as u32,
+            hotspot[Y].clamp(0, image.height as i32 - 1) as u32,
+        );
+        self.handle.set_cursor(Some(cursor));
+    }
+
+    pub fn reset_cursor(&mut self) {
+        self.handle.set_cursor(None);
+    }
+
+    // Several sizes can be given and the platform picks the closest, an
+    // empty list goes back to the default icon
+    pub fn set_icon(&mut self, images: &[Image]) {
+        self.handle
+            .set_icon_from_pixels(images.iter().map(|i| i.to_glfw()).collect());
+    }
+
+    pub fn clipboard(&self) -> Option<String> {
+        self.handle.get_clipboard_string()
+    }
+
+    pub fn set_clipboard(&mut self, text: &str) {
+        self.handle.set_clipboard_string(text)
+    }
+
+    pub fn minimize(&mut self) {
+        self.handle.iconify()
+    }
+
+    pub fn maximize(&mut self) {
+        self.handle.maximize()
+    }
+
+    // Undoes minimize and maximize
+    pub fn restore(&mut self) {
+        self.handle.restore()
+    }
+
+    pub fn focus(&mut self) {
+        self.handle.focus()
+    }
+
+    // Flashes the taskbar entry or bounces the dock icon
+    pub fn request_attention(&mut self) {
+        self.handle.request_attention()
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.handle.is_focused()
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        self.handle.is_iconified()
+    }
+
+    pub fn is_maximized(&self) -> bool {
+        self.handle.is_maximized()
+    }
+
+    // From 0 (invisible) to 1, for the whole window
+    pub fn set_opacity(&mut self, opacity: f32) {
+        self.handle.set_opacity(opacity.clamp(0.0, 1.0))
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.handle.get_opacity()
+    }
+
     pub fn draw(&mut self) {
         self.handle.swap_buffers()
     }
 
+    // Makes the context current on this thread and loads gl through it.
+    // The token is what the render::gl wrappers take to prove it happened.
+    pub fn load_gl(&mut self) -> Gl {
+        self.handle.make_current();
+        gl::load_with(|name| self.handle.get_proc_address(name));
+        Gl::new(self.context.clone())
+    }
+
+    // Every joystick slot, None where nothing is plugged in. Pass to
+    // `Gamepads::update` to get connect events and mapped state.
+    pub fn joysticks(&self) -> Vec<Option<RawJoystick>> {
+        (0..MAX_JOYSTICKS as i32)
+            .map(|i| {
+                let id = glfw::JoystickId::from_i32(i)?;
+                let joystick = self.handle.glfw.get_joystick(id);
+                if !joystick.is_present() {
+                    return None;
+                }
+                Some(RawJoystick {
+                    guid: joystick.get_guid().unwrap_or_default(),
+                    name: joystick.get_name().unwrap_or_default(),
+                    axes: joystick.get_axes(),
+                    buttons: joystick
+                        .get_buttons()
+                        .iter()
+                        .map(|b| *b == glfw::Action::Press as i32)
+                        .collect(),
+                    hats: joystick.get_hats().iter().map(|h| h.bits() as u8).collect(),
+                    mapped: joystick.get_gamepad_state().map(|s| s.into()),
+                })
+            })
+            .collect()
+    }
+
+    // Outside screen coordinates events are converted with the scale at the
+    // time of polling
     pub fn poll(&mut self) -> EventIter {
         self.handle.glfw.poll_events();
+        if self.coordinates != Coordinates::Screen {
+            let dpi = self.dpi();
+            let events = glfw::flush_messages(&self.events)
+                .map(|(time, event)| (time, dpi.convert_event(event, self.coordinates)))
+                .collect();
+            return EventIter::from_events(events);
+        }
         let iter = glfw::flush_messages(&self.events);
-        EventIter { events: iter }
+        EventIter {
+            source: EventSource::Glfw(iter),
+        }
+    }
+}
+
+impl Drop for Window {
+    // Runs before the handle closes, so nothing tries to delete from a
+    // context that's already gone
+    fn drop(&mut self) {
+        self.context.lose();
     }
 }
 
+enum EventSource<'a> {
+    Glfw(FlushedMessages<'a, (f64, Event)>),
+    // Events that didn't come from GLFW, like a replay
+    Buffered(std::vec::IntoIter<(f64, Event)>),
+}
+
 pub struct EventIter<'a> {
-    events: FlushedMessages<'a, (f64, Event)>,
+    source: EventSource<'a>,
+}
+
+impl EventIter<'_> {
+    pub fn from_events(events: Vec<(f64, Event)>) -> Self {
+        EventIter {
+            source: EventSource::Buffered(events.into_iter()),
+        }
+    }
 }
 
 impl Iterator for EventIter<'_> {
     type Item = (f64, Event);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.events.next()
+        match &mut self.source {
+            EventSource::Glfw(events) => events.next(),
+            EventSource::Buffered(events) => events.next(),
+        }
     }
 }
 
+// Opens a real window and runs until it's closed
 #[test]
+#[ignore]
 fn window_test() {
-    let mut window = Window::new();
+    let mut window = Window::new().unwrap();
     while window.is_open() {
         let mut events = window.poll();
         for event in events {
@@ -80,3 +729,56 @@ fn window_test() {
         window.draw();
     }
 }
+
+#[test]
+fn test_image() {
+    let red = ColorRGBA::from_rgba(255, 0, 0, 128);
+    let image = Image::new(2, 1, vec![red, ColorRGBA::default()]).unwrap();
+    let glfw_image = image.to_glfw();
+    assert!(glfw_image.pixels[0].to_ne_bytes() == [255, 0, 0, 128]);
+    assert!(glfw_image.pixels.len() == 2);
+
+    match Image::new(2, 2, vec![red]) {
+        Err(crate::Error::Window(WindowError::InvalidImage(size, 1))) => assert!(size == [2, 2]),
+        _ => panic!("expected an invalid image error"),
+    }
+    assert!(Image::new(u32::MAX, u32::MAX, vec![red]).is_err());
+}
+
+#[test]
+fn test_dpi() {
+    // Retina Mac, sizes in points and a double density framebuffer
+    let mac = Dpi::new([800, 600], [1600, 1200], [2.0, 2.0]);
+    assert!(mac.pixel_ratio() == [2.0, 2.0]);
+    assert!(mac.logical_size() == [800.0, 600.0]);
+    assert!(mac.to_physical([10.0, 20.0].into()) == [20.0, 40.0]);
+    assert!(mac.to_logical([10.0, 20.0].into()) == [10.0, 20.0]);
+
+    // Windows at 150%, sizes already in pixels
+    let win = Dpi::new([1200, 900], [1200, 900], [1.5, 1.5]);
+    assert!(win.logical_size() == [800.0, 600.0]);
+    assert!(win.to_logical([300.0, 150.0].into()) == [200.0, 100.0]);
+    assert!(win.from_logical([200.0, 100.0].into()) == [300.0, 150.0]);
+    assert!(
+        win.convert_event(Event::Size(1200, 900), Coordinates::Logical) == Event::Size(800, 600)
+    );
+    assert!(
+        win.convert_event(Event::CursorPos(3.0, 3.0), Coordinates::Physical)
+            == Event::CursorPos(3.0, 3.0)
+    );
+    assert!(
+        win.convert_event(Event::Scroll(0.0, 1.0), Coordinates::Logical) == Event::Scroll(0.0, 1.0)
+    );
+
+    // A minimized window reports zero sizes
+    let minimized = Dpi::new([0, 0], [0, 0], [1.0, 1.0]);
+    assert!(minimized.pixel_ratio() == [1.0, 1.0]);
+}
+
+#[test]
+fn window_builder_invalid_size() {
+    match WindowBuilder::new().set_size([0, 600]).build() {
+        Err(crate::Error::Window(WindowError::InvalidSize(size))) => assert!(size == [0, 600]),
+        _ => panic!("expected an invalid size error"),
+    }
+}